@@ -3,7 +3,8 @@ use futures::stream::StreamExt;
 use rand::Rng;
 use rmesg::{
     entry::{Entry, LogFacility, LogLevel},
-    klogctl::{klog, KLogEntries},
+    filter::EntryFilter,
+    klogctl::{entries_from_bytes, entries_from_lines, klog, KLogEntries},
     kmsgfile::{kmsg, KMsgEntriesIter, KMsgEntriesStream},
 };
 use std::hint::black_box;
@@ -44,6 +45,7 @@ fn random_entry() -> Entry {
         },
         message: "Some very long string with no purpose. Lorem. Ipsum. Something Something."
             .to_owned(),
+        ..Default::default()
     }
 }
 
@@ -52,6 +54,12 @@ fn display_entry() {
     black_box(displayed);
 }
 
+fn write_entry_to(entry: &Entry, buffer: &mut String) {
+    buffer.clear();
+    entry.write_to(buffer).unwrap();
+    black_box(buffer);
+}
+
 fn entry_to_kmsg_str() {
     let displayed = random_entry().to_kmsg_str().unwrap();
     black_box(displayed);
@@ -76,7 +84,9 @@ fn kmsg_iter_read() {
         true => Some("/dev/kmsg".to_owned()),
         false => None,
     };
-    let entries = KMsgEntriesIter::with_options(file, generate_random_bool()).unwrap();
+    let entries =
+        KMsgEntriesIter::with_options(file, generate_random_bool(), EntryFilter::default())
+            .unwrap();
     let mut count = 0;
     for entry in entries {
         black_box(entry).unwrap();
@@ -92,9 +102,10 @@ async fn kmsg_stream_read() {
         true => Some("/dev/kmsg".to_owned()),
         false => None,
     };
-    let mut entries = KMsgEntriesStream::with_options(file, generate_random_bool())
-        .await
-        .unwrap();
+    let mut entries =
+        KMsgEntriesStream::with_options(file, generate_random_bool(), EntryFilter::default())
+            .await
+            .unwrap();
     let mut count = 0;
     while let Some(entry) = entries.next().await {
         black_box(entry).unwrap();
@@ -106,12 +117,39 @@ async fn kmsg_stream_read() {
 }
 
 fn klog_read() {
-    let entries = klog(false).unwrap();
+    let entries = klog(false, None).unwrap();
     black_box(entries);
 }
 
+// A large klogctl buffer, to compare parsing it whole with parsing it line by line
+fn klog_buffer() -> Vec<u8> {
+    (0..10_000)
+        .map(|n| {
+            format!(
+                "<6>[{: >12}.{:06}] usb 1-1: reset high-speed USB device number {}\n",
+                n, n, n
+            )
+        })
+        .collect::<String>()
+        .into_bytes()
+}
+
+fn klog_parse_lines(buffer: &[u8]) {
+    let all_lines = String::from_utf8(buffer.to_vec()).unwrap();
+    let entries = entries_from_lines(&all_lines).unwrap();
+    black_box(entries);
+}
+
+fn klog_parse_bytes(buffer: &[u8]) {
+    for entry in entries_from_bytes(buffer) {
+        black_box(entry).unwrap();
+    }
+}
+
 fn klog_iter_read() {
-    let entries = KLogEntries::with_options(false, Duration::from_secs(1)).unwrap();
+    let entries =
+        KLogEntries::with_options(false, None, Duration::from_secs(1), EntryFilter::default())
+            .unwrap();
     let mut count = 0;
     for entry in entries {
         black_box(entry).unwrap();
@@ -123,7 +161,9 @@ fn klog_iter_read() {
 }
 
 async fn klog_stream_read() {
-    let mut entries = KLogEntries::with_options(false, Duration::from_secs(1)).unwrap();
+    let mut entries =
+        KLogEntries::with_options(false, None, Duration::from_secs(1), EntryFilter::default())
+            .unwrap();
     let mut count = 0;
     while let Some(entry) = StreamExt::next(&mut entries).await {
         black_box(entry).unwrap();
@@ -146,6 +186,14 @@ pub fn benchmark(c: &mut Criterion) {
             black_box(());
         })
     });
+    let entry = random_entry();
+    let mut buffer = String::new();
+    c.bench_function("write_entry_to", |b| {
+        b.iter(|| {
+            write_entry_to(&entry, &mut buffer);
+            black_box(());
+        })
+    });
     c.bench_function("entry_to_kmsg_str", |b| {
         b.iter(|| {
             entry_to_kmsg_str();
@@ -184,6 +232,19 @@ pub fn benchmark(c: &mut Criterion) {
             black_box(());
         })
     });
+    let buffer = klog_buffer();
+    c.bench_function("klog_parse_lines", |b| {
+        b.iter(|| {
+            klog_parse_lines(&buffer);
+            black_box(());
+        })
+    });
+    c.bench_function("klog_parse_bytes", |b| {
+        b.iter(|| {
+            klog_parse_bytes(&buffer);
+            black_box(());
+        })
+    });
     c.bench_function("klog_iter_read", |b| {
         b.iter(|| {
             klog_iter_read();
@@ -0,0 +1,166 @@
+use crate::entry::Entry;
+use crate::error::RMesgError;
+use core::future::Future;
+use core::pin::Pin;
+use futures::stream::Stream;
+use futures::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Sleep;
+
+/// Wraps a stream of entries, and yields them in batches (`Vec<Entry>`) of up to `size`
+/// entries, e.g. for bulk ingestion APIs that are slow to send one entry at a time to.
+///
+/// A batch is yielded once it's full, or `max_wait` after its first entry came, whichever
+/// comes first, so entries never wait on a quiet stream for long. When the stream ends or
+/// returns an error, the partial batch is yielded first.
+pub struct Batched<S> {
+    inner: S,
+    size: usize,
+    max_wait: Duration,
+
+    // The batch being filled, and when it's due
+    batch: Vec<Entry>,
+    deadline: Option<Pin<Box<Sleep>>>,
+
+    // What the inner stream returned after a batch, to be returned after it
+    queued: Option<Option<Result<Vec<Entry>, RMesgError>>>,
+}
+
+/// Batches a stream of entries. Same as `Batched::new`.
+pub fn batched<S>(inner: S, size: usize, max_wait: Duration) -> Batched<S> {
+    Batched::new(inner, size, max_wait)
+}
+
+impl<S> Batched<S> {
+    /// A `size` of 0 is taken as 1
+    pub fn new(inner: S, size: usize, max_wait: Duration) -> Self {
+        let size = size.max(1);
+        Self {
+            inner,
+            size,
+            max_wait,
+            batch: Vec::with_capacity(size),
+            deadline: None,
+            queued: None,
+        }
+    }
+
+    fn take_batch(&mut self) -> Vec<Entry> {
+        self.deadline = None;
+        std::mem::replace(&mut self.batch, Vec::with_capacity(self.size))
+    }
+
+    // Returns the partial batch (if any) before `next`
+    fn flush_before(
+        &mut self,
+        next: Option<Result<Vec<Entry>, RMesgError>>,
+    ) -> Option<Result<Vec<Entry>, RMesgError>> {
+        match self.batch.is_empty() {
+            true => next,
+            false => {
+                self.queued = Some(next);
+                Some(Ok(self.take_batch()))
+            }
+        }
+    }
+}
+
+impl<S: Stream<Item = Result<Entry, RMesgError>> + Unpin> Stream for Batched<S> {
+    type Item = Result<Vec<Entry>, RMesgError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(queued) = this.queued.take() {
+            return Poll::Ready(queued);
+        }
+
+        loop {
+            match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(entry))) => {
+                    if this.batch.is_empty() {
+                        this.deadline = Some(Box::pin(tokio::time::sleep(this.max_wait)));
+                    }
+                    this.batch.push(entry);
+                    if this.batch.len() >= this.size {
+                        return Poll::Ready(Some(Ok(this.take_batch())));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(this.flush_before(Some(Err(e)))),
+                Poll::Ready(None) => return Poll::Ready(this.flush_before(None)),
+                Poll::Pending => {
+                    return match this.deadline.as_mut().map(|d| d.as_mut().poll(cx)) {
+                        Some(Poll::Ready(())) => Poll::Ready(Some(Ok(this.take_batch()))),
+                        _ => Poll::Pending,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::entry;
+    use futures::stream::{self, StreamExt};
+
+    fn messages(batches: Vec<Result<Vec<Entry>, RMesgError>>) -> Vec<Vec<String>> {
+        batches
+            .into_iter()
+            .map(|batch| batch.unwrap().into_iter().map(|e| e.message).collect())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_batch_by_count() {
+        let inner = stream::iter(["1", "2", "3", "4", "5"].iter().map(|m| Ok(entry(m))));
+        let batches: Vec<_> = batched(inner, 2, Duration::from_secs(60)).collect().await;
+
+        // The partial batch is flushed at the end
+        assert_eq!(
+            messages(batches),
+            vec![vec!["1", "2"], vec!["3", "4"], vec!["5"]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_by_time() {
+        // Two entries, then a third after a while
+        let third = Box::pin(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(entry("3"))
+        });
+        let inner = stream::iter(vec![Ok(entry("1")), Ok(entry("2"))]).chain(stream::once(third));
+        let batches: Vec<_> = batched(inner, 10, Duration::from_millis(20))
+            .collect()
+            .await;
+        assert_eq!(messages(batches), vec![vec!["1", "2"], vec!["3"]]);
+
+        // A quiet stream doesn't hold back the batch it has
+        let inner = stream::iter(vec![Ok(entry("1"))]).chain(stream::pending());
+        let batches: Vec<_> = batched(inner, 10, Duration::from_millis(20))
+            .take(1)
+            .collect()
+            .await;
+        assert_eq!(messages(batches), vec![vec!["1"]]);
+    }
+
+    #[tokio::test]
+    async fn test_errors_flush_the_batch() {
+        let inner = stream::iter(vec![
+            Ok(entry("1")),
+            Err(RMesgError::InternalError("Read failed".to_owned())),
+            Ok(entry("2")),
+        ]);
+        let batches: Vec<_> = batched(inner, 10, Duration::from_secs(60)).collect().await;
+
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0].as_ref().unwrap().len(), 1);
+        assert!(batches[1].is_err());
+        assert_eq!(batches[2].as_ref().unwrap()[0].message, "2");
+    }
+}
@@ -0,0 +1,267 @@
+use crate::entry::Entry;
+use crate::error::RMesgError;
+
+#[cfg(feature = "async")]
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use futures::stream::Stream;
+#[cfg(feature = "async")]
+use futures::task::{Context, Poll};
+
+/// Wraps an iterator or stream of entries, and suppresses runs of consecutive entries
+/// with an identical message, facility and level (like syslog daemons do).
+///
+/// The first entry of a run is passed through. Once the run ends (a different entry
+/// or an error comes along, or the end is reached), a synthetic entry is emitted in
+/// place of the duplicates, with the facility, level, sequence number and timestamp of
+/// the last duplicate:
+/// last message repeated 2 times
+///
+/// When following, the run only ends when a different entry gets logged, so the
+/// synthetic entry may come much later than the duplicates.
+pub struct Coalesce<I> {
+    inner: I,
+
+    // The entry that started the current run
+    last: Option<Entry>,
+    // The last duplicate suppressed, and how many there were
+    last_duplicate: Option<Entry>,
+    repeated: usize,
+
+    // What the inner iterator/stream returned when a run ended, to be returned
+    // after the synthetic entry
+    queued: Option<Option<Result<Entry, RMesgError>>>,
+}
+
+impl<I> Coalesce<I> {
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            last: None,
+            last_duplicate: None,
+            repeated: 0,
+            queued: None,
+        }
+    }
+
+    // Takes the next item from the inner iterator/stream, and returns what to return in
+    // its place, or None when it was a duplicate and the next item needs to be taken.
+    fn coalesce(
+        &mut self,
+        next: Option<Result<Entry, RMesgError>>,
+    ) -> Option<Option<Result<Entry, RMesgError>>> {
+        if let Some(Ok(entry)) = &next {
+            if self.is_duplicate(entry) {
+                self.repeated += 1;
+                self.last_duplicate = next.and_then(Result::ok);
+                return None;
+            }
+            self.last = Some(entry.clone());
+        } else {
+            self.last = None;
+        }
+
+        match self.take_repeated() {
+            Some(repeated) => {
+                self.queued = Some(next);
+                Some(Some(Ok(repeated)))
+            }
+            None => Some(next),
+        }
+    }
+
+    fn is_duplicate(&self, entry: &Entry) -> bool {
+        match &self.last {
+            Some(last) => {
+                last.message == entry.message
+                    && last.facility == entry.facility
+                    && last.level == entry.level
+            }
+            None => false,
+        }
+    }
+
+    fn take_repeated(&mut self) -> Option<Entry> {
+        let last_duplicate = self.last_duplicate.take()?;
+        let repeated = self.repeated;
+        self.repeated = 0;
+
+        Some(Entry {
+            message: format!("last message repeated {} times", repeated),
+            ..last_duplicate
+        })
+    }
+}
+
+impl<I: Iterator<Item = Result<Entry, RMesgError>>> Iterator for Coalesce<I> {
+    type Item = Result<Entry, RMesgError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(queued) = self.queued.take() {
+            return queued;
+        }
+
+        loop {
+            let next = self.inner.next();
+            if let Some(coalesced) = self.coalesce(next) {
+                return coalesced;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<S: Stream<Item = Result<Entry, RMesgError>> + Unpin> Stream for Coalesce<S> {
+    type Item = Result<Entry, RMesgError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(queued) = this.queued.take() {
+            return Poll::Ready(queued);
+        }
+
+        loop {
+            let next = match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(next) => next,
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(coalesced) = this.coalesce(next) {
+                return Poll::Ready(coalesced);
+            }
+        }
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::{LogFacility, LogLevel};
+
+    use std::time::Duration;
+
+    fn entry_at(secs: u64, message: &str) -> Result<Entry, RMesgError> {
+        Ok(Entry {
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Warning),
+            sequence_num: Some(secs as usize),
+            timestamp_from_system_start: Some(Duration::from_secs(secs)),
+            message: message.to_owned(),
+            ..Default::default()
+        })
+    }
+
+    fn messages(entries: Vec<Result<Entry, RMesgError>>) -> Vec<String> {
+        entries.into_iter().map(|e| e.unwrap().message).collect()
+    }
+
+    #[test]
+    fn test_run_followed_by_different_entry() {
+        let entries = vec![
+            entry_at(1, "usb 1-1: reset"),
+            entry_at(2, "usb 1-1: reset"),
+            entry_at(3, "usb 1-1: reset"),
+            entry_at(4, "usb 1-1: connected"),
+        ];
+        let coalesced: Vec<_> = Coalesce::new(entries.into_iter()).collect();
+        assert_eq!(coalesced.len(), 3);
+
+        // The synthetic entry takes the place of the last duplicate
+        assert_eq!(
+            coalesced[1].as_ref().unwrap(),
+            &Entry {
+                message: "last message repeated 2 times".to_owned(),
+                ..entry_at(3, "").unwrap()
+            }
+        );
+        assert_eq!(
+            messages(coalesced),
+            vec![
+                "usb 1-1: reset",
+                "last message repeated 2 times",
+                "usb 1-1: connected"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_at_end() {
+        let entries = vec![
+            entry_at(1, "usb 1-1: connected"),
+            entry_at(2, "usb 1-1: reset"),
+            entry_at(3, "usb 1-1: reset"),
+        ];
+        let coalesced: Vec<_> = Coalesce::new(entries.into_iter()).collect();
+        assert_eq!(
+            messages(coalesced),
+            vec![
+                "usb 1-1: connected",
+                "usb 1-1: reset",
+                "last message repeated 1 times"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_different_level_or_facility() {
+        let mut other_level = entry_at(2, "usb 1-1: reset").unwrap();
+        other_level.level = Some(LogLevel::Error);
+        let mut other_facility = entry_at(3, "usb 1-1: reset").unwrap();
+        other_facility.facility = Some(LogFacility::User);
+
+        let entries = vec![
+            entry_at(1, "usb 1-1: reset"),
+            Ok(other_level),
+            Ok(other_facility),
+        ];
+        let coalesced: Vec<_> = Coalesce::new(entries.into_iter()).collect();
+        assert_eq!(
+            messages(coalesced),
+            vec!["usb 1-1: reset", "usb 1-1: reset", "usb 1-1: reset"]
+        );
+    }
+
+    #[test]
+    fn test_error_ends_run() {
+        let entries = vec![
+            entry_at(1, "usb 1-1: reset"),
+            entry_at(2, "usb 1-1: reset"),
+            Err(RMesgError::InternalError("Parsing failed".to_owned())),
+            entry_at(3, "usb 1-1: reset"),
+        ];
+        let coalesced: Vec<_> = Coalesce::new(entries.into_iter()).collect();
+        assert_eq!(coalesced.len(), 4);
+        assert_eq!(
+            coalesced[1].as_ref().unwrap().message,
+            "last message repeated 1 times"
+        );
+        assert!(coalesced[2].is_err());
+        assert_eq!(coalesced[3].as_ref().unwrap().message, "usb 1-1: reset");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_stream() {
+        use futures::stream::{self, StreamExt};
+
+        let entries = vec![
+            entry_at(1, "usb 1-1: reset"),
+            entry_at(2, "usb 1-1: reset"),
+            entry_at(3, "usb 1-1: reset"),
+            entry_at(4, "usb 1-1: connected"),
+            entry_at(5, "usb 1-1: connected"),
+        ];
+        let coalesced: Vec<_> = Coalesce::new(stream::iter(entries)).collect().await;
+        assert_eq!(
+            messages(coalesced),
+            vec![
+                "usb 1-1: reset",
+                "last message repeated 2 times",
+                "usb 1-1: connected",
+                "last message repeated 1 times"
+            ]
+        );
+    }
+}
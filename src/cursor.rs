@@ -0,0 +1,94 @@
+use crate::entry::Entry;
+
+#[cfg(feature = "extra-traits")]
+use serde::{Deserialize, Serialize};
+
+/// How far a reader of /dev/kmsg got: the sequence number of the last record it read.
+///
+/// Save it (it serializes with the "extra-traits" feature) and pass it to
+/// `KMsgEntriesStream::resume_from` (or `KMsgEntriesIter::resume_from`) after a restart,
+/// to only read the records logged since.
+///
+/// Sequence numbers start over when the system boots, so a cursor is only meaningful
+/// during the boot it was saved in.
+#[cfg_attr(feature = "extra-traits", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cursor {
+    last_sequence_num: Option<usize>,
+}
+
+impl Cursor {
+    /// A cursor before any record
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cursor past the record with this sequence number
+    pub fn after(sequence_num: usize) -> Self {
+        Self {
+            last_sequence_num: Some(sequence_num),
+        }
+    }
+
+    /// The sequence number of the last record read, if any was
+    pub fn last_sequence_num(&self) -> Option<usize> {
+        self.last_sequence_num
+    }
+
+    /// Returns true if the entry comes after the cursor. Entries without a sequence
+    /// number (like raw entries) can't be placed, so they always do.
+    pub fn is_before(&self, entry: &Entry) -> bool {
+        match (self.last_sequence_num, entry.sequence_num) {
+            (Some(last), Some(sequence_num)) => sequence_num > last,
+            _ => true,
+        }
+    }
+
+    /// Moves the cursor past the entry, unless it's already further along
+    pub fn advance(&mut self, entry: &Entry) {
+        if let Some(sequence_num) = entry.sequence_num {
+            if self
+                .last_sequence_num
+                .is_none_or(|last| sequence_num > last)
+            {
+                self.last_sequence_num = Some(sequence_num);
+            }
+        }
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util;
+
+    fn entry(sequence_num: Option<usize>) -> Entry {
+        Entry {
+            sequence_num,
+            ..test_util::entry(&format!("{:?}", sequence_num))
+        }
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut cursor = Cursor::new();
+        assert_eq!(cursor.last_sequence_num(), None);
+        assert!(cursor.is_before(&entry(Some(0))));
+
+        cursor.advance(&entry(Some(11)));
+        cursor.advance(&entry(None));
+        assert_eq!(cursor, Cursor::after(11));
+
+        // Never moves back
+        cursor.advance(&entry(Some(10)));
+        assert_eq!(cursor.last_sequence_num(), Some(11));
+
+        assert!(!cursor.is_before(&entry(Some(10))));
+        assert!(!cursor.is_before(&entry(Some(11))));
+        assert!(cursor.is_before(&entry(Some(12))));
+        assert!(cursor.is_before(&entry(None)));
+    }
+}
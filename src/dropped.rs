@@ -0,0 +1,177 @@
+use crate::entry::Entry;
+use crate::error::RMesgError;
+
+#[cfg(feature = "async")]
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use futures::stream::Stream;
+#[cfg(feature = "async")]
+use futures::task::{Context, Poll};
+
+/// What `DetectDroppedRecords` yields: the entries read, and notifications of records
+/// lost between them
+#[derive(Clone, Debug, PartialEq)]
+pub enum Record {
+    Entry(Entry),
+
+    /// This many records were lost before the next entry, e.g. because the kernel
+    /// overwrote them in the ring buffer before they were read
+    DroppedRecords(usize),
+}
+
+/// Wraps an iterator or stream of entries, and compares the sequence numbers of
+/// consecutive entries to detect records that were lost. When the sequence number jumps
+/// by more than one, a `Record::DroppedRecords` is yielded before the entry after the gap.
+///
+/// Only entries read from /dev/kmsg carry sequence numbers; entries without one pass
+/// through without being checked. Entries skipped by a filter leave gaps in sequence
+/// numbers too, so wrap entries read without a filter to only detect lost records.
+pub struct DetectDroppedRecords<I> {
+    inner: I,
+    last_sequence_num: Option<usize>,
+
+    // The entry after a gap, to be yielded after the notification
+    queued: Option<Entry>,
+}
+
+impl<I> DetectDroppedRecords<I> {
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            last_sequence_num: None,
+            queued: None,
+        }
+    }
+
+    fn detect(
+        &mut self,
+        next: Option<Result<Entry, RMesgError>>,
+    ) -> Option<Result<Record, RMesgError>> {
+        let entry = match next? {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let sequence_num = match entry.sequence_num {
+            Some(sequence_num) => sequence_num,
+            None => return Some(Ok(Record::Entry(entry))),
+        };
+
+        // A sequence number going backwards isn't a gap (the buffer may have been reset),
+        // so it only becomes what the next entry is compared against.
+        let dropped = match self.last_sequence_num.replace(sequence_num) {
+            Some(last) if sequence_num > last + 1 => sequence_num - last - 1,
+            _ => 0,
+        };
+
+        if dropped > 0 {
+            self.queued = Some(entry);
+            Some(Ok(Record::DroppedRecords(dropped)))
+        } else {
+            Some(Ok(Record::Entry(entry)))
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Entry, RMesgError>>> Iterator for DetectDroppedRecords<I> {
+    type Item = Result<Record, RMesgError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(queued) = self.queued.take() {
+            return Some(Ok(Record::Entry(queued)));
+        }
+
+        let next = self.inner.next();
+        self.detect(next)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<S: Stream<Item = Result<Entry, RMesgError>> + Unpin> Stream for DetectDroppedRecords<S> {
+    type Item = Result<Record, RMesgError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(queued) = this.queued.take() {
+            return Poll::Ready(Some(Ok(Record::Entry(queued))));
+        }
+
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(next) => Poll::Ready(this.detect(next)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util;
+
+    fn entry(sequence_num: Option<usize>) -> Entry {
+        Entry {
+            sequence_num,
+            ..test_util::entry("Test message")
+        }
+    }
+
+    fn entries(sequence_nums: &[usize]) -> Vec<Result<Entry, RMesgError>> {
+        sequence_nums.iter().map(|n| Ok(entry(Some(*n)))).collect()
+    }
+
+    fn detect_all(entries: Vec<Result<Entry, RMesgError>>) -> Vec<Record> {
+        DetectDroppedRecords::new(entries.into_iter())
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn test_gap() {
+        assert_eq!(
+            detect_all(entries(&[1, 2, 5, 6])),
+            vec![
+                Record::Entry(entry(Some(1))),
+                Record::Entry(entry(Some(2))),
+                Record::DroppedRecords(2),
+                Record::Entry(entry(Some(5))),
+                Record::Entry(entry(Some(6))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_gap() {
+        // Starting past zero, or going backwards, isn't a gap
+        let records = detect_all(entries(&[7, 8, 9, 0, 1]));
+        assert!(records
+            .iter()
+            .all(|r| !matches!(r, Record::DroppedRecords(_))));
+        assert_eq!(records.len(), 5);
+    }
+
+    #[test]
+    fn test_without_sequence_nums() {
+        let records = detect_all(vec![
+            Ok(entry(Some(1))),
+            Ok(entry(None)),
+            Ok(entry(Some(3))),
+        ]);
+        assert_eq!(records[1], Record::Entry(entry(None)));
+        assert_eq!(records[2], Record::DroppedRecords(1));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_stream() {
+        use futures::stream::{self, StreamExt};
+
+        let records: Vec<_> = DetectDroppedRecords::new(stream::iter(entries(&[1, 2, 5, 6])))
+            .collect()
+            .await;
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[2].as_ref().unwrap(), &Record::DroppedRecords(2));
+    }
+}
@@ -1,16 +1,26 @@
 // Copyright (c) 2019 Polyverse Corporation
 
+use crate::error::RMesgError;
+use crate::formatter::EntryFormatter;
+use crate::parse::{self, KMsgRecord, ParseError};
+use crate::{klogctl, kmsgfile};
+use num::FromPrimitive;
 use num_derive::FromPrimitive;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Display, Error as FmtError, Formatter, Result as FmtResult, Write};
-use std::time::Duration;
-use strum_macros::{Display, EnumString};
+use std::ops::BitOr;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use strum_macros::Display;
 
 #[cfg(feature = "extra-traits")]
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 /// A parsed/structured entry from kernel log buffer
-#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "extra-traits", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Debug, Clone, Default)]
 pub struct Entry {
     // Log facility
     pub facility: Option<LogFacility>,
@@ -22,16 +32,154 @@ pub struct Entry {
     pub sequence_num: Option<usize>,
 
     // The amount of time since system bootstrapped
+    #[cfg_attr(feature = "extra-traits", serde(with = "timestamp_secs"))]
     pub timestamp_from_system_start: Option<Duration>,
 
     // Log message
     pub message: String,
+
+    // Key/value pairs from the record's continuation lines in /dev/kmsg, such as
+    // SUBSYSTEM and DEVICE. Empty for records without any, and for other backends.
+    #[cfg_attr(
+        feature = "extra-traits",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    pub metadata: HashMap<String, String>,
+
+    // The line(s) the entry was parsed from, only when asked for (e.g. with
+    // `RmesgOptions::raw_lines`) since it's a copy of every line read
+    #[cfg_attr(
+        feature = "extra-traits",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub raw_line: Option<String>,
+
+    // The flags of the record in /dev/kmsg, such as whether it's a fragment of a line printed
+    // in pieces. Empty for whole records, and for other backends.
+    #[cfg_attr(
+        feature = "extra-traits",
+        serde(default, skip_serializing_if = "EntryFlags::is_empty")
+    )]
+    pub flags: EntryFlags,
 }
 
+/// The flags field of a /dev/kmsg record (after the timestamp): `-` for none, or `c` for a
+/// fragment of a line printed in pieces (e.g. with `pr_cont`), which consumers may want to
+/// join with the records that continue it. Older kernels mark the fragments after the first
+/// with `+`.
+#[cfg_attr(feature = "extra-traits", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct EntryFlags(u8);
+
+impl EntryFlags {
+    /// A fragment of a line printed in pieces (`c`): the first one, on older kernels
+    pub const FRAGMENT: Self = Self(1);
+
+    /// A fragment continuing the line of an earlier one (`+`, only on older kernels)
+    pub const CONTINUATION: Self = Self(1 << 1);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether all of `other`'s flags are set
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Parses the flags field of a /dev/kmsg record. Flags rmesg doesn't know of are ignored.
+    pub fn from_kmsg_field(field: &str) -> Self {
+        field.chars().fold(Self::empty(), |flags, c| match c {
+            'c' => flags | Self::FRAGMENT,
+            '+' => flags | Self::CONTINUATION,
+            _ => flags,
+        })
+    }
+
+    /// The flags field of a /dev/kmsg record with these flags
+    pub fn to_kmsg_field(&self) -> &'static str {
+        if self.contains(Self::CONTINUATION) {
+            "+"
+        } else if self.contains(Self::FRAGMENT) {
+            "c"
+        } else {
+            "-"
+        }
+    }
+}
+
+impl BitOr for EntryFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+// The metadata keys the kernel logs, in the order it logs them in
+const KERNEL_METADATA_KEYS: [&str; 2] = ["SUBSYSTEM", "DEVICE"];
+
+// Marks messages cut short by `Entry::truncate_message`
+const TRUNCATION_MARKER: &str = "…";
+
 impl Entry {
+    /// Truncates the message to at most `max_len` bytes (backing up to the start of a
+    /// character cut in the middle), and marks it with a trailing `…`, which isn't counted
+    /// in `max_len`. Messages that fit are left as is.
+    pub fn truncate_message(&mut self, max_len: usize) {
+        if let Cow::Owned(truncated) = truncate(&self.message, max_len) {
+            self.message = truncated;
+        }
+    }
+
+    /// Escapes the non-printable characters of the message (see `escape_non_printable`), so
+    /// that printing it can't mess up a terminal. To truncate the message too, truncate it
+    /// first, like `ParseOptions` does: truncating it once escaped can cut an escape short.
+    pub fn escape_message(&mut self) {
+        if let Cow::Owned(escaped) = escape_non_printable(&self.message) {
+            self.message = escaped;
+        }
+    }
+
+    /// Converts the time since system start into an absolute (wall-clock) time,
+    /// given the time at which the system booted (see `rmesg::system_boot_time`).
+    ///
+    /// Returns None when the entry has no timestamp, or the resulting time
+    /// can't be represented.
+    pub fn wall_clock_time(&self, boot_time: SystemTime) -> Option<SystemTime> {
+        self.timestamp_from_system_start
+            .and_then(|ts| boot_time.checked_add(ts))
+    }
+
+    /// Writes the entry like `Display` does into any writer, so one buffer can be reused
+    /// across many entries instead of allocating a String for each.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> FmtResult {
+        EntryFormatter::default().write_entry(w, self)
+    }
+
+    /// Parses a record the way /dev/kmsg has it, with the metadata on its continuation lines
+    /// (see `kmsgfile::parse_record`). The inverse of `to_kmsg_str`.
+    pub fn from_kmsg_line(line: &str) -> Result<Entry, RMesgError> {
+        kmsgfile::parse_record(line)
+    }
+
+    /// Parses a line the way klogctl reads it (see `klogctl::entry_from_line`). The inverse of
+    /// `to_klog_str`, except for the sequence number, which klogctl lines don't have.
+    pub fn from_klog_line(line: &str) -> Result<Entry, RMesgError> {
+        Ok(klogctl::entry_from_line(line)?)
+    }
+
     pub fn to_faclev(&self) -> Option<u8> {
         match (self.facility, self.level) {
-            (Some(facility), Some(level)) => Some(((facility as u8) << 3) + (level as u8)),
+            (Some(facility), Some(level)) => Some(encode_priority(facility, level)),
             _ => None,
         }
     }
@@ -63,42 +211,110 @@ impl Entry {
     // 6,1,0,-;Command, line: BOOT_IMAGE=/boot/kernel console=ttyS0 console=ttyS1 page_poison=1 vsyscall=emulate panic=1 root=/dev/sr0 text
     //  LINE2=foobar
     //  LINE 3 = foobar ; with semicolon
+    /// Renders the entry the way /dev/kmsg has it: the header and message, then the
+    /// metadata as ` KEY=value` continuation lines (without a trailing line break).
+    ///
+    /// For records read from /dev/kmsg, this is byte for byte the record read back (see
+    /// `kmsgfile::parse_record`), as long as it had no fields past the timestamp (such as the
+    /// caller id of kernels built with CONFIG_PRINTK_CALLER), which entries don't keep. The
+    /// metadata keys the kernel logs (SUBSYSTEM, then DEVICE) come in its order, and any others
+    /// after them, sorted.
     pub fn to_kmsg_str(&self) -> Result<String, FmtError> {
         if let Some(faclev) = self.to_faclev() {
             // +7 for buffer + capacity is 12 (for timestamp) + 5 (for punctuations) + 1 for facllev + message
             let mut retstr = String::with_capacity(25 + self.message.len());
 
-            let sequence_num = self.sequence_num.unwrap_or(0);
-            write!(retstr, "{},{},", faclev, sequence_num)?;
+            let record = KMsgRecord {
+                priority: faclev.into(),
+                sequence_num: self.sequence_num.unwrap_or(0),
+                timestamp_from_system_start: self.timestamp_from_system_start.unwrap_or_default(),
+                flags: self.flags.to_kmsg_field(),
+                message: &self.message,
+            };
+            write!(retstr, "{}", record)?;
 
-            if let Some(ts) = self.timestamp_from_system_start {
-                write!(retstr, "{},-;", ts.as_micros())?;
-            } else {
-                retstr.push_str("0,-;");
+            let mut metadata: Vec<_> = self.metadata.iter().collect();
+            metadata.sort_by_key(|(key, _)| {
+                let kernel_order = KERNEL_METADATA_KEYS.iter().position(|k| k == key);
+                (kernel_order.unwrap_or(KERNEL_METADATA_KEYS.len()), *key)
+            });
+            for (key, value) in metadata {
+                write!(retstr, "\n {}={}", key, value)?;
             }
 
-            write!(retstr, "{}", self.message)?;
-
             Ok(retstr)
         } else {
             Ok(self.message.to_string())
         }
     }
-}
 
-impl Display for Entry {
-    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    // Like so (logfmt, e.g. for Grafana Loki), leaving out fields the entry doesn't have:
+    // ts=24241.325252 facility=kern level=info seq=42 msg="usb 1-1: \"reset\" done"
+    pub fn to_logfmt(&self) -> String {
+        let mut retstr = String::with_capacity(64 + self.message.len());
+
+        // Writing into a String can't fail
         if let Some(ts) = self.timestamp_from_system_start {
-            write!(f, "[{: >16.6}] ", ts.as_secs_f64())?
+            // Down to microseconds like the kernel, without the noise of printing an f64
+            let secs = format!("{}.{:06}", ts.as_secs(), ts.subsec_micros());
+            let _ = write!(
+                retstr,
+                "ts={} ",
+                secs.trim_end_matches('0').trim_end_matches('.')
+            );
+        }
+        if let Some(facility) = self.facility {
+            let _ = write!(retstr, "facility={} ", facility);
+        }
+        if let Some(level) = self.level {
+            let _ = write!(retstr, "level={} ", level);
+        }
+        if let Some(sequence_num) = self.sequence_num {
+            let _ = write!(retstr, "seq={} ", sequence_num);
+        }
+
+        retstr.push_str("msg=\"");
+        for c in self.message.chars() {
+            match c {
+                '"' => retstr.push_str("\\\""),
+                '\\' => retstr.push_str("\\\\"),
+                '\n' => retstr.push_str("\\n"),
+                '\r' => retstr.push_str("\\r"),
+                '\t' => retstr.push_str("\\t"),
+                c => retstr.push(c),
+            }
         }
+        retstr.push('"');
+
+        retstr
+    }
 
-        write!(f, "{}", self.message)
+    // Like so (RFC 5424, e.g. for forwarding to a remote syslog server):
+    // <6>1 - - kernel - - - usb 1-1: new high-speed USB device number 2
+    //
+    // The kernel only knows the time since boot, so the timestamp and hostname are left
+    // nil for the relay or collector to fill in. Entries without a facility or level are
+    // kern and notice.
+    pub fn to_syslog_rfc5424(&self) -> String {
+        let priority = encode_priority(
+            self.facility.unwrap_or(LogFacility::Kern),
+            self.level.unwrap_or(LogLevel::Notice),
+        );
+
+        format!("<{}>1 - - kernel - - - {}", priority, self.message)
+    }
+}
+
+/// Formats with the default `EntryFormatter`: the timestamp in seconds since
+/// system start (when present), followed by the message.
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.write_to(f)
     }
 }
 
 /// Linux kmesg (kernel message buffer) Log Facility.
-#[cfg_attr(feature = "extra-traits", derive(Serialize, Deserialize))]
-#[derive(EnumString, Debug, PartialEq, Display, Copy, Clone, FromPrimitive)]
+#[derive(Debug, PartialEq, Eq, Hash, Display, Copy, Clone, FromPrimitive)]
 pub enum LogFacility {
     #[strum(serialize = "kern")]
     Kern = 0,
@@ -135,11 +351,52 @@ pub enum LogFacility {
 
     #[strum(serialize = "ftp")]
     FTP,
+
+    #[strum(serialize = "ntp")]
+    NTP,
+
+    #[strum(serialize = "audit")]
+    Audit,
+
+    #[strum(serialize = "console")]
+    Console,
+
+    #[strum(serialize = "clock")]
+    Clock,
+
+    #[strum(serialize = "local0")]
+    Local0,
+
+    #[strum(serialize = "local1")]
+    Local1,
+
+    #[strum(serialize = "local2")]
+    Local2,
+
+    #[strum(serialize = "local3")]
+    Local3,
+
+    #[strum(serialize = "local4")]
+    Local4,
+
+    #[strum(serialize = "local5")]
+    Local5,
+
+    #[strum(serialize = "local6")]
+    Local6,
+
+    #[strum(serialize = "local7")]
+    Local7,
 }
 
 /// Linux kmesg (kernel message buffer) Log Level.
-#[cfg_attr(feature = "extra-traits", derive(Serialize, Deserialize))]
-#[derive(EnumString, Debug, PartialEq, Display, Copy, Clone, FromPrimitive)]
+///
+/// Levels order by their syslog severity number (see `as_severity`), so more severe levels
+/// compare as less: `Emergency < Alert < ... < Debug`. Emergency is the most severe.
+///
+/// So a level at least as severe as a threshold is less than or equal to it, e.g.
+/// `level <= LogLevel::Warning` holds for warnings and anything worse.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Display, Copy, Clone, FromPrimitive)]
 pub enum LogLevel {
     #[strum(serialize = "emerg")]
     Emergency = 0,
@@ -166,6 +423,315 @@ pub enum LogLevel {
     Debug,
 }
 
+const ALL_FACILITIES: [LogFacility; 24] = [
+    LogFacility::Kern,
+    LogFacility::User,
+    LogFacility::Mail,
+    LogFacility::Daemon,
+    LogFacility::Auth,
+    LogFacility::Syslog,
+    LogFacility::Lpr,
+    LogFacility::News,
+    LogFacility::UUCP,
+    LogFacility::Cron,
+    LogFacility::AuthPriv,
+    LogFacility::FTP,
+    LogFacility::NTP,
+    LogFacility::Audit,
+    LogFacility::Console,
+    LogFacility::Clock,
+    LogFacility::Local0,
+    LogFacility::Local1,
+    LogFacility::Local2,
+    LogFacility::Local3,
+    LogFacility::Local4,
+    LogFacility::Local5,
+    LogFacility::Local6,
+    LogFacility::Local7,
+];
+
+const ALL_LEVELS: [LogLevel; 8] = [
+    LogLevel::Emergency,
+    LogLevel::Alert,
+    LogLevel::Critical,
+    LogLevel::Error,
+    LogLevel::Warning,
+    LogLevel::Notice,
+    LogLevel::Info,
+    LogLevel::Debug,
+];
+
+impl LogFacility {
+    /// Every facility, in the order of their codes (kern first)
+    pub fn all() -> &'static [LogFacility] {
+        &ALL_FACILITIES
+    }
+
+    /// The numeric syslog facility code (0 for kern through 23 for local7), as in RFC 5424
+    pub fn as_code(&self) -> u8 {
+        *self as u8
+    }
+
+    /// The facility with this numeric syslog code, if it's one (0 through 23)
+    pub fn from_code(code: u8) -> Option<LogFacility> {
+        LogFacility::from_u8(code)
+    }
+}
+
+impl LogLevel {
+    /// Every level, most severe first (in the order of their severity numbers)
+    pub fn all() -> &'static [LogLevel] {
+        &ALL_LEVELS
+    }
+
+    /// The numeric syslog severity (0 for Emergency through 7 for Debug), as in RFC 5424
+    pub fn as_severity(&self) -> u8 {
+        *self as u8
+    }
+
+    /// The level with this numeric syslog severity, if it's one (0 through 7)
+    pub fn from_severity(severity: u8) -> Option<LogLevel> {
+        LogLevel::from_u8(severity)
+    }
+}
+
+// Truncates like `Entry::truncate_message` does, borrowing strings that fit as they are
+fn truncate(s: &str, max_len: usize) -> Cow<'_, str> {
+    if s.len() <= max_len {
+        return Cow::Borrowed(s);
+    }
+
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut truncated = String::with_capacity(end + TRUNCATION_MARKER.len());
+    truncated.push_str(&s[..end]);
+    truncated.push_str(TRUNCATION_MARKER);
+    Cow::Owned(truncated)
+}
+
+/// Escapes control characters (like ESC, which starts terminal escape sequences, or a
+/// carriage return) as `\xNN`, one for each byte of the character, like dmesg does. Tabs and
+/// line breaks are left as they are. Strings without any to escape are borrowed as they are.
+pub fn escape_non_printable(s: &str) -> Cow<'_, str> {
+    let is_escaped = |c: char| c.is_control() && c != '\t' && c != '\n';
+    if !s.contains(is_escaped) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut escaped = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match is_escaped(c) {
+            true => {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    // Writing into a String can't fail
+                    let _ = write!(escaped, "\\x{:02x}", byte);
+                }
+            }
+            false => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Splits a syslog priority (`facility * 8 + level`, like the `<6>` lines start with) into
+/// its facility and level. The facility is None for priorities past the last facility (191).
+pub fn decode_priority(prio: u8) -> (Option<LogFacility>, Option<LogLevel>) {
+    let (facility, level) = parse::split_priority(prio.into());
+    (LogFacility::from_u32(facility), LogLevel::from_u32(level))
+}
+
+/// Combines a facility and level into a syslog priority (`facility * 8 + level`)
+pub fn encode_priority(facility: LogFacility, level: LogLevel) -> u8 {
+    parse::join_priority(facility.as_code(), level.as_severity())
+}
+
+/// Parses the syslog facility names case-insensitively (i.e. "kern" or "LOCAL0"),
+/// and the numeric facilities "0" through "23".
+impl FromStr for LogFacility {
+    type Err = RMesgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(num) = s.parse::<u32>() {
+            return LogFacility::from_u32(num)
+                .ok_or_else(|| RMesgError::UnknownLogFacility(s.to_owned()));
+        }
+
+        match s.to_lowercase().as_str() {
+            "kern" => Ok(LogFacility::Kern),
+            "user" => Ok(LogFacility::User),
+            "mail" => Ok(LogFacility::Mail),
+            "daemon" => Ok(LogFacility::Daemon),
+            "auth" => Ok(LogFacility::Auth),
+            "syslog" => Ok(LogFacility::Syslog),
+            "lpr" => Ok(LogFacility::Lpr),
+            "news" => Ok(LogFacility::News),
+            "uucp" => Ok(LogFacility::UUCP),
+            "cron" => Ok(LogFacility::Cron),
+            "authpriv" => Ok(LogFacility::AuthPriv),
+            "ftp" => Ok(LogFacility::FTP),
+            "ntp" => Ok(LogFacility::NTP),
+            "audit" => Ok(LogFacility::Audit),
+            "console" => Ok(LogFacility::Console),
+            "clock" => Ok(LogFacility::Clock),
+            "local0" => Ok(LogFacility::Local0),
+            "local1" => Ok(LogFacility::Local1),
+            "local2" => Ok(LogFacility::Local2),
+            "local3" => Ok(LogFacility::Local3),
+            "local4" => Ok(LogFacility::Local4),
+            "local5" => Ok(LogFacility::Local5),
+            "local6" => Ok(LogFacility::Local6),
+            "local7" => Ok(LogFacility::Local7),
+            _ => Err(RMesgError::UnknownLogFacility(s.to_owned())),
+        }
+    }
+}
+
+/// Parses the syslog level names case-insensitively, along with their common aliases
+/// (i.e. "warn" or "warning"), and the numeric levels "0" through "7".
+impl FromStr for LogLevel {
+    type Err = RMesgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(num) = s.parse::<u32>() {
+            return LogLevel::from_u32(num)
+                .ok_or_else(|| RMesgError::UnknownLogLevel(s.to_owned()));
+        }
+
+        match s.to_lowercase().as_str() {
+            "emerg" | "emergency" | "panic" => Ok(LogLevel::Emergency),
+            "alert" => Ok(LogLevel::Alert),
+            "crit" | "critical" => Ok(LogLevel::Critical),
+            "err" | "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warning),
+            "notice" => Ok(LogLevel::Notice),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(RMesgError::UnknownLogLevel(s.to_owned())),
+        }
+    }
+}
+
+// Facility and level serialize as the same lowercase names they Display as
+// (i.e. "kern", "info"), so there's one source of truth for the names.
+#[cfg(feature = "extra-traits")]
+impl Serialize for LogFacility {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "extra-traits")]
+impl<'de> Deserialize<'de> for LogFacility {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "extra-traits")]
+impl Serialize for LogLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "extra-traits")]
+impl<'de> Deserialize<'de> for LogLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// (De)serializes the timestamp as a floating point number of seconds (or null)
+#[cfg(feature = "extra-traits")]
+mod timestamp_secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(
+        ts: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match ts {
+            Some(d) => serializer.serialize_some(&d.as_secs_f64()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<f64>::deserialize(deserializer)?.map(Duration::from_secs_f64))
+    }
+}
+
+/// How iterators and streams reading the kernel log buffer handle records they can't parse
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Return them as errors (`RMesgError::KMsgParseError` from /dev/kmsg, and
+    /// `RMesgError::EntryParsingError` from klogctl)
+    Strict,
+
+    /// Skip them, so one bad record doesn't end a follow session
+    #[default]
+    Lenient,
+
+    /// Make entries of them with what can be parsed: the message, along with whichever of
+    /// the other fields can be made out
+    Lossy,
+}
+
+/// How readers make entries of the lines they read. The readers (`klogctl::KLogEntries`,
+/// `kmsgfile::KMsgEntriesIter`, `kmsgfile::KMsgEntriesStream` and `file::FileEntries`) take
+/// them whole with `with_parse_options`, or one at a time with their other setters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// How lines that can't be parsed are handled. Skipped by default (`ParseMode::Lenient`).
+    /// Files are always parsed strictly.
+    pub parse_mode: ParseMode,
+
+    /// When set, entries carry the line(s) they were parsed from in `raw_line`
+    pub raw_lines: bool,
+
+    /// When `Some`, messages longer than this many bytes are truncated to it, with a trailing
+    /// `…` (see `Entry::truncate_message`). They're truncated as they're parsed, before being
+    /// copied out of the line, so filters see them truncated. Raw lines are truncated too.
+    pub max_message_len: Option<usize>,
+
+    /// When set, non-printable characters in messages are escaped as `\xNN` (see
+    /// `Entry::escape_message`). Messages are escaped after being truncated, so escapes aren't
+    /// cut short, which can make them longer than `max_message_len`.
+    pub escape_messages: bool,
+}
+
+impl ParseOptions {
+    // Copies a message out of the line it was parsed from, truncated and escaped as set
+    pub(crate) fn message(&self, message: &str) -> String {
+        let message = match self.max_message_len {
+            Some(max_len) => truncate(message, max_len),
+            None => Cow::Borrowed(message),
+        };
+        match escape_non_printable(&message) {
+            Cow::Owned(escaped) if self.escape_messages => escaped,
+            _ => message.into_owned(),
+        }
+    }
+
+    // The line(s) an entry was parsed from, when they're kept, truncated like the message
+    pub(crate) fn raw_line(&self, line: &str) -> Option<String> {
+        self.raw_lines.then(|| match self.max_message_len {
+            Some(max_len) => truncate(line, max_len).into_owned(),
+            None => line.to_owned(),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub enum EntryParsingError {
     Completed,
@@ -173,6 +739,11 @@ pub enum EntryParsingError {
     Generic(String),
 }
 impl Error for EntryParsingError {}
+impl From<ParseError> for EntryParsingError {
+    fn from(e: ParseError) -> Self {
+        Self::Generic(e.0)
+    }
+}
 impl Display for EntryParsingError {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         write!(
@@ -191,6 +762,8 @@ impl Display for EntryParsingError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::formatter::TimeFormat;
+    use std::collections::HashSet;
 
     #[test]
     fn test_serialize_to_klog() {
@@ -200,6 +773,7 @@ mod tests {
             level: Some(LogLevel::Info),
             sequence_num: Some(10),
             message: "Test message".to_owned(),
+            ..Default::default()
         };
         let expected_serialization = "<6>[    24241.325252]Test message";
 
@@ -220,6 +794,7 @@ mod tests {
             level: Some(LogLevel::Info),
             sequence_num: Some(23),
             message: "Test message".to_owned(),
+            ..Default::default()
         };
         let expected_serialization = "6,23,24241325252,-;Test message";
 
@@ -232,6 +807,125 @@ mod tests {
         assert_eq!(printed_boxed_entry_struct, expected_serialization);
     }
 
+    #[test]
+    fn test_from_klog_line() {
+        let entry = Entry {
+            timestamp_from_system_start: Some(Duration::from_micros(24241325252)),
+            facility: Some(LogFacility::Daemon),
+            level: Some(LogLevel::Warning),
+            message: "Test message".to_owned(),
+            ..Default::default()
+        };
+        let line = entry.to_klog_str().unwrap();
+        assert_eq!(Entry::from_klog_line(&line).unwrap(), entry);
+
+        let parsed =
+            Entry::from_klog_line("<3>usb 1-1: device descriptor read/64, error -71").unwrap();
+        assert_eq!(parsed.level, Some(LogLevel::Error));
+        assert_eq!(parsed.timestamp_from_system_start, None);
+        assert_eq!(
+            parsed.to_klog_str().unwrap(),
+            "<3>usb 1-1: device descriptor read/64, error -71"
+        );
+    }
+
+    #[test]
+    fn test_from_kmsg_line() {
+        let mut entry = Entry {
+            timestamp_from_system_start: Some(Duration::from_micros(5291300)),
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Info),
+            sequence_num: Some(342),
+            message: "usb 1-1: new high-speed USB device number 2 using ehci-pci".to_owned(),
+            ..Default::default()
+        };
+        let line = entry.to_kmsg_str().unwrap();
+        assert_eq!(Entry::from_kmsg_line(&line).unwrap(), entry);
+
+        entry
+            .metadata
+            .insert("SUBSYSTEM".to_owned(), "usb".to_owned());
+        entry
+            .metadata
+            .insert("DEVICE".to_owned(), "c189:1".to_owned());
+        let line = entry.to_kmsg_str().unwrap();
+        assert_eq!(Entry::from_kmsg_line(&line).unwrap(), entry);
+
+        assert!(matches!(
+            Entry::from_kmsg_line("6,99999999999999999999999,0,-;overflow"),
+            Err(RMesgError::KMsgParseError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_serialize_to_logfmt() {
+        let mut entry_struct = Entry {
+            timestamp_from_system_start: Some(Duration::from_secs_f64(12.34)),
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Info),
+            sequence_num: Some(42),
+            message: "Test message".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            entry_struct.to_logfmt(),
+            r#"ts=12.34 facility=kern level=info seq=42 msg="Test message""#
+        );
+
+        entry_struct.message = r#"usb 1-1: "reset" at C:\path"#.to_owned();
+        assert_eq!(
+            entry_struct.to_logfmt(),
+            r#"ts=12.34 facility=kern level=info seq=42 msg="usb 1-1: \"reset\" at C:\\path""#
+        );
+
+        entry_struct.message = "line one\n\tline two".to_owned();
+        assert_eq!(
+            entry_struct.to_logfmt(),
+            r#"ts=12.34 facility=kern level=info seq=42 msg="line one\n\tline two""#
+        );
+
+        entry_struct.timestamp_from_system_start = Some(Duration::from_micros(1_286_258));
+        entry_struct.message = "Test message".to_owned();
+        assert_eq!(
+            entry_struct.to_logfmt(),
+            r#"ts=1.286258 facility=kern level=info seq=42 msg="Test message""#
+        );
+
+        entry_struct.timestamp_from_system_start = Some(Duration::from_secs(5));
+        assert!(entry_struct.to_logfmt().starts_with("ts=5 "));
+
+        // Absent fields are left out
+        let entry_struct = Entry {
+            message: "".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(entry_struct.to_logfmt(), r#"msg="""#);
+    }
+
+    #[test]
+    fn test_serialize_to_syslog_rfc5424() {
+        let mut entry_struct = Entry {
+            timestamp_from_system_start: Some(Duration::from_secs_f64(12.34)),
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Info),
+            sequence_num: Some(42),
+            message: "usb 1-1: new high-speed USB device number 2".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            entry_struct.to_syslog_rfc5424(),
+            "<6>1 - - kernel - - - usb 1-1: new high-speed USB device number 2"
+        );
+
+        entry_struct.facility = Some(LogFacility::Daemon);
+        entry_struct.level = Some(LogLevel::Error);
+        assert!(entry_struct.to_syslog_rfc5424().starts_with("<27>1 "));
+
+        entry_struct.facility = None;
+        entry_struct.level = None;
+        assert!(entry_struct.to_syslog_rfc5424().starts_with("<5>1 "));
+    }
+
     #[test]
     fn test_display() {
         let entry_struct = Entry {
@@ -240,6 +934,7 @@ mod tests {
             level: Some(LogLevel::Info),
             sequence_num: Some(15),
             message: "Test message".to_owned(),
+            ..Default::default()
         };
         let expected_serialization = "[    24241.325252] Test message";
 
@@ -251,4 +946,328 @@ mod tests {
         let printed_boxed_entry_struct = format!("{}", boxed_entry_struct);
         assert_eq!(printed_boxed_entry_struct, expected_serialization);
     }
+
+    #[test]
+    fn test_display_without_timestamp() {
+        // As klogctl reads entries from kernels without printk timestamps (CONFIG_PRINTK_TIME)
+        let entry = Entry::from_klog_line("<6>usb 1-1: reset high-speed USB device").unwrap();
+        assert_eq!(entry.timestamp_from_system_start, None);
+        assert_eq!(format!("{}", entry), "usb 1-1: reset high-speed USB device");
+
+        let mut formatter = EntryFormatter::new()
+            .with_decode(true)
+            .with_sequence(true)
+            .with_delta(true);
+        assert_eq!(
+            formatter.format(&entry),
+            "kern  :info  : usb 1-1: reset high-speed USB device"
+        );
+        let boot_time = SystemTime::UNIX_EPOCH;
+        for time_format in [
+            TimeFormat::SecondsSinceBoot,
+            TimeFormat::Ctime(boot_time),
+            TimeFormat::Iso8601(boot_time),
+            TimeFormat::Reltime(boot_time),
+        ]
+        .iter()
+        {
+            let formatted = EntryFormatter::new()
+                .with_timestamp(*time_format)
+                .format(&entry);
+            assert_eq!(formatted, "usb 1-1: reset high-speed USB device");
+        }
+    }
+
+    #[test]
+    fn test_truncate_message() {
+        let mut entry = Entry {
+            message: "Call Trace: dump_stack_lvl+0x48/0x70 panic+0x118/0x2f0".to_owned(),
+            ..Default::default()
+        };
+
+        let mut fits = entry.clone();
+        fits.truncate_message(entry.message.len());
+        assert_eq!(fits, entry);
+
+        entry.truncate_message(10);
+        assert_eq!(entry.message, "Call Trace…");
+
+        // Doesn't cut characters in half
+        entry.message = "ééééé".to_owned();
+        entry.truncate_message(5);
+        assert_eq!(entry.message, "éé…");
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let line = "6,1,0,-;usb 1-1: Product: \x1b[2JEvil";
+        let message = &line[8..];
+
+        let options = ParseOptions::default();
+        assert_eq!(options.message(message), message);
+        assert_eq!(options.raw_line(line), None);
+
+        let options = ParseOptions {
+            raw_lines: true,
+            max_message_len: Some(20),
+            escape_messages: true,
+            ..Default::default()
+        };
+        // Truncated before escaping, so the escape is whole
+        assert_eq!(options.message(message), "usb 1-1: Product: \\x1b[…");
+        assert_eq!(options.raw_line(line).unwrap(), "6,1,0,-;usb 1-1: Pro…");
+    }
+
+    #[test]
+    fn test_escape_message() {
+        let mut entry = Entry {
+            message: "usb 1-1: Product: \x1b[2J\x1b[31mEvil\r\x07\u{85}!".to_owned(),
+            ..Default::default()
+        };
+
+        let unescaped = entry.clone();
+        entry.escape_message();
+        assert_eq!(
+            entry.message,
+            "usb 1-1: Product: \\x1b[2J\\x1b[31mEvil\\x0d\\x07\\xc2\\x85!"
+        );
+        assert_ne!(entry, unescaped);
+
+        // Printable text (tabs and line breaks included) is left alone
+        for s in ["Call Trace:\n <TASK>", "a\tb", "ünïcödé ✓", ""] {
+            assert!(matches!(escape_non_printable(s), Cow::Borrowed(b) if b == s));
+        }
+    }
+
+    #[test]
+    fn test_write_to() {
+        let entries = [
+            Entry {
+                timestamp_from_system_start: Some(Duration::from_secs_f64(24241.325252)),
+                facility: Some(LogFacility::Kern),
+                level: Some(LogLevel::Info),
+                sequence_num: Some(15),
+                message: "Test message".to_owned(),
+                ..Default::default()
+            },
+            Entry {
+                message: "Another test message".to_owned(),
+                ..Default::default()
+            },
+        ];
+
+        // One buffer, reused
+        let mut buffer = String::new();
+        for entry in entries.iter() {
+            buffer.clear();
+            entry.write_to(&mut buffer).unwrap();
+            assert_eq!(buffer, format!("{}", entry));
+        }
+    }
+
+    #[test]
+    fn test_priority_round_trip() {
+        for prio in 0..=191u8 {
+            match decode_priority(prio) {
+                (Some(facility), Some(level)) => {
+                    assert_eq!(encode_priority(facility, level), prio)
+                }
+                decoded => panic!("Unable to decode priority {}: {:?}", prio, decoded),
+            }
+        }
+
+        assert_eq!(
+            decode_priority(6),
+            (Some(LogFacility::Kern), Some(LogLevel::Info))
+        );
+        assert_eq!(
+            decode_priority(191),
+            (Some(LogFacility::Local7), Some(LogLevel::Debug))
+        );
+        for prio in 192..=255u8 {
+            assert_eq!(decode_priority(prio).0, None);
+        }
+    }
+
+    #[test]
+    fn test_severity_and_code() {
+        let levels = [
+            LogLevel::Emergency,
+            LogLevel::Alert,
+            LogLevel::Critical,
+            LogLevel::Error,
+            LogLevel::Warning,
+            LogLevel::Notice,
+            LogLevel::Info,
+            LogLevel::Debug,
+        ];
+        for (severity, level) in levels.iter().enumerate() {
+            assert_eq!(level.as_severity() as usize, severity);
+            assert_eq!(LogLevel::from_severity(level.as_severity()), Some(*level));
+        }
+        assert_eq!(LogLevel::from_severity(8), None);
+
+        // Ordered like their severity numbers: more severe is less
+        assert!(levels.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(LogLevel::Emergency < LogLevel::Warning);
+        assert!(LogLevel::Warning < LogLevel::Debug);
+        assert_eq!(levels.iter().min(), Some(&LogLevel::Emergency));
+        assert!(LogLevel::Error <= LogLevel::Warning && LogLevel::Warning <= LogLevel::Warning);
+        assert!(LogLevel::Notice > LogLevel::Warning);
+
+        assert_eq!(LogFacility::Kern.as_code(), 0);
+        assert_eq!(LogFacility::User.as_code(), 1);
+        assert_eq!(LogFacility::AuthPriv.as_code(), 10);
+        assert_eq!(LogFacility::Local0.as_code(), 16);
+        assert_eq!(LogFacility::Local7.as_code(), 23);
+        for code in 0..=23 {
+            assert_eq!(LogFacility::from_code(code).unwrap().as_code(), code);
+        }
+        assert_eq!(LogFacility::from_code(24), None);
+    }
+
+    #[test]
+    fn test_all() {
+        let levels = LogLevel::all();
+        assert_eq!(levels.len(), 8);
+        assert_eq!(levels.iter().collect::<HashSet<_>>().len(), 8);
+        for (severity, level) in levels.iter().enumerate() {
+            assert_eq!(level.as_severity() as usize, severity);
+        }
+
+        let facilities = LogFacility::all();
+        assert_eq!(facilities.len(), 24);
+        assert_eq!(facilities.iter().collect::<HashSet<_>>().len(), 24);
+        for (code, facility) in facilities.iter().enumerate() {
+            assert_eq!(facility.as_code() as usize, code);
+        }
+    }
+
+    #[test]
+    fn test_log_level_from_str() {
+        let spellings = [
+            ("emerg", LogLevel::Emergency),
+            ("emergency", LogLevel::Emergency),
+            ("panic", LogLevel::Emergency),
+            ("alert", LogLevel::Alert),
+            ("crit", LogLevel::Critical),
+            ("critical", LogLevel::Critical),
+            ("err", LogLevel::Error),
+            ("error", LogLevel::Error),
+            ("warn", LogLevel::Warning),
+            ("warning", LogLevel::Warning),
+            ("notice", LogLevel::Notice),
+            ("info", LogLevel::Info),
+            ("debug", LogLevel::Debug),
+            ("WARNING", LogLevel::Warning),
+            ("Err", LogLevel::Error),
+            ("0", LogLevel::Emergency),
+            ("4", LogLevel::Warning),
+            ("7", LogLevel::Debug),
+        ];
+        for (spelling, level) in spellings.iter() {
+            assert_eq!(spelling.parse::<LogLevel>().unwrap(), *level);
+        }
+
+        // Display round-trips
+        for level in spellings.iter().map(|(_, l)| l) {
+            assert_eq!(level.to_string().parse::<LogLevel>().unwrap(), *level);
+        }
+
+        for invalid in ["verbose", "8", "-1", ""].iter() {
+            assert!(matches!(
+                invalid.parse::<LogLevel>(),
+                Err(RMesgError::UnknownLogLevel(s)) if s == *invalid
+            ));
+        }
+    }
+
+    #[test]
+    fn test_log_facility_from_str() {
+        assert_eq!("kern".parse::<LogFacility>().unwrap(), LogFacility::Kern);
+        assert_eq!("user".parse::<LogFacility>().unwrap(), LogFacility::User);
+        assert_eq!(
+            "local7".parse::<LogFacility>().unwrap(),
+            LogFacility::Local7
+        );
+        assert_eq!(
+            "Daemon".parse::<LogFacility>().unwrap(),
+            LogFacility::Daemon
+        );
+        assert_eq!("UUCP".parse::<LogFacility>().unwrap(), LogFacility::UUCP);
+        assert_eq!("3".parse::<LogFacility>().unwrap(), LogFacility::Daemon);
+        assert_eq!("16".parse::<LogFacility>().unwrap(), LogFacility::Local0);
+
+        // Display round-trips over every numeric facility
+        for num in 0..=23 {
+            let facility = num.to_string().parse::<LogFacility>().unwrap();
+            assert_eq!(facility as u32, num);
+            assert_eq!(
+                facility.to_string().parse::<LogFacility>().unwrap(),
+                facility
+            );
+        }
+
+        for invalid in ["kernel", "24", "local8", ""].iter() {
+            assert!(matches!(
+                invalid.parse::<LogFacility>(),
+                Err(RMesgError::UnknownLogFacility(s)) if s == *invalid
+            ));
+        }
+    }
+
+    #[test]
+    fn test_wall_clock_time() {
+        let boot_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut entry_struct = Entry {
+            timestamp_from_system_start: Some(Duration::from_micros(24241325252)),
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Info),
+            sequence_num: Some(15),
+            message: "Test message".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            entry_struct.wall_clock_time(boot_time),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_micros(1_700_024_241_325_252))
+        );
+
+        entry_struct.timestamp_from_system_start = None;
+        assert_eq!(entry_struct.wall_clock_time(boot_time), None);
+    }
+
+    #[cfg(feature = "extra-traits")]
+    #[test]
+    fn test_json_round_trip() {
+        let entry_struct = Entry {
+            timestamp_from_system_start: Some(Duration::from_micros(24241325252)),
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Info),
+            sequence_num: Some(15),
+            message: "Test message".to_owned(),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&entry_struct).unwrap();
+        assert_eq!(
+            json,
+            r#"{"facility":"kern","level":"info","sequence_num":15,"timestamp_from_system_start":24241.325252,"message":"Test message"}"#
+        );
+        let entry_again: Entry = serde_json::from_str(&json).unwrap();
+        assert_eq!(entry_struct, entry_again);
+
+        let empty_entry_struct = Entry {
+            message: "Test message".to_owned(),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&empty_entry_struct).unwrap();
+        assert_eq!(
+            json,
+            r#"{"facility":null,"level":null,"sequence_num":null,"timestamp_from_system_start":null,"message":"Test message"}"#
+        );
+        let empty_entry_again: Entry = serde_json::from_str(&json).unwrap();
+        assert_eq!(empty_entry_struct, empty_entry_again);
+    }
 }
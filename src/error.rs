@@ -2,6 +2,7 @@ use crate::entry;
 use std::convert::From;
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
 use std::time::SystemTimeError;
 
 #[derive(Debug)]
@@ -17,7 +18,15 @@ pub enum RMesgError {
     EntryParsingError(String),
     UnableToObtainElapsedTime(SystemTimeError),
     DevKMsgFileOpenError(String),
+    DeviceNotFound(PathBuf),
     OperationNotPermitted(String),
+    UnsupportedFilter(String),
+    UnknownLogLevel(String),
+    UnknownLogFacility(String),
+    UnknownBackend(String),
+    NotSupportedOnThisPlatform(String),
+    UnsupportedOperation(String),
+    KMsgParseError { line: String, reason: String },
 }
 impl Error for RMesgError {}
 impl Display for RMesgError {
@@ -39,7 +48,16 @@ impl Display for RMesgError {
                     "Failed to add a Duration to SystemTime".to_owned(),
                 Self::KLogTimestampsDisabled => "Kernel Log timestamps are disabled".to_owned(),
                 Self::DevKMsgFileOpenError(s) => s.to_owned(),
+                Self::DeviceNotFound(path) => format!("DeviceNotFound: {}", path.display()),
                 Self::OperationNotPermitted(s) => format!("OperationNotPermitted: {}", s),
+                Self::UnsupportedFilter(s) => format!("UnsupportedFilter: {}", s),
+                Self::UnknownLogLevel(s) => format!("UnknownLogLevel: {}", s),
+                Self::UnknownLogFacility(s) => format!("UnknownLogFacility: {}", s),
+                Self::UnknownBackend(s) => format!("UnknownBackend: {}", s),
+                Self::NotSupportedOnThisPlatform(s) => format!("NotSupportedOnThisPlatform: {}", s),
+                Self::UnsupportedOperation(s) => format!("UnsupportedOperation: {}", s),
+                Self::KMsgParseError { line, reason } =>
+                    format!("KMsgParseError: {} (line: {})", reason, line),
             }
         )
     }
@@ -0,0 +1,457 @@
+use crate::common;
+use crate::entry::{Entry, EntryParsingError, LogFacility, ParseOptions};
+/// Reads kernel log entries saved to a file, such as /var/log/kern.log
+/// or the output of dmesg, rather than the live kernel log buffer.
+///
+use crate::error::RMesgError;
+use crate::filter::EntryFilter;
+use crate::klogctl;
+use crate::kmsgfile;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+lazy_static! {
+    static ref RE_KMSG_LINE: Regex =
+        Regex::new(r"^[[:digit:]]+,[[:digit:]]+,[[:digit:]]+,[^;]*;").unwrap();
+    static ref RE_KLOG_LINE: Regex = Regex::new(r"^[[:space:]]*<[[:digit:]]+>").unwrap();
+    static ref RE_SYSLOG_LINE: Regex = Regex::new(
+        r"(?x)^
+        # Either 'Jun  5 14:32:01' or '2024-06-05T14:32:01.123456+00:00'
+        ([[:alpha:]]{3}[[:space:]]+[[:digit:]]+[[:space:]]+[[:digit:]]{2}:[[:digit:]]{2}:[[:digit:]]{2}
+        |[[:digit:]]{4}-[[:digit:]]{2}-[[:digit:]]{2}T[[:^space:]]+)
+        [[:space:]]+[[:^space:]]+
+        [[:space:]]+kernel:[[:space:]]?
+        (?P<message>.*)
+        $"
+    )
+    .unwrap();
+    static ref RE_DMESG_LINE: Regex = Regex::new(
+        r"(?x)^
+        [\[][[:space:]]*(?P<timestampstr>[[:digit:]]+\.[[:digit:]]+)[\]][[:space:]]?
+        (?P<message>.*)
+        $"
+    )
+    .unwrap();
+}
+
+// The first two bytes of every gzip file
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The formats of files entries can be read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    /// Lines as read from /dev/kmsg:
+    /// 6,1,4512,-;message
+    KMsg,
+
+    /// Lines as read through klogctl (or printed by `dmesg -r`):
+    /// <6>[    0.004512] message
+    KLog,
+
+    /// Lines as printed by dmesg:
+    /// [    0.004512] message
+    Dmesg,
+
+    /// Lines as written by syslog daemons to /var/log/kern.log:
+    /// Jun  5 14:32:01 hostname kernel: [    0.004512] message
+    Syslog,
+}
+
+impl FileFormat {
+    /// Detects the format of a file from one of its lines
+    pub fn detect(line: &str) -> FileFormat {
+        if RE_KMSG_LINE.is_match(line) {
+            FileFormat::KMsg
+        } else if RE_KLOG_LINE.is_match(line) {
+            FileFormat::KLog
+        } else if RE_SYSLOG_LINE.is_match(line) {
+            FileFormat::Syslog
+        } else {
+            FileFormat::Dmesg
+        }
+    }
+
+    /// Parses a line in this format into an entry.
+    /// Lines not matching the format are kept whole as the message of an entry.
+    pub fn entry_from_line(self, line: &str) -> Result<Entry, EntryParsingError> {
+        self.entry_from_line_with(line, &ParseOptions::default())
+    }
+
+    // Parses a line like `entry_from_line`, with the message made as `parse_options` say
+    fn entry_from_line_with(
+        self,
+        line: &str,
+        parse_options: &ParseOptions,
+    ) -> Result<Entry, EntryParsingError> {
+        match self {
+            FileFormat::KMsg => kmsgfile::entry_from_line_with(line, parse_options),
+            FileFormat::KLog => klogctl::entry_from_line_with(line, parse_options),
+            FileFormat::Dmesg => dmesg_entry_from_line(line, parse_options),
+            FileFormat::Syslog => match RE_SYSLOG_LINE.captures(line) {
+                // Syslog daemons only write lines from the kernel with this tag
+                Some(syslogparts) => Ok(Entry {
+                    facility: Some(LogFacility::Kern),
+                    ..dmesg_entry_from_line(&syslogparts["message"], parse_options)?
+                }),
+                None => dmesg_entry_from_line(line, parse_options),
+            },
+        }
+    }
+}
+
+/// Iterates over the entries in a file, line by line.
+///
+/// The format of the file is detected from its first non-empty line (see `FileFormat`).
+/// Gzip-compressed files are detected too, and decompressed with feature `gzip`.
+/// Empty lines are skipped. Unlike reading the live buffer, iteration ends at the end of the file.
+pub struct FileEntries {
+    filter: EntryFilter,
+    format: Option<FileFormat>,
+    parse_options: ParseOptions,
+    lines_iter: io::Lines<Box<dyn BufRead + Send>>,
+}
+
+impl FileEntries {
+    /// Create a new FileEntries with two specific options
+    /// `path`: The file to read entries from
+    /// `filter: EntryFilter` Entries not accepted by this filter are skipped
+    pub fn with_options<P: AsRef<Path>>(path: P, filter: EntryFilter) -> Result<Self, RMesgError> {
+        Ok(Self {
+            filter,
+            format: None,
+            parse_options: ParseOptions::default(),
+            lines_iter: open(path.as_ref())?.lines(),
+        })
+    }
+
+    /// Sets all of the `ParseOptions` at once, rather than one at a time with the setters
+    /// below. Files are always parsed strictly, whatever the `parse_mode`.
+    pub fn with_parse_options(mut self, parse_options: ParseOptions) -> Self {
+        self.parse_options = parse_options;
+        self
+    }
+
+    /// Sets `ParseOptions::raw_lines`
+    pub fn with_raw_lines(mut self, raw_lines: bool) -> Self {
+        self.parse_options.raw_lines = raw_lines;
+        self
+    }
+
+    /// Sets `ParseOptions::max_message_len`
+    pub fn with_max_message_len(mut self, max_message_len: Option<usize>) -> Self {
+        self.parse_options.max_message_len = max_message_len;
+        self
+    }
+
+    /// Sets `ParseOptions::escape_messages`
+    pub fn with_escape_messages(mut self, escape_messages: bool) -> Self {
+        self.parse_options.escape_messages = escape_messages;
+        self
+    }
+
+    /// The format of the file, once detected from its first non-empty line
+    pub fn format(&self) -> Option<FileFormat> {
+        self.format
+    }
+}
+
+impl Iterator for FileEntries {
+    type Item = Result<Entry, RMesgError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines_iter.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(RMesgError::IOError(format!("{}", e)))),
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let format = *self.format.get_or_insert_with(|| FileFormat::detect(&line));
+            let mut entry = match format.entry_from_line_with(&line, &self.parse_options) {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(RMesgError::from(e))),
+            };
+
+            if self.filter.is_past_until(&entry) {
+                return None;
+            }
+
+            if self.filter.accepts(&entry) {
+                entry.raw_line = self.parse_options.raw_line(&line);
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+// Opens a file for reading, decompressed if it's gzip-compressed
+fn open(path: &Path) -> Result<Box<dyn BufRead + Send>, RMesgError> {
+    let file = fs::File::open(path).map_err(|e| {
+        RMesgError::IOError(format!("Unable to open file {}: {}", path.display(), e))
+    })?;
+    decompressed(BufReader::new(file), path)
+}
+
+// Returns the reader as is, unless it's gzip-compressed, when it returns a reader of the
+// decompressed contents instead (or an error, without feature `gzip`).
+fn decompressed(
+    mut reader: BufReader<fs::File>,
+    path: &Path,
+) -> Result<Box<dyn BufRead + Send>, RMesgError> {
+    let is_gzip = reader
+        .fill_buf()
+        .map_err(|e| RMesgError::IOError(format!("Unable to read file {}: {}", path.display(), e)))?
+        .starts_with(&GZIP_MAGIC);
+    if !is_gzip {
+        return Ok(Box::new(reader));
+    }
+
+    // Multi-member, since concatenated gzip files are valid gzip files too
+    #[cfg(feature = "gzip")]
+    return Ok(Box::new(BufReader::new(
+        flate2::bufread::MultiGzDecoder::new(reader),
+    )));
+
+    #[cfg(not(feature = "gzip"))]
+    Err(RMesgError::UnsupportedOperation(format!(
+        "{} is gzip-compressed, which needs rmesg built with feature gzip",
+        path.display()
+    )))
+}
+
+/// Reads a whole file as one big string with line-breaks, decompressed like `FileEntries`
+/// reads it when it's gzip-compressed
+pub fn file_raw(path: &Path) -> Result<String, RMesgError> {
+    let mut contents = String::new();
+    open(path)?.read_to_string(&mut contents).map_err(|e| {
+        RMesgError::IOError(format!("Unable to read file {}: {}", path.display(), e))
+    })?;
+    Ok(contents)
+}
+
+/// Reads all entries in a file
+pub fn file_entries(path: &Path, filter: EntryFilter) -> Result<Vec<Entry>, RMesgError> {
+    FileEntries::with_options(path, filter)?.collect()
+}
+
+// Parses a line as printed by dmesg, which carries no facility or level:
+// [    1.283919] usb 1-1: new high-speed USB device number 2 using xhci_hcd
+fn dmesg_entry_from_line(
+    line: &str,
+    parse_options: &ParseOptions,
+) -> Result<Entry, EntryParsingError> {
+    match RE_DMESG_LINE.captures(line) {
+        Some(dmesgparts) => Ok(Entry {
+            timestamp_from_system_start: common::parse_timestamp_secs(
+                &dmesgparts["timestampstr"],
+                line,
+            )?,
+            message: parse_options.message(&dmesgparts["message"]),
+            ..Default::default()
+        }),
+        None => Ok(Entry {
+            message: parse_options.message(line),
+            ..Default::default()
+        }),
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::LogLevel;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn fixture(name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+            .iter()
+            .collect()
+    }
+
+    fn read_fixture(name: &str) -> (Vec<Entry>, Option<FileFormat>) {
+        let mut entries = FileEntries::with_options(fixture(name), EntryFilter::default()).unwrap();
+        let parsed: Result<Vec<Entry>, RMesgError> = entries.by_ref().collect();
+        (parsed.unwrap(), entries.format())
+    }
+
+    fn timestamps(entries: &[Entry]) -> Vec<Option<Duration>> {
+        entries
+            .iter()
+            .map(|e| e.timestamp_from_system_start)
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(FileFormat::detect("6,1,4512,-;message"), FileFormat::KMsg);
+        assert_eq!(
+            FileFormat::detect("<6>[    0.004512] message"),
+            FileFormat::KLog
+        );
+        assert_eq!(FileFormat::detect("<6>message"), FileFormat::KLog);
+        assert_eq!(
+            FileFormat::detect("[    0.004512] message"),
+            FileFormat::Dmesg
+        );
+        assert_eq!(
+            FileFormat::detect("Jun  5 14:32:01 buildbox kernel: [    0.004512] message"),
+            FileFormat::Syslog
+        );
+        assert_eq!(
+            FileFormat::detect("2024-06-05T14:32:01.123456+00:00 buildbox kernel: message"),
+            FileFormat::Syslog
+        );
+    }
+
+    #[test]
+    fn test_kern_log() {
+        let (entries, format) = read_fixture("kern.log");
+        assert_eq!(format, Some(FileFormat::Syslog));
+        assert_eq!(entries.len(), 4);
+
+        assert_eq!(
+            entries[0],
+            Entry {
+                facility: Some(LogFacility::Kern),
+                timestamp_from_system_start: Some(Duration::ZERO),
+                message: "Linux version 6.1.0-21-amd64 (debian-kernel@lists.debian.org)".to_owned(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            entries[2].message,
+            "usb 1-1: new high-speed USB device number 2 using xhci_hcd"
+        );
+
+        // Not every line carries a timestamp
+        assert_eq!(
+            entries[3],
+            Entry {
+                facility: Some(LogFacility::Kern),
+                message: "Out of memory: Killed process 1234 (stress)".to_owned(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_kmsg_dump() {
+        let (entries, format) = read_fixture("kmsg.txt");
+        assert_eq!(format, Some(FileFormat::KMsg));
+
+        // Includes the continuation line
+        assert_eq!(entries.len(), 5);
+        assert_eq!(
+            entries[4],
+            Entry {
+                facility: Some(LogFacility::Kern),
+                level: Some(LogLevel::Error),
+                sequence_num: Some(3),
+                timestamp_from_system_start: Some(Duration::from_micros(227_000_001)),
+                message: "Out of memory: Killed process 1234 (stress)".to_owned(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(entries[3].message, " SUBSYSTEM=usb");
+    }
+
+    #[test]
+    fn test_klog_and_dmesg_dumps() {
+        let (klog_entries, format) = read_fixture("klog.txt");
+        assert_eq!(format, Some(FileFormat::KLog));
+        assert_eq!(klog_entries[3].level, Some(LogLevel::Error));
+
+        let (dmesg_entries, format) = read_fixture("dmesg.txt");
+        assert_eq!(format, Some(FileFormat::Dmesg));
+        assert_eq!(dmesg_entries[3].level, None);
+
+        // Same log, different formats (klog messages keep the space after the timestamp)
+        assert_eq!(timestamps(&klog_entries), timestamps(&dmesg_entries));
+        for (klog_entry, dmesg_entry) in klog_entries.iter().zip(dmesg_entries.iter()) {
+            assert_eq!(klog_entry.message.trim_start(), dmesg_entry.message);
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = EntryFilter {
+            min_level: Some(LogLevel::Error),
+            ..Default::default()
+        };
+        let entries = file_entries(&fixture("klog.txt"), filter).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].message,
+            " Out of memory: Killed process 1234 (stress)"
+        );
+
+        let filter = EntryFilter {
+            until: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        assert_eq!(
+            file_entries(&fixture("dmesg.txt"), filter).unwrap().len(),
+            2
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        let (entries, format) = read_fixture("kern.log.gz");
+        assert_eq!(format, Some(FileFormat::Syslog));
+        assert_eq!(entries, read_fixture("kern.log").0);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_file_raw_gzip() {
+        assert_eq!(
+            file_raw(&fixture("kern.log.gz")).unwrap(),
+            file_raw(&fixture("kern.log")).unwrap()
+        );
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn test_gzip_unsupported() {
+        assert!(matches!(
+            FileEntries::with_options(fixture("kern.log.gz"), EntryFilter::default()),
+            Err(RMesgError::UnsupportedOperation(_))
+        ));
+    }
+
+    #[test]
+    fn test_escape_and_truncate() {
+        let entries: Vec<Entry> =
+            FileEntries::with_options(fixture("kmsg_escapes.txt"), EntryFilter::default())
+                .unwrap()
+                .with_max_message_len(Some(19))
+                .with_escape_messages(true)
+                .collect::<Result<_, _>>()
+                .unwrap();
+
+        // The escape sequence is cut after its first byte, which is escaped whole
+        assert_eq!(entries[0].message, "usb 1-1: Product: \\x1b…");
+        assert_eq!(entries[1].message, "usb 1-1: Manufactur…");
+    }
+
+    #[test]
+    fn test_missing_file() {
+        assert!(matches!(
+            FileEntries::with_options(fixture("doesnotexist.log"), EntryFilter::default()),
+            Err(RMesgError::IOError(_))
+        ));
+    }
+}
@@ -0,0 +1,532 @@
+use crate::entry::{Entry, LogFacility, LogLevel};
+use regex::Regex;
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Where a message originated, as told by its facility
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageSource {
+    /// Messages logged by the kernel (the `kern` facility), like `dmesg -k`
+    Kernel,
+    /// Messages written to the log buffer from userspace (any other facility), like `dmesg -u`
+    Userspace,
+}
+
+/// Criteria deciding which entries the reading APIs yield.
+///
+/// The default filter accepts every entry. Entries missing a field that a
+/// criterion looks at are kept, so that malformed or raw lines are never
+/// silently hidden. The exceptions are the time window and the sequence number range:
+/// when `since` or `until` (or `min_seq` or `max_seq`) is set, entries without a
+/// timestamp (or sequence number) are dropped since they can't be placed in it.
+#[derive(Clone, Debug, Default)]
+pub struct EntryFilter {
+    /// When set, drops entries less severe than this level (like `dmesg --level`).
+    /// `LogLevel::Warning` keeps warnings, errors, critical, alerts and emergencies.
+    pub min_level: Option<LogLevel>,
+
+    /// When set, only keeps entries at these levels (like `dmesg --level=warn,err`).
+    /// Entries without a level are always kept.
+    pub levels: Option<HashSet<LogLevel>>,
+
+    /// When set, only keeps entries from these facilities (like `dmesg --facility`).
+    /// Entries without a facility are always kept.
+    pub facilities: Option<HashSet<LogFacility>>,
+
+    /// When set, drops entries logged before this time since system start.
+    pub since: Option<Duration>,
+
+    /// When set, drops entries logged after this time since system start.
+    /// Iterators and streams end at the first entry past this bound.
+    pub until: Option<Duration>,
+
+    /// When set, only keeps kernel or only keeps userspace messages.
+    /// Entries without a facility are always kept.
+    ///
+    /// The klogctl backend can't reliably tell userspace messages apart, and refuses
+    /// `MessageSource::Userspace` with `RMesgError::UnsupportedFilter`.
+    pub source: Option<MessageSource>,
+
+    /// When set, only keeps entries whose message matches this regex (like `dmesg | grep`).
+    /// Prefix the pattern with `(?i)` to match case-insensitively.
+    pub message_regex: Option<Regex>,
+
+    /// Inverts `message_regex`, to only keep entries whose message doesn't match
+    /// (like `dmesg | grep -v`). Has no effect when `message_regex` isn't set.
+    pub invert_message_regex: bool,
+
+    /// When set, drops entries with a sequence number lower than this.
+    /// Only the devkmsg backend (and files in its format) number entries.
+    ///
+    /// The klogctl backend refuses it with `RMesgError::UnsupportedFilter`.
+    pub min_seq: Option<usize>,
+
+    /// When set, drops entries with a sequence number higher than this.
+    /// Iterators and streams end at the first entry past this bound.
+    ///
+    /// The klogctl backend refuses it with `RMesgError::UnsupportedFilter`.
+    pub max_seq: Option<usize>,
+}
+
+// Regex doesn't implement PartialEq, so filters compare their patterns instead
+impl PartialEq for EntryFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.min_level == other.min_level
+            && self.levels == other.levels
+            && self.facilities == other.facilities
+            && self.since == other.since
+            && self.until == other.until
+            && self.source == other.source
+            && self.message_regex.as_ref().map(Regex::as_str)
+                == other.message_regex.as_ref().map(Regex::as_str)
+            && self.invert_message_regex == other.invert_message_regex
+            && self.min_seq == other.min_seq
+            && self.max_seq == other.max_seq
+    }
+}
+
+impl EntryFilter {
+    /// Returns true if the entry passes every criterion of this filter.
+    pub fn accepts(&self, entry: &Entry) -> bool {
+        let level_ok = match (self.min_level, entry.level) {
+            // More severe levels order before less severe ones
+            (Some(min_level), Some(level)) => level <= min_level,
+            _ => true,
+        };
+
+        let levels_ok = match (&self.levels, entry.level) {
+            (Some(levels), Some(level)) => levels.contains(&level),
+            _ => true,
+        };
+
+        let facility_ok = match (&self.facilities, entry.facility) {
+            (Some(facilities), Some(facility)) => facilities.contains(&facility),
+            _ => true,
+        };
+
+        let window_ok = match (self.since, self.until, entry.timestamp_from_system_start) {
+            (None, None, _) => true,
+            (_, _, None) => false,
+            (since, until, Some(timestamp)) => {
+                since.is_none_or(|since| timestamp >= since)
+                    && until.is_none_or(|until| timestamp <= until)
+            }
+        };
+
+        let source_ok = match (self.source, entry.facility) {
+            (Some(MessageSource::Kernel), Some(facility)) => facility == LogFacility::Kern,
+            (Some(MessageSource::Userspace), Some(facility)) => facility != LogFacility::Kern,
+            _ => true,
+        };
+
+        let message_ok = match &self.message_regex {
+            Some(regex) => regex.is_match(&entry.message) != self.invert_message_regex,
+            None => true,
+        };
+
+        let seq_ok = match (self.min_seq, self.max_seq, entry.sequence_num) {
+            (None, None, _) => true,
+            (_, _, None) => false,
+            (min_seq, max_seq, Some(sequence_num)) => {
+                min_seq.is_none_or(|min_seq| sequence_num >= min_seq)
+                    && max_seq.is_none_or(|max_seq| sequence_num <= max_seq)
+            }
+        };
+
+        level_ok && levels_ok && facility_ok && window_ok && source_ok && message_ok && seq_ok
+    }
+
+    /// Returns true if the entry was logged after `until`, or numbered after `max_seq`.
+    ///
+    /// Kernel log entries are logged in increasing timestamp (and sequence number) order,
+    /// so no entry following this one can be accepted either, and readers can stop.
+    pub fn is_past_until(&self, entry: &Entry) -> bool {
+        let past_until = match (self.until, entry.timestamp_from_system_start) {
+            (Some(until), Some(timestamp)) => timestamp > until,
+            _ => false,
+        };
+
+        let past_max_seq = match (self.max_seq, entry.sequence_num) {
+            (Some(max_seq), Some(sequence_num)) => sequence_num > max_seq,
+            _ => false,
+        };
+
+        past_until || past_max_seq
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry_with_level(level: Option<LogLevel>) -> Entry {
+        Entry {
+            facility: Some(LogFacility::Kern),
+            level,
+            message: format!("{:?}", level),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_accepts_everything() {
+        let filter = EntryFilter::default();
+        assert!(filter.accepts(&entry_with_level(Some(LogLevel::Debug))));
+        assert!(filter.accepts(&entry_with_level(Some(LogLevel::Emergency))));
+        assert!(filter.accepts(&entry_with_level(None)));
+    }
+
+    #[test]
+    fn test_min_level() {
+        let entries = vec![
+            entry_with_level(Some(LogLevel::Emergency)),
+            entry_with_level(Some(LogLevel::Debug)),
+            entry_with_level(Some(LogLevel::Error)),
+            entry_with_level(None),
+            entry_with_level(Some(LogLevel::Info)),
+            entry_with_level(Some(LogLevel::Warning)),
+            entry_with_level(Some(LogLevel::Notice)),
+        ];
+
+        let filter = EntryFilter {
+            min_level: Some(LogLevel::Warning),
+            ..Default::default()
+        };
+
+        let levels: Vec<Option<LogLevel>> = entries
+            .into_iter()
+            .filter(|e| filter.accepts(e))
+            .map(|e| e.level)
+            .collect();
+
+        assert_eq!(
+            levels,
+            vec![
+                Some(LogLevel::Emergency),
+                Some(LogLevel::Error),
+                None,
+                Some(LogLevel::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_levels() {
+        let entries = vec![
+            entry_with_level(Some(LogLevel::Emergency)),
+            entry_with_level(Some(LogLevel::Error)),
+            entry_with_level(None),
+            entry_with_level(Some(LogLevel::Warning)),
+            entry_with_level(Some(LogLevel::Info)),
+        ];
+
+        // Unlike min_level, more severe levels than those listed are dropped too
+        let filter = EntryFilter {
+            levels: Some([LogLevel::Warning, LogLevel::Error].into()),
+            ..Default::default()
+        };
+
+        let levels: Vec<Option<LogLevel>> = entries
+            .into_iter()
+            .filter(|e| filter.accepts(e))
+            .map(|e| e.level)
+            .collect();
+
+        assert_eq!(
+            levels,
+            vec![Some(LogLevel::Error), None, Some(LogLevel::Warning)]
+        );
+    }
+
+    fn entry_with_facility(facility: Option<LogFacility>) -> Entry {
+        Entry {
+            facility,
+            level: Some(LogLevel::Info),
+            message: format!("{:?}", facility),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_facilities() {
+        let entries = vec![
+            entry_with_facility(Some(LogFacility::Kern)),
+            entry_with_facility(Some(LogFacility::User)),
+            entry_with_facility(None),
+            entry_with_facility(Some(LogFacility::Daemon)),
+            entry_with_facility(Some(LogFacility::User)),
+            entry_with_facility(Some(LogFacility::Kern)),
+        ];
+
+        let filter = EntryFilter {
+            facilities: Some([LogFacility::Kern, LogFacility::Daemon].into()),
+            ..Default::default()
+        };
+
+        let facilities: Vec<Option<LogFacility>> = entries
+            .into_iter()
+            .filter(|e| filter.accepts(e))
+            .map(|e| e.facility)
+            .collect();
+
+        assert_eq!(
+            facilities,
+            vec![
+                Some(LogFacility::Kern),
+                None,
+                Some(LogFacility::Daemon),
+                Some(LogFacility::Kern),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_min_level_and_facilities() {
+        let filter = EntryFilter {
+            min_level: Some(LogLevel::Error),
+            facilities: Some([LogFacility::Kern].into()),
+            ..Default::default()
+        };
+
+        let mut entry = entry_with_facility(Some(LogFacility::Kern));
+        assert!(!filter.accepts(&entry));
+
+        entry.level = Some(LogLevel::Critical);
+        assert!(filter.accepts(&entry));
+
+        entry.facility = Some(LogFacility::User);
+        assert!(!filter.accepts(&entry));
+    }
+
+    fn entry_with_timestamp(timestamp: Option<u64>) -> Entry {
+        Entry {
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Info),
+            timestamp_from_system_start: timestamp.map(Duration::from_secs),
+            message: format!("{:?}", timestamp),
+            ..Default::default()
+        }
+    }
+
+    fn accepted_timestamps(filter: &EntryFilter) -> Vec<Option<u64>> {
+        vec![Some(1), None, Some(5), Some(10), Some(15), Some(20)]
+            .into_iter()
+            .map(entry_with_timestamp)
+            .filter(|e| filter.accepts(e))
+            .map(|e| e.timestamp_from_system_start.map(|ts| ts.as_secs()))
+            .collect()
+    }
+
+    #[test]
+    fn test_since_and_until() {
+        let filter = EntryFilter {
+            since: Some(Duration::from_secs(5)),
+            until: Some(Duration::from_secs(15)),
+            ..Default::default()
+        };
+        assert_eq!(
+            accepted_timestamps(&filter),
+            vec![Some(5), Some(10), Some(15)]
+        );
+
+        assert!(!filter.is_past_until(&entry_with_timestamp(Some(15))));
+        assert!(filter.is_past_until(&entry_with_timestamp(Some(16))));
+        assert!(!filter.is_past_until(&entry_with_timestamp(None)));
+    }
+
+    #[test]
+    fn test_only_since() {
+        let filter = EntryFilter {
+            since: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        assert_eq!(
+            accepted_timestamps(&filter),
+            vec![Some(10), Some(15), Some(20)]
+        );
+        assert!(!filter.is_past_until(&entry_with_timestamp(Some(1000))));
+    }
+
+    #[test]
+    fn test_only_until() {
+        let filter = EntryFilter {
+            until: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        assert_eq!(
+            accepted_timestamps(&filter),
+            vec![Some(1), Some(5), Some(10)]
+        );
+    }
+
+    #[test]
+    fn test_no_window_keeps_untimestamped() {
+        assert_eq!(
+            accepted_timestamps(&EntryFilter::default()),
+            vec![Some(1), None, Some(5), Some(10), Some(15), Some(20)]
+        );
+    }
+
+    #[test]
+    fn test_source() {
+        let facilities = [
+            Some(LogFacility::Kern),
+            Some(LogFacility::User),
+            None,
+            Some(LogFacility::Daemon),
+            Some(LogFacility::Kern),
+        ];
+
+        let accepted = |source| {
+            let filter = EntryFilter {
+                source: Some(source),
+                ..Default::default()
+            };
+            facilities
+                .iter()
+                .map(|f| entry_with_facility(*f))
+                .filter(|e| filter.accepts(e))
+                .map(|e| e.facility)
+                .collect::<Vec<Option<LogFacility>>>()
+        };
+
+        assert_eq!(
+            accepted(MessageSource::Kernel),
+            vec![Some(LogFacility::Kern), None, Some(LogFacility::Kern)]
+        );
+        assert_eq!(
+            accepted(MessageSource::Userspace),
+            vec![Some(LogFacility::User), None, Some(LogFacility::Daemon)]
+        );
+    }
+
+    fn entry_with_sequence_num(sequence_num: Option<usize>) -> Entry {
+        Entry {
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Info),
+            sequence_num,
+            message: format!("{:?}", sequence_num),
+            ..Default::default()
+        }
+    }
+
+    fn accepted_sequence_nums(filter: &EntryFilter) -> Vec<Option<usize>> {
+        vec![Some(10), Some(11), None, Some(12), Some(13)]
+            .into_iter()
+            .map(entry_with_sequence_num)
+            .filter(|e| filter.accepts(e))
+            .map(|e| e.sequence_num)
+            .collect()
+    }
+
+    #[test]
+    fn test_sequence_num_range() {
+        let filter = EntryFilter {
+            min_seq: Some(11),
+            max_seq: Some(12),
+            ..Default::default()
+        };
+        assert_eq!(accepted_sequence_nums(&filter), vec![Some(11), Some(12)]);
+
+        assert!(!filter.is_past_until(&entry_with_sequence_num(Some(12))));
+        assert!(filter.is_past_until(&entry_with_sequence_num(Some(13))));
+        assert!(!filter.is_past_until(&entry_with_sequence_num(None)));
+
+        let filter = EntryFilter {
+            min_seq: Some(12),
+            ..Default::default()
+        };
+        assert_eq!(accepted_sequence_nums(&filter), vec![Some(12), Some(13)]);
+        assert!(!filter.is_past_until(&entry_with_sequence_num(Some(1000))));
+
+        let filter = EntryFilter {
+            max_seq: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(accepted_sequence_nums(&filter), vec![Some(10)]);
+
+        // Without a range, entries without a sequence number are kept
+        assert_eq!(
+            accepted_sequence_nums(&EntryFilter::default()),
+            vec![Some(10), Some(11), None, Some(12), Some(13)]
+        );
+    }
+
+    fn accepted_messages(filter: &EntryFilter) -> Vec<String> {
+        [
+            "Out of memory: Killed process 1234 (stress)",
+            "usb 1-1: new high-speed USB device number 2",
+            "oom_reaper: reaped process 1234 (stress)",
+            "EXT4-fs (vda): mounted filesystem",
+        ]
+        .iter()
+        .map(|message| Entry {
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Info),
+            message: message.to_string(),
+            ..Default::default()
+        })
+        .filter(|e| filter.accepts(e))
+        .map(|e| e.message)
+        .collect()
+    }
+
+    #[test]
+    fn test_message_regex() {
+        let filter = EntryFilter {
+            message_regex: Some(Regex::new("oom").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            accepted_messages(&filter),
+            vec!["oom_reaper: reaped process 1234 (stress)"]
+        );
+
+        let filter = EntryFilter {
+            message_regex: Some(Regex::new("(?i)out of memory|oom").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            accepted_messages(&filter),
+            vec![
+                "Out of memory: Killed process 1234 (stress)",
+                "oom_reaper: reaped process 1234 (stress)",
+            ]
+        );
+
+        let filter = EntryFilter {
+            message_regex: Some(Regex::new(r"^\S+ \d+-\d+:").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            accepted_messages(&filter),
+            vec!["usb 1-1: new high-speed USB device number 2"]
+        );
+    }
+
+    #[test]
+    fn test_invert_message_regex() {
+        let filter = EntryFilter {
+            message_regex: Some(Regex::new("(?i)oom|memory").unwrap()),
+            invert_message_regex: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            accepted_messages(&filter),
+            vec![
+                "usb 1-1: new high-speed USB device number 2",
+                "EXT4-fs (vda): mounted filesystem",
+            ]
+        );
+
+        // Without a regex, inverting keeps everything
+        let filter = EntryFilter {
+            invert_message_regex: true,
+            ..Default::default()
+        };
+        assert_eq!(accepted_messages(&filter).len(), 4);
+    }
+}
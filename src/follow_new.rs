@@ -0,0 +1,150 @@
+use crate::entry::Entry;
+use crate::error::RMesgError;
+use std::time::Duration;
+
+#[cfg(feature = "async")]
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use futures::stream::Stream;
+#[cfg(feature = "async")]
+use futures::task::{Context, Poll};
+
+/// Wraps an iterator or stream of entries, and skips the entries that were already in the
+/// buffer when it was created, so only entries logged after that are yielded (like `dmesg -W`).
+///
+/// Entries are compared against the newest of the existing entries by sequence number,
+/// or by timestamp for entries without one (like entries read through klogctl). Entries
+/// with neither (like raw entries) can't be told apart, so they all pass through.
+pub struct FollowNew<I> {
+    inner: I,
+    last_sequence_num: Option<usize>,
+    last_timestamp: Option<Duration>,
+}
+
+impl<I> FollowNew<I> {
+    /// `existing` are the entries in the buffer at the start, e.g. from `rmesg::log_entries`
+    pub fn new(inner: I, existing: &[Entry]) -> Self {
+        Self {
+            inner,
+            last_sequence_num: existing.iter().filter_map(|e| e.sequence_num).max(),
+            last_timestamp: existing
+                .iter()
+                .filter_map(|e| e.timestamp_from_system_start)
+                .max(),
+        }
+    }
+
+    fn is_new(&self, entry: &Entry) -> bool {
+        match (entry.sequence_num, entry.timestamp_from_system_start) {
+            (Some(sequence_num), _) => self
+                .last_sequence_num
+                .is_none_or(|last| sequence_num > last),
+            (None, Some(timestamp)) => self.last_timestamp.is_none_or(|last| timestamp > last),
+            (None, None) => true,
+        }
+    }
+
+    fn accepts(&self, next: &Option<Result<Entry, RMesgError>>) -> bool {
+        match next {
+            Some(Ok(entry)) => self.is_new(entry),
+            _ => true,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Entry, RMesgError>>> Iterator for FollowNew<I> {
+    type Item = Result<Entry, RMesgError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let next = self.inner.next();
+            if self.accepts(&next) {
+                return next;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<S: Stream<Item = Result<Entry, RMesgError>> + Unpin> Stream for FollowNew<S> {
+    type Item = Result<Entry, RMesgError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let next = match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(next) => next,
+                Poll::Pending => return Poll::Pending,
+            };
+            if this.accepts(&next) {
+                return Poll::Ready(next);
+            }
+        }
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util;
+
+    fn entry(sequence_num: Option<usize>, secs: u64) -> Entry {
+        Entry {
+            sequence_num,
+            timestamp_from_system_start: Some(Duration::from_secs(secs)),
+            ..test_util::entry(&format!("Logged at {}", secs))
+        }
+    }
+
+    #[test]
+    fn test_sequence_nums() {
+        let existing: Vec<_> = (1..4).map(|n| entry(Some(n), n as u64)).collect();
+        let buffer = (1..6).map(|n| Ok(entry(Some(n), n as u64)));
+
+        let new: Vec<_> = FollowNew::new(buffer, &existing)
+            .map(|e| e.unwrap().sequence_num)
+            .collect();
+        assert_eq!(new, vec![Some(4), Some(5)]);
+    }
+
+    #[test]
+    fn test_timestamps() {
+        // Entries without sequence numbers, like those read through klogctl
+        let existing = vec![entry(None, 1), entry(None, 2)];
+        let buffer = vec![
+            Ok(entry(None, 1)),
+            Ok(entry(None, 2)),
+            Err(RMesgError::InternalError("Parsing failed".to_owned())),
+            Ok(entry(None, 3)),
+        ];
+
+        let new: Vec<_> = FollowNew::new(buffer.into_iter(), &existing).collect();
+        assert_eq!(new.len(), 2);
+        assert!(new[0].is_err());
+        assert_eq!(new[1].as_ref().unwrap(), &entry(None, 3));
+    }
+
+    #[test]
+    fn test_empty_buffer() {
+        let buffer = vec![Ok(entry(Some(0), 0)), Ok(entry(Some(1), 1))];
+        assert_eq!(FollowNew::new(buffer.into_iter(), &[]).count(), 2);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_stream() {
+        use futures::stream::{self, StreamExt};
+
+        let existing: Vec<_> = (1..4).map(|n| entry(Some(n), n as u64)).collect();
+        let buffer: Vec<_> = (1..6).map(|n| Ok(entry(Some(n), n as u64))).collect();
+
+        let new: Vec<_> = FollowNew::new(stream::iter(buffer), &existing)
+            .map(|e| e.unwrap().sequence_num)
+            .collect()
+            .await;
+        assert_eq!(new, vec![Some(4), Some(5)]);
+    }
+}
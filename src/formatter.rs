@@ -0,0 +1,542 @@
+use crate::entry::{encode_priority, Entry};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use std::fmt::{Result as FmtResult, Write};
+use std::time::{Duration, SystemTime};
+
+/// How the timestamp of an entry is printed
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum TimeFormat {
+    /// Seconds since system start (the default):
+    /// [    24241.325252]
+    #[default]
+    SecondsSinceBoot,
+
+    /// Local wall-clock time (like `dmesg -T`), computed from the time the system booted
+    /// (see `rmesg::system_boot_time`):
+    /// [Wed Jun  5 14:32:01 2024]
+    ///
+    /// May be inaccurate if the system was suspended or its clock changed since boot.
+    Ctime(SystemTime),
+
+    /// ISO-8601 (RFC3339) wall-clock time in UTC, with microseconds, computed from the time
+    /// the system booted (see `rmesg::system_boot_time`). Suited to log ingestion:
+    /// 2024-06-05T14:32:01.123456Z
+    ///
+    /// May be inaccurate if the system was suspended or its clock changed since boot.
+    Iso8601(SystemTime),
+
+    /// Compact local wall-clock time (like `dmesg -e`), computed from the time the system
+    /// booted (see `rmesg::system_boot_time`). The date is printed for the first entry
+    /// formatted and whenever the day changes, and only the time otherwise:
+    /// [Jun 5 23:59] first message
+    /// [23:59:30] second message
+    /// [Jun 6 00:00] third message
+    ///
+    /// This makes the formatter stateful, so use one formatter per sequence of entries.
+    /// May be inaccurate if the system was suspended or its clock changed since boot.
+    Reltime(SystemTime),
+
+    /// No timestamp at all (like `dmesg -t`), only the message and any other parts
+    None,
+}
+
+/// Formats entries for display, with configurable parts.
+///
+/// The `Display` impl of `Entry` uses the default formatter, which prints the
+/// timestamp (in seconds since system start) followed by the message.
+///
+/// ```
+/// use rmesg::formatter::{EntryFormatter, TimeFormat};
+///
+/// let formatter = EntryFormatter::new()
+///     .with_timestamp(TimeFormat::SecondsSinceBoot)
+///     .with_decode(true)
+///     .with_sequence(true);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EntryFormatter {
+    time_format: TimeFormat,
+    decode: bool,
+    priority: bool,
+    sequence: bool,
+    delta: bool,
+    force_prefix: bool,
+
+    // Timestamp of the previous entry formatted, to compute deltas
+    previous_timestamp: Option<Duration>,
+
+    // Day of the previous entry formatted with TimeFormat::Reltime, to print dates on rollovers
+    previous_date: Option<NaiveDate>,
+}
+
+impl EntryFormatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how timestamps are printed.
+    pub fn with_timestamp(mut self, time_format: TimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
+
+    /// When set, prefixes entries with their facility and level names in fixed-width
+    /// columns (like `dmesg -x`):
+    /// kern  :warn  : [    24241.325252] message
+    ///
+    /// A missing facility or level prints as blanks, to keep columns aligned.
+    pub fn with_decode(mut self, decode: bool) -> Self {
+        self.decode = decode;
+        self
+    }
+
+    /// When set, prefixes entries with their syslog priority number (as in the `<N>` lines
+    /// start with), along with the facility and level it encodes, to check the decoding against:
+    /// prio=4 (kern.warn) [    24241.325252] message
+    ///
+    /// Entries missing a facility or level have no priority, and print none.
+    pub fn with_priority(mut self, priority: bool) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// When set, prefixes entries with their sequence number (when they have one):
+    /// [seq 42] [    24241.325252] message
+    pub fn with_sequence(mut self, sequence: bool) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// When set, prints the time elapsed since the previous entry formatted
+    /// (like `dmesg -d`). This makes the formatter stateful, so use one formatter per
+    /// sequence of entries:
+    /// <+    0.000123> [    24241.325252] message
+    ///
+    /// The first entry has a delta of zero. An entry with an earlier timestamp than its
+    /// predecessor (e.g. when sequence numbers and timestamps disagree) is clamped to
+    /// a delta of zero. Entries without a timestamp print no delta.
+    pub fn with_delta(mut self, delta: bool) -> Self {
+        self.delta = delta;
+        self
+    }
+
+    /// When set, repeats the prefix (the timestamp and any other parts before the message) on
+    /// every line of a message with line breaks (like `dmesg --force-prefix`):
+    /// [    24241.325252] first line
+    /// [    24241.325252] second line
+    ///
+    /// Otherwise only the first line is prefixed.
+    pub fn with_force_prefix(mut self, force_prefix: bool) -> Self {
+        self.force_prefix = force_prefix;
+        self
+    }
+
+    /// Formats the entry into a new String
+    pub fn format(&mut self, entry: &Entry) -> String {
+        let mut formatted = String::with_capacity(entry.message.len() + 32);
+        // Writing into a String can't fail
+        let _ = self.write_entry(&mut formatted, entry);
+        formatted
+    }
+
+    /// Formats the entry into any writer
+    pub fn write_entry<W: Write>(&mut self, w: &mut W, entry: &Entry) -> FmtResult {
+        if !self.force_prefix {
+            self.write_prefix(w, entry)?;
+            return w.write_str(&entry.message);
+        }
+
+        // Written once and repeated, since writing deltas and reltimes changes the state
+        let mut prefix = String::with_capacity(64);
+        self.write_prefix(&mut prefix, entry)?;
+        for (i, line) in entry.message.split('\n').enumerate() {
+            if i > 0 {
+                w.write_char('\n')?;
+            }
+            w.write_str(&prefix)?;
+            w.write_str(line)?;
+        }
+        Ok(())
+    }
+
+    // Everything before the message
+    fn write_prefix<W: Write>(&mut self, w: &mut W, entry: &Entry) -> FmtResult {
+        if self.priority {
+            if let (Some(facility), Some(level)) = (entry.facility, entry.level) {
+                write!(
+                    w,
+                    "prio={} ({}.{}) ",
+                    encode_priority(facility, level),
+                    facility,
+                    level
+                )?;
+            }
+        }
+
+        if self.decode {
+            match entry.facility {
+                Some(facility) => write!(w, "{:<6}:", facility)?,
+                None => w.write_str("      :")?,
+            }
+            match entry.level {
+                Some(level) => write!(w, "{:<6}: ", level)?,
+                None => w.write_str("      : ")?,
+            }
+        }
+
+        if self.sequence {
+            if let Some(sequence_num) = entry.sequence_num {
+                write!(w, "[seq {}] ", sequence_num)?;
+            }
+        }
+
+        if self.delta {
+            if let Some(delta) = self.delta_since_previous(entry.timestamp_from_system_start) {
+                write!(w, "<+{: >12.6}> ", delta.as_secs_f64())?;
+            }
+        }
+
+        if let Some(ts) = entry.timestamp_from_system_start {
+            match self.time_format {
+                TimeFormat::SecondsSinceBoot => write!(w, "[{: >16.6}] ", ts.as_secs_f64())?,
+                TimeFormat::Ctime(boot_time) => {
+                    if let Some(wall_clock_time) = boot_time.checked_add(ts) {
+                        write!(
+                            w,
+                            "[{}] ",
+                            DateTime::<Local>::from(wall_clock_time).format("%a %b %e %H:%M:%S %Y")
+                        )?
+                    }
+                }
+                TimeFormat::Reltime(boot_time) => {
+                    if let Some(wall_clock_time) = boot_time.checked_add(ts) {
+                        let local_time = DateTime::<Local>::from(wall_clock_time);
+                        let date = local_time.date_naive();
+                        match self.previous_date.replace(date) {
+                            Some(previous_date) if previous_date == date => {
+                                write!(w, "[{}] ", local_time.format("%H:%M:%S"))?
+                            }
+                            _ => write!(w, "[{}] ", local_time.format("%b %-d %H:%M"))?,
+                        }
+                    }
+                }
+                TimeFormat::None => {}
+                TimeFormat::Iso8601(boot_time) => {
+                    if let Some(wall_clock_time) = boot_time.checked_add(ts) {
+                        write!(
+                            w,
+                            "{} ",
+                            DateTime::<Utc>::from(wall_clock_time).format("%Y-%m-%dT%H:%M:%S%.6fZ")
+                        )?
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delta_since_previous(&mut self, timestamp: Option<Duration>) -> Option<Duration> {
+        let timestamp = timestamp?;
+        let delta = match self.previous_timestamp {
+            Some(previous) => timestamp.saturating_sub(previous),
+            None => Duration::ZERO,
+        };
+
+        self.previous_timestamp = Some(timestamp);
+        Some(delta)
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::{LogFacility, LogLevel};
+    use chrono::TimeZone;
+
+    fn test_entry() -> Entry {
+        Entry {
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Warning),
+            sequence_num: Some(42),
+            timestamp_from_system_start: Some(Duration::from_secs_f64(1921.5)),
+            message: "Test message".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_matches_display() {
+        let entry = test_entry();
+        assert_eq!(
+            EntryFormatter::new().format(&entry),
+            "[     1921.500000] Test message"
+        );
+        assert_eq!(EntryFormatter::new().format(&entry), format!("{}", entry));
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut formatter = EntryFormatter::new().with_decode(true);
+        let mut entry = test_entry();
+        assert_eq!(
+            formatter.format(&entry),
+            "kern  :warn  : [     1921.500000] Test message"
+        );
+
+        entry.facility = Some(LogFacility::Daemon);
+        entry.level = Some(LogLevel::Notice);
+        assert_eq!(
+            formatter.format(&entry),
+            "daemon:notice: [     1921.500000] Test message"
+        );
+
+        entry.facility = Some(LogFacility::AuthPriv);
+        entry.level = Some(LogLevel::Emergency);
+        assert_eq!(
+            formatter.format(&entry),
+            "authpriv:emerg : [     1921.500000] Test message"
+        );
+
+        entry.facility = None;
+        entry.level = Some(LogLevel::Info);
+        assert_eq!(
+            formatter.format(&entry),
+            "      :info  : [     1921.500000] Test message"
+        );
+
+        entry.level = None;
+        assert_eq!(
+            formatter.format(&entry),
+            "      :      : [     1921.500000] Test message"
+        );
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut formatter = EntryFormatter::new().with_priority(true);
+        let mut entry = test_entry();
+        assert_eq!(
+            formatter.format(&entry),
+            "prio=4 (kern.warn) [     1921.500000] Test message"
+        );
+
+        entry.facility = Some(LogFacility::Daemon);
+        entry.level = Some(LogLevel::Info);
+        assert_eq!(
+            formatter.format(&entry),
+            "prio=30 (daemon.info) [     1921.500000] Test message"
+        );
+
+        entry.facility = Some(LogFacility::Local7);
+        entry.level = Some(LogLevel::Debug);
+        assert_eq!(
+            formatter.format(&entry),
+            "prio=191 (local7.debug) [     1921.500000] Test message"
+        );
+
+        // Along with the decoded columns
+        let mut formatter = formatter.with_decode(true);
+        entry.facility = Some(LogFacility::Kern);
+        entry.level = Some(LogLevel::Emergency);
+        assert_eq!(
+            formatter.format(&entry),
+            "prio=0 (kern.emerg) kern  :emerg : [     1921.500000] Test message"
+        );
+
+        entry.level = None;
+        assert_eq!(
+            formatter.format(&entry),
+            "kern  :      : [     1921.500000] Test message"
+        );
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut formatter = EntryFormatter::new().with_sequence(true);
+        let mut entry = test_entry();
+        assert_eq!(
+            formatter.format(&entry),
+            "[seq 42] [     1921.500000] Test message"
+        );
+
+        entry.sequence_num = None;
+        assert_eq!(formatter.format(&entry), "[     1921.500000] Test message");
+    }
+
+    #[test]
+    fn test_ctime() {
+        // Wed Jun  5 14:00:00 2024 UTC
+        let boot_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_596_000);
+        let mut formatter = EntryFormatter::new().with_timestamp(TimeFormat::Ctime(boot_time));
+        let mut entry = test_entry();
+
+        let expected_time =
+            DateTime::<Local>::from(SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_597_921))
+                .format("%a %b %e %H:%M:%S %Y");
+        assert_eq!(
+            formatter.format(&entry),
+            format!("[{}] Test message", expected_time)
+        );
+
+        entry.timestamp_from_system_start = None;
+        assert_eq!(formatter.format(&entry), "Test message");
+    }
+
+    #[test]
+    fn test_iso8601() {
+        // Wed Jun  5 14:00:00 2024 UTC
+        let boot_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_596_000);
+        let mut formatter = EntryFormatter::new().with_timestamp(TimeFormat::Iso8601(boot_time));
+        let mut entry = test_entry();
+
+        entry.timestamp_from_system_start = Some(Duration::from_micros(1_921_123_456));
+        assert_eq!(
+            formatter.format(&entry),
+            "2024-06-05T14:32:01.123456Z Test message"
+        );
+
+        // Precision stops at microseconds
+        entry.timestamp_from_system_start = Some(Duration::from_nanos(1_921_000_001_999));
+        assert_eq!(
+            formatter.format(&entry),
+            "2024-06-05T14:32:01.000001Z Test message"
+        );
+
+        entry.timestamp_from_system_start = None;
+        assert_eq!(formatter.format(&entry), "Test message");
+    }
+
+    #[test]
+    fn test_reltime() {
+        let boot_time: SystemTime = Local
+            .with_ymd_and_hms(2024, 6, 5, 23, 59, 0)
+            .unwrap()
+            .into();
+        let mut formatter = EntryFormatter::new().with_timestamp(TimeFormat::Reltime(boot_time));
+        let mut entry = test_entry();
+
+        let formatted: Vec<String> = [0, 30, 59, 90, 3600]
+            .iter()
+            .map(|secs| {
+                entry.timestamp_from_system_start = Some(Duration::from_secs(*secs));
+                formatter.format(&entry)
+            })
+            .collect();
+
+        assert_eq!(
+            formatted,
+            vec![
+                "[Jun 5 23:59] Test message",
+                "[23:59:30] Test message",
+                "[23:59:59] Test message",
+                "[Jun 6 00:00] Test message",
+                "[00:59:00] Test message",
+            ]
+        );
+
+        // Entries without a timestamp don't affect dates
+        entry.timestamp_from_system_start = None;
+        assert_eq!(formatter.format(&entry), "Test message");
+        entry.timestamp_from_system_start = Some(Duration::from_secs(3660));
+        assert_eq!(formatter.format(&entry), "[01:00:00] Test message");
+    }
+
+    #[test]
+    fn test_notime() {
+        let mut formatter = EntryFormatter::new().with_timestamp(TimeFormat::None);
+        assert_eq!(formatter.format(&test_entry()), "Test message");
+
+        let mut formatter = formatter.with_decode(true).with_sequence(true);
+        assert_eq!(
+            formatter.format(&test_entry()),
+            "kern  :warn  : [seq 42] Test message"
+        );
+    }
+
+    #[test]
+    fn test_all_together() {
+        let mut formatter = EntryFormatter::new()
+            .with_decode(true)
+            .with_sequence(true)
+            .with_delta(true);
+        assert_eq!(
+            formatter.format(&test_entry()),
+            "kern  :warn  : [seq 42] <+    0.000000> [     1921.500000] Test message"
+        );
+    }
+
+    #[test]
+    fn test_force_prefix() {
+        let mut entry = test_entry();
+        entry.message = "first line\nsecond line\nthird line".to_owned();
+
+        let mut formatter = EntryFormatter::new().with_decode(true).with_delta(true);
+        assert_eq!(
+            formatter.format(&entry),
+            "kern  :warn  : <+    0.000000> [     1921.500000] first line\nsecond line\nthird line"
+        );
+
+        // Every line carries the same prefix, delta included
+        let mut formatter = formatter.with_force_prefix(true);
+        assert_eq!(
+            formatter.format(&entry),
+            "kern  :warn  : <+    0.000000> [     1921.500000] first line
+kern  :warn  : <+    0.000000> [     1921.500000] second line
+kern  :warn  : <+    0.000000> [     1921.500000] third line"
+        );
+
+        // A single line is formatted as without
+        assert_eq!(
+            EntryFormatter::new()
+                .with_force_prefix(true)
+                .format(&test_entry()),
+            EntryFormatter::new().format(&test_entry())
+        );
+    }
+
+    #[test]
+    fn test_delta() {
+        let mut formatter = EntryFormatter::new().with_delta(true);
+
+        let timestamps = [
+            Some(Duration::from_micros(1_000_000)),
+            Some(Duration::from_micros(1_000_123)),
+            None,
+            Some(Duration::from_micros(1_500_123)),
+            // earlier than the previous one
+            Some(Duration::from_micros(1_400_000)),
+            Some(Duration::from_micros(1_400_001)),
+        ];
+
+        let deltas: Vec<Option<Duration>> = timestamps
+            .iter()
+            .map(|ts| formatter.delta_since_previous(*ts))
+            .collect();
+
+        assert_eq!(
+            deltas,
+            vec![
+                Some(Duration::ZERO),
+                Some(Duration::from_micros(123)),
+                None,
+                Some(Duration::from_micros(500_000)),
+                Some(Duration::ZERO),
+                Some(Duration::from_micros(1)),
+            ]
+        );
+
+        let mut entry = test_entry();
+        entry.timestamp_from_system_start = Some(Duration::from_micros(1_400_124));
+        assert_eq!(
+            formatter.format(&entry),
+            "<+    0.000123> [        1.400124] Test message"
+        );
+
+        entry.timestamp_from_system_start = None;
+        assert_eq!(formatter.format(&entry), "Test message");
+    }
+}
@@ -0,0 +1,129 @@
+use crate::entry::Entry;
+use crate::error::RMesgError;
+use core::future::Future;
+use core::pin::Pin;
+use futures::stream::Stream;
+use futures::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+/// What a stream with heartbeats (see `Heartbeat`) yields: entries, and marks of idleness
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamItem {
+    Entry(Entry),
+
+    /// No entry was logged for the heartbeat interval
+    Idle,
+}
+
+/// Wraps a stream of entries, and yields `StreamItem::Idle` each time no entry comes for
+/// the heartbeat interval, e.g. for a UI to show the stream is alive. Without an interval,
+/// only entries are yielded.
+pub struct Heartbeat<S> {
+    inner: S,
+    interval: Option<Duration>,
+
+    // Fires an interval after the last item. Made on the first poll, inside the runtime.
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> Heartbeat<S> {
+    pub fn new(inner: S, interval: Option<Duration>) -> Self {
+        Self {
+            inner,
+            interval,
+            sleep: None,
+        }
+    }
+}
+
+impl<S: Stream<Item = Result<Entry, RMesgError>> + Unpin> Stream for Heartbeat<S> {
+    type Item = Result<StreamItem, RMesgError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if let Poll::Ready(next) = Pin::new(&mut this.inner).poll_next(cx) {
+            if let Some(sleep) = this.sleep.as_mut() {
+                if let Some(interval) = this.interval {
+                    sleep.as_mut().reset(Instant::now() + interval);
+                }
+            }
+            return Poll::Ready(next.map(|next| next.map(StreamItem::Entry)));
+        }
+
+        let interval = match this.interval {
+            Some(interval) => interval,
+            None => return Poll::Pending,
+        };
+        let sleep = this
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(interval)));
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                sleep.as_mut().reset(Instant::now() + interval);
+                Poll::Ready(Some(Ok(StreamItem::Idle)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_util::entry;
+    use futures::stream::{self, StreamExt};
+
+    #[tokio::test]
+    async fn test_idle_without_entries() {
+        let items: Vec<_> = Heartbeat::new(
+            stream::pending::<Result<Entry, RMesgError>>(),
+            Some(Duration::from_millis(10)),
+        )
+        .take(3)
+        .collect()
+        .await;
+
+        assert_eq!(items.len(), 3);
+        assert!(items
+            .iter()
+            .all(|item| matches!(item, Ok(StreamItem::Idle))));
+    }
+
+    #[tokio::test]
+    async fn test_entries_then_idle() {
+        let inner =
+            stream::iter(vec![Ok(entry("first")), Ok(entry("second"))]).chain(stream::pending());
+        let items: Vec<_> = Heartbeat::new(inner, Some(Duration::from_millis(10)))
+            .take(3)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(
+            items,
+            vec![
+                StreamItem::Entry(entry("first")),
+                StreamItem::Entry(entry("second")),
+                StreamItem::Idle,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_heartbeat() {
+        let inner = stream::iter(vec![Ok(entry("first")), Ok(entry("second"))]);
+        let items: Vec<_> = Heartbeat::new(inner, None).collect().await;
+        assert_eq!(items.len(), 2);
+
+        // Without an interval, a stream without entries stays silent
+        let mut silent = Heartbeat::new(stream::pending::<Result<Entry, RMesgError>>(), None);
+        let timed_out = tokio::time::timeout(Duration::from_millis(30), silent.next()).await;
+        assert!(timed_out.is_err());
+    }
+}
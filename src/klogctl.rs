@@ -1,5 +1,5 @@
 use crate::common;
-use crate::entry::{Entry, EntryParsingError};
+use crate::entry::{Entry, EntryParsingError, LogLevel, ParseMode, ParseOptions};
 /// This crate provides a klogctl interface from Rust.
 /// klogctl is a Linux syscall that allows reading the Linux Kernel Log buffer.
 /// https://elinux.org/Debugging_by_printing
@@ -10,15 +10,21 @@ use crate::entry::{Entry, EntryParsingError};
 /// This allows Rust programs to consume dmesg-like output programmatically.
 ///
 use crate::error::RMesgError;
+use crate::filter::{EntryFilter, MessageSource};
 
 use errno::errno;
-use lazy_static::lazy_static;
-use regex::Regex;
 use std::convert::TryFrom;
 use std::fs;
+use std::ops::Range;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, SystemTime};
 use strum_macros::Display;
 
+#[cfg(any(feature = "sync", feature = "async"))]
+use std::sync::atomic::Ordering;
+
 #[cfg(feature = "async")]
 use core::future::Future;
 #[cfg(feature = "async")]
@@ -32,8 +38,6 @@ use tokio::time as tokiotime;
 
 #[cfg(feature = "sync")]
 use std::iter::Iterator;
-#[cfg(feature = "sync")]
-use std::thread;
 
 #[cfg(target_os = "linux")]
 // Can be removed once upstream libc supports it.
@@ -44,6 +48,8 @@ extern "C" {
 /// Mark this as unsafe to be compliant with the Linux/Libc variant
 /// Allows compilation on non-linux platforms which can be useful for
 /// portability of downstream tools without complex conditionals.
+/// It is never called: `safely_wrapped_klogctl` returns
+/// `RMesgError::NotSupportedOnThisPlatform` on these platforms first.
 #[cfg(not(target_os = "linux"))]
 unsafe fn klogctl(
     _syslog_type: libc::c_int,
@@ -70,23 +76,66 @@ pub enum KLogType {
     SyslogActionSizeBuffer,
 }
 
+impl KLogType {
+    /// Whether this action only reads, leaving the buffer and the console as they are
+    /// (SYSLOG_ACTION_READ_ALL, SYSLOG_ACTION_SIZE_UNREAD and SYSLOG_ACTION_SIZE_BUFFER).
+    /// SYSLOG_ACTION_READ consumes what it reads, so it isn't one of them.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            KLogType::SyslogActionReadAll
+                | KLogType::SyslogActionSizeUnread
+                | KLogType::SyslogActionSizeBuffer
+        )
+    }
+}
+
 pub type SignedInt = libc::c_int;
 
 /// The path under /proc where the parameter to set (or unset) logging a timestamp resides
 pub const SYS_MODULE_PRINTK_PARAMETERS_TIME: &str = "/sys/module/printk/parameters/time";
 
+/// The path under /proc where the console log level is, the first of the printk log levels
+pub const PROC_SYS_KERNEL_PRINTK: &str = "/proc/sys/kernel/printk";
+
+/// When a buffer too small to read the log into is grown, it grows to at least this size
+const MIN_GROWN_BUFFER_SIZE: usize = 4096;
+
 /// suggest polling every ten seconds
 pub const SUGGESTED_POLL_INTERVAL: std::time::Duration = Duration::from_secs(10);
 
-lazy_static! {
-    static ref RE_ENTRY_WITH_TIMESTAMP: Regex = Regex::new(
-        r"(?x)^
-        [[:space:]]*<(?P<faclevstr>[[:digit:]]*)>
-        [[:space:]]*([\[][[:space:]]*(?P<timestampstr>[[:digit:]]*\.[[:digit:]]*)[\]])?
-        (?P<message>.*)
-        $"
-    )
-    .unwrap();
+/// How much longer than the poll interval to sleep, so the check whether a poll is due passes
+const SLEEP_MARGIN: Duration = Duration::from_millis(200);
+
+/// When backing off (see `KLogEntries::with_max_poll_interval`), the poll interval grows
+/// to at least this, so backing off from a zero poll interval gets anywhere
+const MIN_BACKOFF_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a sleeping iterator checks whether it was asked to stop
+#[cfg(feature = "sync")]
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Where `KLogEntries` gets the current time from, and how its iterator sleeps until the
+/// next poll is due. `SystemClock` is the default; others can stand in for it, e.g. so tests
+/// control time rather than sleep.
+pub trait Clock: Send {
+    fn now(&self) -> SystemTime;
+
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system's wall clock, sleeping the calling thread
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
 }
 
 /// While reading the kernel log buffer is very useful in and of itself (especially when running the CLI),
@@ -109,20 +158,30 @@ lazy_static! {
 ///
 pub struct KLogEntries {
     clear: bool,
+    buffer_size: Option<usize>,
+    filter: EntryFilter,
     entries: Vec<Entry>,
     last_timestamp: Option<Duration>,
-    poll_interval: Duration,
+    poll_interval: Duration, // Grows from min_poll_interval up to max_poll_interval when backing off
+    min_poll_interval: Duration,
+    max_poll_interval: Duration,
     sleep_interval: Duration, // Just slightly longer than poll interval so the check passes
     last_poll: SystemTime,
+    clock: Box<dyn Clock>,
+    until_reached: bool, // Set once an entry past the filter's `until` is seen
+    stop: Option<Arc<AtomicBool>>,
+    parse_options: ParseOptions,
 
     #[cfg(feature = "async")]
     sleep_future: Option<Pin<Box<tokiotime::Sleep>>>,
 }
 
 impl KLogEntries {
-    /// Create a new KLogEntries with two specific options
+    /// Create a new KLogEntries with four specific options
     /// `clear: bool` specifies Whether or not to clear the buffer after every read.
+    /// `buffer_size: Option<usize>` specifies the size of the buffer to read into (see `klog_raw`).
     /// `poll_interval: Duration` specifies the interval after which to poll the buffer for new lines
+    /// `filter: EntryFilter` specifies which entries to yield. Others are skipped.
     ///
     /// Choice of these parameters affects how the iterator behaves significantly.
     ///
@@ -138,8 +197,15 @@ impl KLogEntries {
     /// This crate exports a constant `SUGGESTED_POLL_INTERVAL` which contains the recommended
     /// default when in doubt.
     ///
-    pub fn with_options(clear: bool, poll_interval: Duration) -> Result<KLogEntries, RMesgError> {
-        let sleep_interval = match poll_interval.checked_add(Duration::from_millis(200)) {
+    pub fn with_options(
+        clear: bool,
+        buffer_size: Option<usize>,
+        poll_interval: Duration,
+        filter: EntryFilter,
+    ) -> Result<KLogEntries, RMesgError> {
+        ensure_filter_supported(&filter)?;
+
+        let sleep_interval = match poll_interval.checked_add(SLEEP_MARGIN) {
             Some(si) => si,
             None => return Err(RMesgError::UnableToAddDurationToSystemTime),
         };
@@ -153,16 +219,167 @@ impl KLogEntries {
         Ok(KLogEntries {
             entries: Vec::new(),
             poll_interval,
+            min_poll_interval: poll_interval,
+            max_poll_interval: poll_interval,
+            parse_options: ParseOptions::default(),
             sleep_interval,
             last_poll,
+            clock: Box::new(SystemClock),
             clear,
+            buffer_size,
+            filter,
             last_timestamp: None,
+            until_reached: false,
+            stop: None,
 
             #[cfg(feature = "async")]
             sleep_future: None,
         })
     }
 
+    /// Create a new KLogEntries with the same options as `with_options`, that ends (returns
+    /// `None`) once `stop` is set, e.g. from another thread shutting down.
+    ///
+    /// While waiting for the next poll, the iterator checks `stop` every few milliseconds
+    /// rather than sleeping through the whole poll interval, so it ends promptly.
+    pub fn with_options_stoppable(
+        clear: bool,
+        buffer_size: Option<usize>,
+        poll_interval: Duration,
+        filter: EntryFilter,
+        stop: Arc<AtomicBool>,
+    ) -> Result<KLogEntries, RMesgError> {
+        let mut entries = Self::with_options(clear, buffer_size, poll_interval, filter)?;
+        entries.stop = Some(stop);
+        Ok(entries)
+    }
+
+    /// Backs off polling while the buffer is quiet, instead of polling at a fixed interval:
+    /// each poll that finds no new entries doubles the poll interval, up to `max_poll_interval`,
+    /// and a poll that finds new entries resets it to the `poll_interval` it was created with.
+    pub fn with_max_poll_interval(mut self, max_poll_interval: Duration) -> Self {
+        self.max_poll_interval = max_poll_interval.max(self.min_poll_interval);
+        self
+    }
+
+    /// Reads the time from, and sleeps with, `clock` instead of the `SystemClock`.
+    /// The first poll is still due right away.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Result<Self, RMesgError> {
+        self.last_poll = clock
+            .now()
+            .checked_sub(self.sleep_interval)
+            .ok_or(RMesgError::UnableToAddDurationToSystemTime)?;
+        self.clock = Box::new(clock);
+        Ok(self)
+    }
+
+    /// Sets all of the `ParseOptions` at once, rather than one at a time with the setters below
+    pub fn with_parse_options(mut self, parse_options: ParseOptions) -> Self {
+        self.parse_options = parse_options;
+        self
+    }
+
+    /// Sets `ParseOptions::raw_lines`
+    pub fn with_raw_lines(mut self, raw_lines: bool) -> Self {
+        self.parse_options.raw_lines = raw_lines;
+        self
+    }
+
+    /// Sets `ParseOptions::parse_mode`. With `ParseMode::Strict`, the poll reading a line
+    /// that can't be parsed fails, losing the lines read with it.
+    pub fn with_parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_options.parse_mode = parse_mode;
+        self
+    }
+
+    /// Sets `ParseOptions::max_message_len`
+    pub fn with_max_message_len(mut self, max_message_len: Option<usize>) -> Self {
+        self.parse_options.max_message_len = max_message_len;
+        self
+    }
+
+    /// Sets `ParseOptions::escape_messages`
+    pub fn with_escape_messages(mut self, escape_messages: bool) -> Self {
+        self.parse_options.escape_messages = escape_messages;
+        self
+    }
+
+    // Called after each poll, with whether it found any new entries
+    fn adjust_poll_interval(&mut self, found_new_entries: bool) {
+        self.poll_interval = match found_new_entries {
+            true => self.min_poll_interval,
+            false => self
+                .poll_interval
+                .saturating_mul(2)
+                .max(MIN_BACKOFF_POLL_INTERVAL)
+                .min(self.max_poll_interval),
+        };
+        self.sleep_interval = self.poll_interval.saturating_add(SLEEP_MARGIN);
+    }
+
+    #[cfg(any(feature = "sync", feature = "async"))]
+    fn stopped(&self) -> bool {
+        match &self.stop {
+            Some(stop) => stop.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    // Sleeps for `sleep_interval`, or until asked to stop
+    #[cfg(feature = "sync")]
+    fn sleep_unless_stopped(&self) {
+        if self.stop.is_none() {
+            self.clock.sleep(self.sleep_interval);
+            return;
+        }
+
+        let start = self.clock.now();
+        while !self.stopped() {
+            let slept = self
+                .clock
+                .now()
+                .duration_since(start)
+                .unwrap_or(self.sleep_interval);
+            match self.sleep_interval.checked_sub(slept) {
+                Some(remaining) if !remaining.is_zero() => {
+                    self.clock.sleep(remaining.min(STOP_CHECK_INTERVAL))
+                }
+                _ => return,
+            }
+        }
+    }
+
+    // The time since the last poll, by the clock
+    fn since_last_poll(&self) -> Result<Duration, RMesgError> {
+        self.clock
+            .now()
+            .duration_since(self.last_poll)
+            .map_err(RMesgError::UnableToObtainElapsedTime)
+    }
+
+    /// Returns the next entry without blocking, for callers driving polling from their
+    /// own event loop or scheduler.
+    ///
+    /// Returns `Ok(None)` immediately when no new entry is available, instead of sleeping
+    /// until one is. The log buffer is only polled when at least `poll_interval` elapsed
+    /// since the last poll, so construct with a zero `poll_interval` to poll on every call.
+    ///
+    /// Returns `Ok(None)` forever once the filter's `until` is reached.
+    pub fn try_next_entry(&mut self) -> Result<Option<Entry>, RMesgError> {
+        if self.entries.is_empty() && !self.until_reached {
+            let elapsed = self.since_last_poll()?;
+
+            if elapsed >= self.poll_interval {
+                self.poll()?;
+            }
+        }
+
+        match self.entries.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(self.entries.remove(0))),
+        }
+    }
+
     /// This method conducts the actual polling of the log buffer.
     ///
     /// It tracks the timestamp of the last line buffered, and only adds lines
@@ -173,39 +390,53 @@ impl KLogEntries {
     /// polling/iterating.
     ///
     fn poll(&mut self) -> Result<usize, RMesgError> {
-        self.last_poll = SystemTime::now();
-
-        let mut entries = klog(self.clear)?;
-        let mut entriesadded: usize = 0;
-        match self.last_timestamp {
-            None => {
-                entriesadded += entries.len();
-                self.entries.append(&mut entries);
+        self.last_poll = self.clock.now();
+
+        // Entries are parsed straight from the bytes read, one line at a time
+        let buffer = klog_bytes(self.clear, self.buffer_size)?;
+        let (records, _) = skip_truncated_record(&buffer);
+
+        let mut new_entries = Vec::new();
+        let mut newest_timestamp: Option<Duration> = None;
+        for line in lines_from_bytes(records) {
+            let entry = match entry_from_bytes_with_options(line, &self.parse_options)? {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let skip = match (self.last_timestamp, entry.timestamp_from_system_start) {
+                // take everything on the first poll
+                (None, _) => false,
+                // skip if entry timestamp is older than or equal to last timestamp
+                (Some(last_timestamp), Some(timestamp)) => timestamp <= last_timestamp,
+                // skip all without timestamp
+                (Some(_), None) => true,
+            };
+            if skip {
+                continue;
             }
-            Some(last_timestamp) => {
-                while !entries.is_empty() {
-                    let entry = entries.remove(0);
-                    let skip = match entry.timestamp_from_system_start {
-                        // skip if entry timestamp is older than or equal to last timestamp
-                        Some(timestamp) => timestamp <= last_timestamp,
-                        // skip all without timestamp
-                        None => true,
-                    };
-
-                    if !skip {
-                        self.entries.push(entry);
-                        entriesadded += 1;
-                    }
-                }
+
+            if self.filter.is_past_until(&entry) {
+                self.until_reached = true;
+                break;
             }
-        };
 
-        if let Some(entry) = self.entries.last() {
+            // Track timestamps of filtered out entries too, so they aren't re-read next poll
             if entry.timestamp_from_system_start.is_some() {
-                self.last_timestamp = entry.timestamp_from_system_start;
+                newest_timestamp = entry.timestamp_from_system_start;
             }
+
+            if self.filter.accepts(&entry) {
+                new_entries.push(entry);
+            }
+        }
+
+        if newest_timestamp.is_some() {
+            self.last_timestamp = newest_timestamp;
         }
+        self.adjust_poll_interval(newest_timestamp.is_some());
 
+        let entriesadded = new_entries.len();
+        self.entries.append(&mut new_entries);
         Ok(entriesadded)
     }
 }
@@ -219,10 +450,18 @@ impl Iterator for KLogEntries {
     /// NOT a thread-safe method either. It is suggested this method be always
     /// blocked on to ensure no messages are missed.
     fn next(&mut self) -> Option<Self::Item> {
+        if self.stopped() {
+            return None;
+        }
+
         while self.entries.is_empty() {
-            let elapsed = match self.last_poll.elapsed() {
+            if self.until_reached || self.stopped() {
+                return None;
+            }
+
+            let elapsed = match self.since_last_poll() {
                 Ok(duration) => duration,
-                Err(e) => return Some(Err(RMesgError::UnableToObtainElapsedTime(e))),
+                Err(e) => return Some(Err(e)),
             };
 
             // Poll once if entering next and time since last poll
@@ -234,7 +473,7 @@ impl Iterator for KLogEntries {
                     return Some(Err(e));
                 }
             } else {
-                thread::sleep(self.sleep_interval);
+                self.sleep_unless_stopped();
             }
         }
 
@@ -261,11 +500,19 @@ impl Stream for KLogEntries {
             }
         }
 
+        if self.stopped() {
+            return Poll::Ready(None);
+        }
+
         // entries empty?
         while self.entries.is_empty() {
-            let elapsed = match self.last_poll.elapsed() {
+            if self.until_reached {
+                return Poll::Ready(None);
+            }
+
+            let elapsed = match self.since_last_poll() {
                 Ok(duration) => duration,
-                Err(e) => return Poll::Ready(Some(Err(RMesgError::UnableToObtainElapsedTime(e)))),
+                Err(e) => return Poll::Ready(Some(Err(e))),
             };
 
             // Did enough time pass since last poll? If so try to poll
@@ -292,28 +539,72 @@ impl Stream for KLogEntries {
 /// only provides one bool parameter which indicates whether the buffer is to be cleared
 /// or not, after its contents have been read.
 ///
+/// `buffer_size` is the size of the buffer the log is read into. When `None`, the size
+/// of the kernel's log buffer is queried (SYSLOG_ACTION_SIZE_BUFFER), which always fits.
+/// The kernel only copies whole lines that fit, so a smaller buffer doesn't truncate lines,
+/// but can leave out the oldest ones. To never return partial logs, a smaller buffer is
+/// grown (up to the kernel's size) until growing it no longer reads anything more.
+/// Since lines left out would be lost upon clearing, the kernel's size is used when
+/// `clear` is set.
+///
+/// When `clear` is set, the buffer is read and cleared in one call
+/// (SYSLOG_ACTION_READ_CLEAR) rather than read and then cleared, so entries logged in
+/// between can't be cleared without having been read. This is what keeps following
+/// with `clear` from dropping entries.
+///
 /// Note that this is a by-definition synchronous function. So it is available
 /// whether or not "async" feature is enabled
 ///
-pub fn klog_raw(clear: bool) -> Result<String, RMesgError> {
+pub fn klog_raw(clear: bool, buffer_size: Option<usize>) -> Result<String, RMesgError> {
+    Ok(String::from_utf8(klog_bytes(clear, buffer_size)?)?)
+}
+
+/// Reads the buffer like `klog_raw`, as bytes that are parsed line by line (see
+/// `entries_from_bytes`) rather than all checked into one String first.
+pub fn klog_bytes(clear: bool, buffer_size: Option<usize>) -> Result<Vec<u8>, RMesgError> {
+    klog_bytes_with(safely_wrapped_klogctl, clear, buffer_size)
+}
+
+// Takes the syscall wrapper as a parameter, so tests can check the actions without clearing
+fn klog_bytes_with<F>(
+    mut klogctl: F,
+    clear: bool,
+    buffer_size: Option<usize>,
+) -> Result<Vec<u8>, RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
     let mut dummy_buffer: Vec<u8> = vec![0; 0];
-    let kernel_buffer_size =
-        safely_wrapped_klogctl(KLogType::SyslogActionSizeBuffer, &mut dummy_buffer)?;
+    let kernel_buffer_size = klogctl(KLogType::SyslogActionSizeBuffer, &mut dummy_buffer)?;
 
     let klogtype = match clear {
         true => KLogType::SyslogActionReadClear,
         false => KLogType::SyslogActionReadAll,
     };
 
-    let mut real_buffer: Vec<u8> = vec![0; kernel_buffer_size];
-    let bytes_read = safely_wrapped_klogctl(klogtype, &mut real_buffer)?;
+    let mut buffer_size = match (clear, buffer_size) {
+        (false, Some(buffer_size)) => buffer_size.min(kernel_buffer_size),
+        _ => kernel_buffer_size,
+    };
 
-    //adjust buffer capacity to what was read
-    real_buffer.resize(bytes_read, 0);
-    let utf8_str = String::from_utf8(real_buffer)?;
+    let mut previous_bytes_read: Option<usize> = None;
+    loop {
+        let mut real_buffer: Vec<u8> = vec![0; buffer_size];
+        let bytes_read = klogctl(klogtype.clone(), &mut real_buffer)?;
 
-    // if incremental,
-    Ok(utf8_str)
+        // Done when the buffer can hold everything, or didn't need to be any bigger
+        if buffer_size >= kernel_buffer_size || previous_bytes_read == Some(bytes_read) {
+            //adjust buffer capacity to what was read
+            real_buffer.resize(bytes_read, 0);
+            return Ok(real_buffer);
+        }
+
+        previous_bytes_read = Some(bytes_read);
+        buffer_size = buffer_size
+            .saturating_mul(2)
+            .max(MIN_GROWN_BUFFER_SIZE)
+            .min(kernel_buffer_size);
+    }
 }
 
 /// This is the key safe function that makes the klogctl syslog call with parameters.
@@ -324,9 +615,224 @@ pub fn klog_raw(clear: bool) -> Result<String, RMesgError> {
 /// Note that this is a by-definition synchronous function. So it is available
 /// whether or not "async" feature is enabled
 ///
-pub fn klog(clear: bool) -> Result<Vec<Entry>, RMesgError> {
-    let all_lines = klog_raw(clear)?;
-    Ok(entries_from_lines(&all_lines)?)
+pub fn klog(clear: bool, buffer_size: Option<usize>) -> Result<Vec<Entry>, RMesgError> {
+    Ok(klog_read(clear, buffer_size)?.entries)
+}
+
+// Reads the buffer like `klog`, with the entries made as `parse_options` say
+pub(crate) fn klog_with_options(
+    clear: bool,
+    buffer_size: Option<usize>,
+    parse_options: &ParseOptions,
+) -> Result<Vec<Entry>, RMesgError> {
+    let buffer = klog_bytes(clear, buffer_size)?;
+    let (records, _) = skip_truncated_record(&buffer);
+    lines_from_bytes(records)
+        .filter_map(|line| entry_from_bytes_with_options(line, parse_options).transpose())
+        .collect()
+}
+
+/// What was read from the kernel log buffer by `klog_read`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadResult {
+    pub entries: Vec<Entry>,
+
+    /// How many bytes were read from the buffer
+    pub bytes_read: usize,
+
+    /// Whether the buffer began partway through a record (e.g. because it wrapped). That
+    /// partial record isn't in `entries`.
+    pub truncated: bool,
+}
+
+/// Reads the kernel log buffer like `klog`, along with how many bytes were read and
+/// whether the oldest record was truncated.
+pub fn klog_read(clear: bool, buffer_size: Option<usize>) -> Result<ReadResult, RMesgError> {
+    let buffer = klog_bytes(clear, buffer_size)?;
+    Ok(read_result_from_bytes(&buffer)?)
+}
+
+/// What clearing the kernel log buffer cleared
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClearReport {
+    /// How many entries were in the buffer when it was cleared
+    pub entries_cleared: usize,
+}
+
+/// Clears the kernel log buffer (like `dmesg -C`), and reports how many entries it held.
+///
+/// The entries are read and cleared in one call (SYSLOG_ACTION_READ_CLEAR), so none can be
+/// logged in between and go uncounted. The count is of lines read, so it is only as precise
+/// as the kernel's formatting of records into lines.
+///
+/// Clearing takes CAP_SYSLOG (see `safely_wrapped_klogctl`).
+pub fn klog_clear() -> Result<ClearReport, RMesgError> {
+    klog_clear_with(safely_wrapped_klogctl)
+}
+
+// Takes the syscall wrapper as a parameter, so tests can check the actions without clearing
+fn klog_clear_with<F>(mut klogctl: F) -> Result<ClearReport, RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
+    let mut dummy_buffer: Vec<u8> = vec![0; 0];
+    let kernel_buffer_size = klogctl(KLogType::SyslogActionSizeBuffer, &mut dummy_buffer)?;
+
+    let mut buffer: Vec<u8> = vec![0; kernel_buffer_size];
+    let bytes_read = klogctl(KLogType::SyslogActionReadClear, &mut buffer)?;
+    buffer.truncate(bytes_read);
+
+    Ok(ClearReport {
+        entries_cleared: count_lines(&buffer),
+    })
+}
+
+/// Clears the kernel log buffer like `klog_clear`, but returns the entries the filter accepts,
+/// so the important ones (e.g. warnings and worse) aren't lost along with the noise. The
+/// kernel can't clear selectively, so every entry is cleared either way.
+///
+/// Like `klog_clear`, the entries are read and cleared in one call, so none are lost in
+/// between, and it takes CAP_SYSLOG.
+pub fn klog_clear_and_return_filtered(filter: &EntryFilter) -> Result<Vec<Entry>, RMesgError> {
+    klog_clear_and_return_filtered_with(safely_wrapped_klogctl, filter)
+}
+
+// Takes the syscall wrapper as a parameter, so tests can check the actions without clearing
+fn klog_clear_and_return_filtered_with<F>(
+    klogctl: F,
+    filter: &EntryFilter,
+) -> Result<Vec<Entry>, RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
+    ensure_filter_supported(filter)?;
+
+    let buffer = klog_bytes_with(klogctl, true, None)?;
+    let mut entries = read_result_from_bytes(&buffer)?.entries;
+    entries.retain(|e| filter.accepts(e));
+    Ok(entries)
+}
+
+/// Reads the kernel log buffer like `klog_read`, guaranteed never to clear it: only
+/// SYSLOG_ACTION_SIZE_BUFFER and SYSLOG_ACTION_READ_ALL are issued, through
+/// `read_only_klogctl`, which refuses any action that isn't read-only.
+///
+/// The functions that can clear the buffer are `klog_clear` and
+/// `klog_clear_and_return_filtered`, and `klog_raw`, `klog_bytes`, `klog`, `klog_read` and
+/// `KLogEntries` when `clear` is set. Everything else only reads it.
+pub fn klog_read_only(buffer_size: Option<usize>) -> Result<ReadResult, RMesgError> {
+    klog_read_only_with(read_only(safely_wrapped_klogctl), buffer_size)
+}
+
+// Takes the syscall wrapper as a parameter, so tests can check the actions issued
+fn klog_read_only_with<F>(klogctl: F, buffer_size: Option<usize>) -> Result<ReadResult, RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
+    let buffer = klog_bytes_with(klogctl, false, buffer_size)?;
+    Ok(read_result_from_bytes(&buffer)?)
+}
+
+/// Makes the klogctl call like `safely_wrapped_klogctl`, but refuses actions that aren't
+/// read-only (see `KLogType::is_read_only`) with `RMesgError::UnsupportedOperation`, without
+/// making the call.
+pub fn read_only_klogctl(klogtype: KLogType, buf_u8: &mut [u8]) -> Result<usize, RMesgError> {
+    read_only(safely_wrapped_klogctl)(klogtype, buf_u8)
+}
+
+// Wraps the syscall wrapper, so tests can check refused actions never reach it
+fn read_only<F>(mut klogctl: F) -> impl FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
+    move |klogtype, buf_u8| match klogtype.is_read_only() {
+        true => klogctl(klogtype, buf_u8),
+        false => Err(RMesgError::UnsupportedOperation(format!(
+            "klogctl ({}) isn't read-only, which reading the buffer read-only doesn't allow",
+            klogtype
+        ))),
+    }
+}
+
+/// Checks that the kernel log buffer can be read through klogctl, without reading it: the
+/// buffer size is asked for (SYSLOG_ACTION_SIZE_BUFFER), which takes the same privileges:
+/// CAP_SYSLOG when `dmesg_restrict` is set, and none otherwise (see `safely_wrapped_klogctl`).
+pub fn klog_check_access() -> Result<(), RMesgError> {
+    klog_check_access_with(safely_wrapped_klogctl)
+}
+
+// Takes the syscall wrapper as a parameter, so tests can simulate failures
+fn klog_check_access_with<F>(mut klogctl: F) -> Result<(), RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
+    let mut dummy_buffer: Vec<u8> = vec![0; 0];
+    klogctl(KLogType::SyslogActionSizeBuffer, &mut dummy_buffer)?;
+    Ok(())
+}
+
+/// Checks that the kernel log buffer can be cleared through klogctl, without clearing it:
+/// the size of what's unread is asked for (SYSLOG_ACTION_SIZE_UNREAD), which like clearing
+/// always takes CAP_SYSLOG, even when reading doesn't (see `klog_check_access`).
+pub fn klog_check_clear_access() -> Result<(), RMesgError> {
+    klog_check_clear_access_with(safely_wrapped_klogctl)
+}
+
+// Takes the syscall wrapper as a parameter, so tests can simulate failures
+fn klog_check_clear_access_with<F>(mut klogctl: F) -> Result<(), RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
+    let mut dummy_buffer: Vec<u8> = vec![0; 0];
+    klogctl(KLogType::SyslogActionSizeUnread, &mut dummy_buffer)?;
+    Ok(())
+}
+
+/// Counts the records in the kernel log buffer without parsing them, from the lines read.
+///
+/// Like `klog_clear`'s count, it's only as precise as the kernel's formatting of records
+/// into lines: a record whose message has line breaks counts once per line. A record
+/// truncated at the start of the buffer isn't counted.
+pub fn klog_record_count() -> Result<usize, RMesgError> {
+    klog_record_count_with(safely_wrapped_klogctl)
+}
+
+// Takes the syscall wrapper as a parameter, so tests can count a mocked buffer
+fn klog_record_count_with<F>(klogctl: F) -> Result<usize, RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
+    let buffer = klog_bytes_with(klogctl, false, None)?;
+    let (records, _) = skip_truncated_record(&buffer);
+    Ok(count_lines(records))
+}
+
+fn count_lines(buffer: &[u8]) -> usize {
+    buffer
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .count()
+}
+
+/// Checks that this backend can honor the filter.
+///
+/// Userspace messages can't reliably be told apart from kernel messages through klogctl,
+/// and entries read through it aren't numbered, so filtering for only userspace messages
+/// or by sequence number is refused rather than silently returning the wrong entries.
+pub fn ensure_filter_supported(filter: &EntryFilter) -> Result<(), RMesgError> {
+    if filter.min_seq.is_some() || filter.max_seq.is_some() {
+        return Err(RMesgError::UnsupportedFilter(
+            "klogctl entries have no sequence numbers. Use the /dev/kmsg backend instead."
+                .to_owned(),
+        ));
+    }
+
+    match filter.source {
+        Some(MessageSource::Userspace) => Err(RMesgError::UnsupportedFilter(
+            "klogctl can't tell userspace messages apart from kernel messages. Use the /dev/kmsg backend instead.".to_owned(),
+        )),
+        _ => Ok(()),
+    }
 }
 
 /// This function checks whether or not timestamps are enabled in the Linux Kernel log entries.
@@ -348,6 +854,75 @@ pub fn klog_timestamps_enable(desired: bool) -> Result<(), RMesgError> {
     )?)
 }
 
+/// Returns the console log level: the least severe level of messages the kernel prints to
+/// the console (like `dmesg -n` sets). klogctl can only set it, so it is read from
+/// /proc/sys/kernel/printk.
+pub fn klog_console_level() -> Result<LogLevel, RMesgError> {
+    console_level_from_printk(&fs::read_to_string(PROC_SYS_KERNEL_PRINTK)?)
+}
+
+// The kernel prints messages more severe than (below) the first of the printk levels
+fn console_level_from_printk(printk: &str) -> Result<LogLevel, RMesgError> {
+    let console_loglevel = printk
+        .split_whitespace()
+        .next()
+        .and_then(|level| level.parse::<u8>().ok())
+        .ok_or_else(|| {
+            RMesgError::InternalError(format!(
+                "Unable to parse the console log level from {}: {:?}",
+                PROC_SYS_KERNEL_PRINTK, printk
+            ))
+        })?;
+
+    match console_loglevel.checked_sub(1) {
+        // Above debug, everything is printed
+        Some(severity) => Ok(LogLevel::from_severity(severity).unwrap_or(LogLevel::Debug)),
+        None => Err(RMesgError::IntegerOutOfBound(format!(
+            "Console log level {} in {} prints no messages at all",
+            console_loglevel, PROC_SYS_KERNEL_PRINTK
+        ))),
+    }
+}
+
+/// Sets the console log level (like `dmesg -n`), so the kernel prints messages at this level
+/// and more severe to the console (SYSLOG_ACTION_CONSOLE_LEVEL). Takes CAP_SYSLOG.
+pub fn klog_console_level_set(level: LogLevel) -> Result<(), RMesgError> {
+    klog_console_level_set_with(safely_wrapped_klogctl, level)
+}
+
+// Takes the syscall wrapper as a parameter, so tests can check the actions without setting
+fn klog_console_level_set_with<F>(mut klogctl: F, level: LogLevel) -> Result<(), RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
+    // The kernel only reads the level, from the length, and prints messages below it
+    let mut level_buffer: Vec<u8> = vec![0; usize::from(level.as_severity()) + 1];
+    klogctl(KLogType::SyslogActionConsoleLevel, &mut level_buffer)?;
+    Ok(())
+}
+
+/// Enables or disables printing messages to the console (like `dmesg -E` and `dmesg -D`),
+/// through SYSLOG_ACTION_CONSOLE_ON and SYSLOG_ACTION_CONSOLE_OFF. Disabling remembers the
+/// console log level, and enabling restores it. Like setting the level, takes CAP_SYSLOG.
+pub fn klog_console_output_set(enabled: bool) -> Result<(), RMesgError> {
+    klog_console_output_set_with(safely_wrapped_klogctl, enabled)
+}
+
+// Takes the syscall wrapper as a parameter, so tests can check the actions without setting
+fn klog_console_output_set_with<F>(mut klogctl: F, enabled: bool) -> Result<(), RMesgError>
+where
+    F: FnMut(KLogType, &mut [u8]) -> Result<usize, RMesgError>,
+{
+    let klogtype = match enabled {
+        true => KLogType::SyslogActionConsoleOn,
+        false => KLogType::SyslogActionConsoleOff,
+    };
+
+    let mut dummy_buffer: Vec<u8> = vec![0; 0];
+    klogctl(klogtype, &mut dummy_buffer)?;
+    Ok(())
+}
+
 // Message spec: https://github.com/torvalds/linux/blob/master/Documentation/ABI/testing/dev-kmsg
 // Parses a kernel log line that looks like this (we ignore lines wtihout the timestamp):
 // <5>a.out[4054]: segfault at 7ffd5503d358 ip 00007ffd5503d358 sp 00007ffd5503d258 error 15
@@ -360,44 +935,238 @@ pub fn entries_from_lines(all_lines: &str) -> Result<Vec<Entry>, EntryParsingErr
     entry_results
 }
 
-pub fn entry_from_line(line: &str) -> Result<Entry, EntryParsingError> {
-    if let Some(klogparts) = RE_ENTRY_WITH_TIMESTAMP.captures(line) {
-        let (facility, level) = match klogparts.name("faclevstr") {
-            Some(faclevstr) => common::parse_favlecstr(faclevstr.as_str(), line)?,
-            None => (None, None),
-        };
+/// Parses lines read through klogctl like `entries_from_lines`, except that a first line
+/// without a priority prefix (`<6>`) is the tail of a truncated record: it is dropped
+/// rather than parsed into a garbled entry, and the result marked `truncated`.
+pub fn read_result_from_lines(all_lines: &str) -> Result<ReadResult, EntryParsingError> {
+    read_result_from_bytes(all_lines.as_bytes())
+}
+
+/// Like `read_result_from_lines`, straight from the bytes read (see `entries_from_bytes`)
+pub fn read_result_from_bytes(buffer: &[u8]) -> Result<ReadResult, EntryParsingError> {
+    let (records, truncated) = skip_truncated_record(buffer);
+
+    Ok(ReadResult {
+        entries: entries_from_bytes(records).collect::<Result<_, _>>()?,
+        bytes_read: buffer.len(),
+        truncated,
+    })
+}
 
-        let timestamp_from_system_start = match klogparts.name("timestampstr") {
-            Some(timestampstr) => common::parse_timestamp_secs(timestampstr.as_str(), line)?,
-            None => None,
+// Skips the first line when it has no priority prefix, since it's then the tail of a
+// truncated record. Returns what's left, and whether anything was skipped.
+fn skip_truncated_record(buffer: &[u8]) -> (&[u8], bool) {
+    let first_line_end = buffer
+        .iter()
+        .position(|b| *b == b'\n')
+        .unwrap_or(buffer.len());
+
+    match priority_prefix(&buffer[..first_line_end]) {
+        Some((digits, _)) if !digits.is_empty() => (buffer, false),
+        _ if buffer.is_empty() => (buffer, false),
+        _ => (&buffer[(first_line_end + 1).min(buffer.len())..], true),
+    }
+}
+
+/// Parses lines read through klogctl lazily, one at a time, straight from the bytes read.
+/// Entries are the same as `entries_from_lines` parses, without the whole buffer having
+/// to be a String or the entries collected first.
+pub fn entries_from_bytes(
+    buffer: &[u8],
+) -> impl Iterator<Item = Result<Entry, EntryParsingError>> + '_ {
+    lines_from_bytes(buffer).map(|line| entry_from_bytes(line, &ParseOptions::default()))
+}
+
+// Splits the bytes read into lines without copying them
+fn lines_from_bytes(buffer: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = buffer;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+
+        // Lines end like `str::lines` ends them: at \n or \r\n
+        let line = match rest.iter().position(|b| *b == b'\n') {
+            Some(end) => {
+                let line = &rest[..end];
+                rest = &rest[end + 1..];
+                line.strip_suffix(b"\r").unwrap_or(line)
+            }
+            None => std::mem::take(&mut rest),
         };
 
-        let message = klogparts["message"].to_owned();
+        Some(line)
+    })
+}
 
-        Ok(Entry {
-            facility,
-            level,
-            sequence_num: None,
-            timestamp_from_system_start,
-            message,
-        })
-    } else {
-        Ok(Entry {
-            facility: None,
-            level: None,
-            sequence_num: None,
-            timestamp_from_system_start: None,
-            message: line.to_owned(),
-        })
+// Parses a line as `parse_options` say, handling lines that can't be parsed as its
+// `parse_mode` says, with None for those to skip
+fn entry_from_bytes_with_options(
+    line: &[u8],
+    parse_options: &ParseOptions,
+) -> Result<Option<Entry>, RMesgError> {
+    let mut entry = match (
+        entry_from_bytes(line, parse_options),
+        parse_options.parse_mode,
+    ) {
+        (Ok(entry), _) => entry,
+        (Err(e), ParseMode::Strict) => return Err(e.into()),
+        (Err(_), ParseMode::Lenient) => return Ok(None),
+        (Err(_), ParseMode::Lossy) => lossy_entry_from_bytes(line, parse_options),
+    };
+    entry.raw_line = parse_options.raw_line(&String::from_utf8_lossy(line));
+    Ok(Some(entry))
+}
+
+// What can be made out of a line that doesn't parse: the line parsed with invalid UTF-8
+// replaced, or when it still doesn't parse (e.g. a priority out of range), the whole line as
+// the message
+fn lossy_entry_from_bytes(line: &[u8], parse_options: &ParseOptions) -> Entry {
+    let line = String::from_utf8_lossy(line);
+    entry_from_bytes(line.as_bytes(), parse_options).unwrap_or_else(|_| Entry {
+        message: parse_options.message(&line),
+        ..Default::default()
+    })
+}
+
+// Parses a line: an optional priority prefix (like `<6>`), then an optional timestamp (like
+// `[    5.140900]`), then the message. A line without a priority prefix is all message.
+fn entry_from_bytes(line: &[u8], parse_options: &ParseOptions) -> Result<Entry, EntryParsingError> {
+    let line = std::str::from_utf8(line)
+        .map_err(|e| EntryParsingError::Generic(format!("Line is not valid UTF-8: {}", e)))?;
+    let bytes = line.as_bytes();
+
+    let (faclev, prefix_end) = match priority_prefix(bytes) {
+        Some(prefix) => prefix,
+        None => {
+            return Ok(Entry {
+                message: parse_options.message(line),
+                ..Default::default()
+            })
+        }
+    };
+    let (facility, level) = common::parse_favlecstr(&line[faclev], line)?;
+
+    let timestamp_start = skip_while(bytes, prefix_end, is_space);
+    let (timestamp_from_system_start, message_start) = match timestamp(bytes, timestamp_start) {
+        Some((timestampstr, timestamp_end)) => (
+            common::parse_timestamp_secs(&line[timestampstr], line)?,
+            timestamp_end,
+        ),
+        None => (None, timestamp_start),
+    };
+
+    Ok(Entry {
+        facility,
+        level,
+        timestamp_from_system_start,
+        message: parse_options.message(&line[message_start..]),
+        ..Default::default()
+    })
+}
+
+// The priority prefix (like `<6>`) at the start of a line: where its digits are, and
+// where it ends
+fn priority_prefix(line: &[u8]) -> Option<(Range<usize>, usize)> {
+    let start = skip_while(line, 0, is_space);
+    if line.get(start) != Some(&b'<') {
+        return None;
+    }
+
+    let digits_end = skip_while(line, start + 1, is_digit);
+    match line.get(digits_end) {
+        Some(b'>') => Some((start + 1..digits_end, digits_end + 1)),
+        _ => None,
+    }
+}
+
+// The timestamp (like `[    5.140900]`) at `start`: where its digits are, and where it ends
+fn timestamp(line: &[u8], start: usize) -> Option<(Range<usize>, usize)> {
+    if line.get(start) != Some(&b'[') {
+        return None;
+    }
+
+    let digits_start = skip_while(line, start + 1, is_space);
+    let dot = skip_while(line, digits_start, is_digit);
+    if line.get(dot) != Some(&b'.') {
+        return None;
+    }
+
+    let digits_end = skip_while(line, dot + 1, is_digit);
+    match line.get(digits_end) {
+        Some(b']') => Some((digits_start..digits_end, digits_end + 1)),
+        _ => None,
     }
 }
 
+fn skip_while(line: &[u8], start: usize, predicate: fn(u8) -> bool) -> usize {
+    start
+        + line[start.min(line.len())..]
+            .iter()
+            .take_while(|b| predicate(**b))
+            .count()
+}
+
+// Same as the POSIX [[:space:]] and [[:digit:]] classes
+fn is_space(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | 0x0b | 0x0c | b'\r')
+}
+
+fn is_digit(b: u8) -> bool {
+    b.is_ascii_digit()
+}
+
+pub fn entry_from_line(line: &str) -> Result<Entry, EntryParsingError> {
+    entry_from_line_with(line, &ParseOptions::default())
+}
+
+// Parses a line like `entry_from_line`, with the message made as `parse_options` say
+pub(crate) fn entry_from_line_with(
+    line: &str,
+    parse_options: &ParseOptions,
+) -> Result<Entry, EntryParsingError> {
+    entry_from_bytes(line.as_bytes(), parse_options)
+}
+
 // ************************** Private
 
 /// Safely wraps the klogctl for Rusty types
 /// All higher-level functions are built over this function at the base.
 /// It prevents unsafe code from proliferating beyond this wrapper.
+///
+/// A failed call (a negative return) is always an error, never zero bytes: the errno is
+/// returned as `RMesgError::OperationNotPermitted` for EPERM, and as
+/// `RMesgError::InternalError` carrying the errno and the action code otherwise.
+///
+/// EPERM is what the kernel fails with when the process lacks the privilege an action
+/// takes. Reading the buffer (SYSLOG_ACTION_READ_ALL) and asking for its size
+/// (SYSLOG_ACTION_SIZE_BUFFER) take CAP_SYSLOG only when /proc/sys/kernel/dmesg_restrict
+/// is 1. Every other action, such as clearing the buffer or setting the console log
+/// level, always takes CAP_SYSLOG.
 pub fn safely_wrapped_klogctl(klogtype: KLogType, buf_u8: &mut [u8]) -> Result<usize, RMesgError> {
+    if cfg!(not(target_os = "linux")) {
+        return Err(RMesgError::NotSupportedOnThisPlatform(format!(
+            "klogctl ({}) is only available on Linux",
+            klogtype
+        )));
+    }
+
+    safely_wrapped_klogctl_with(
+        |klt, buf_cchar, buflen| unsafe { klogctl(klt, buf_cchar, buflen) },
+        klogtype,
+        buf_u8,
+    )
+}
+
+// Takes the libc call as a parameter, so tests can shim it with failing calls
+fn safely_wrapped_klogctl_with<F>(
+    syscall: F,
+    klogtype: KLogType,
+    buf_u8: &mut [u8],
+) -> Result<usize, RMesgError>
+where
+    F: FnOnce(libc::c_int, *mut libc::c_char, libc::c_int) -> libc::c_int,
+{
     // convert klogtype
     let klt = klogtype.clone() as libc::c_int;
 
@@ -418,7 +1187,7 @@ pub fn safely_wrapped_klogctl(klogtype: KLogType, buf_u8: &mut [u8]) -> Result<u
         }
     };
 
-    let response_cint: libc::c_int = unsafe { klogctl(klt, buf_cchar, buflen) };
+    let response_cint: libc::c_int = syscall(klt, buf_cchar, buflen);
 
     if response_cint < 0 {
         let err = errno();
@@ -427,8 +1196,8 @@ pub fn safely_wrapped_klogctl(klogtype: KLogType, buf_u8: &mut [u8]) -> Result<u
             return Err(RMesgError::OperationNotPermitted(format!("{}", klogtype)));
         } else {
             return Err(RMesgError::InternalError(format!(
-                "Request ({}) to klogctl failed. errno={}",
-                klogtype, err
+                "Request ({}, action {}) to klogctl failed with {}. errno={} ({})",
+                klogtype, klt, response_cint, err.0, err
             )));
         }
     }
@@ -452,6 +1221,45 @@ pub fn safely_wrapped_klogctl(klogtype: KLogType, buf_u8: &mut [u8]) -> Result<u
 #[cfg(all(test, target_os = "linux"))]
 mod test {
     use super::*;
+    use crate::entry::{LogFacility, LogLevel};
+    use std::sync::Mutex;
+
+    // A clock that only moves when advanced or slept with, recording the sleeps
+    #[derive(Clone)]
+    struct MockClock {
+        now: Arc<Mutex<SystemTime>>,
+        sleeps: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl MockClock {
+        fn new() -> Self {
+            Self {
+                now: Arc::new(Mutex::new(
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(1_000),
+                )),
+                sleeps: Arc::new(Mutex::new(vec![])),
+            }
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+
+        fn sleeps(&self) -> Vec<Duration> {
+            self.sleeps.lock().unwrap().clone()
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> SystemTime {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.sleeps.lock().unwrap().push(duration);
+            self.advance(duration);
+        }
+    }
 
     #[test]
     fn get_kernel_buffer_size() {
@@ -466,11 +1274,29 @@ mod test {
 
     #[test]
     fn test_klog() {
-        let entries = klog(false);
+        let entries = klog(false, None);
         assert!(entries.is_ok(), "Response from klog not Ok");
         assert!(!entries.unwrap().is_empty(), "Should have non-empty logs");
     }
 
+    #[test]
+    fn test_klog_buffer_size() {
+        let full = klog_raw(false, None).unwrap();
+        assert!(!full.is_empty(), "Should have non-empty logs");
+
+        // Too small to hold even one line, so it grows until everything fits
+        let grown = klog_raw(false, Some(16)).unwrap();
+        assert_eq!(grown.lines().next(), full.lines().next());
+        assert!(grown.len() >= full.len());
+
+        let grown = klog_raw(false, Some(0)).unwrap();
+        assert_eq!(grown.lines().next(), full.lines().next());
+
+        // Bigger than the kernel's buffer is capped to it
+        let capped = klog_raw(false, Some(usize::MAX)).unwrap();
+        assert_eq!(capped.lines().next(), full.lines().next());
+    }
+
     #[cfg(feature = "sync")]
     #[test]
     fn test_iterator() {
@@ -479,7 +1305,8 @@ mod test {
         //assert!(enable_timestamp_result.is_ok());
 
         // Don't clear the buffer. Poll every second.
-        let iterator_result = KLogEntries::with_options(false, SUGGESTED_POLL_INTERVAL);
+        let iterator_result =
+            KLogEntries::with_options(false, None, SUGGESTED_POLL_INTERVAL, EntryFilter::default());
         assert!(iterator_result.is_ok());
 
         let iterator = iterator_result.unwrap();
@@ -501,7 +1328,8 @@ mod test {
         //assert!(enable_timestamp_result.is_ok());
 
         // Don't clear the buffer. Poll every second.
-        let stream_result = KLogEntries::with_options(false, SUGGESTED_POLL_INTERVAL);
+        let stream_result =
+            KLogEntries::with_options(false, None, SUGGESTED_POLL_INTERVAL, EntryFilter::default());
         assert!(stream_result.is_ok());
 
         let mut stream = stream_result.unwrap();
@@ -517,6 +1345,566 @@ mod test {
         }
     }
 
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_stoppable_iterator() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut entries = KLogEntries::with_options_stoppable(
+            false,
+            None,
+            SUGGESTED_POLL_INTERVAL,
+            EntryFilter::default(),
+            stop.clone(),
+        )
+        .unwrap();
+        while entries.try_next_entry().unwrap().is_some() {}
+
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            stop.store(true, Ordering::Relaxed);
+        });
+
+        // Without the stop signal, this would sleep until the next poll and then keep
+        // iterating forever
+        let start = SystemTime::now();
+        for entry in entries {
+            assert!(entry.is_ok());
+        }
+        assert!(start.elapsed().unwrap() < SUGGESTED_POLL_INTERVAL);
+        stopper.join().unwrap();
+    }
+
+    #[test]
+    fn test_poll_interval_backoff() {
+        let min_poll = Duration::from_secs(1);
+        let max_poll = Duration::from_secs(5);
+        let mut entries = KLogEntries::with_options(false, None, min_poll, EntryFilter::default())
+            .unwrap()
+            .with_max_poll_interval(max_poll);
+
+        // Grows while idle, up to the max
+        let mut intervals = vec![];
+        for _ in 0..4 {
+            entries.adjust_poll_interval(false);
+            intervals.push(entries.poll_interval);
+        }
+        assert_eq!(
+            intervals,
+            vec![
+                Duration::from_secs(2),
+                Duration::from_secs(4),
+                max_poll,
+                max_poll
+            ]
+        );
+        assert_eq!(entries.sleep_interval, max_poll + SLEEP_MARGIN);
+
+        // Resets on new data
+        entries.adjust_poll_interval(true);
+        assert_eq!(entries.poll_interval, min_poll);
+        assert_eq!(entries.sleep_interval, min_poll + SLEEP_MARGIN);
+
+        // Fixed without a max
+        let mut entries =
+            KLogEntries::with_options(false, None, min_poll, EntryFilter::default()).unwrap();
+        entries.adjust_poll_interval(false);
+        assert_eq!(entries.poll_interval, min_poll);
+
+        // Backs off from zero too
+        let mut entries =
+            KLogEntries::with_options(false, None, Duration::ZERO, EntryFilter::default())
+                .unwrap()
+                .with_max_poll_interval(max_poll);
+        entries.adjust_poll_interval(false);
+        assert_eq!(entries.poll_interval, MIN_BACKOFF_POLL_INTERVAL);
+    }
+
+    #[test]
+    fn test_try_next_entry() {
+        // Poll on every call
+        let mut entries =
+            KLogEntries::with_options(false, None, Duration::ZERO, EntryFilter::default()).unwrap();
+
+        let first = entries.try_next_entry();
+        assert!(first.is_ok());
+        assert!(first.unwrap().is_some(), "Should have non-empty logs");
+
+        // Drain what's in the buffer
+        while entries.try_next_entry().unwrap().is_some() {}
+
+        // The buffer is unchanged, so calls return immediately instead of blocking
+        let start = SystemTime::now();
+        for _ in 0..10 {
+            assert!(entries.try_next_entry().is_ok());
+        }
+        assert!(start.elapsed().unwrap() < SUGGESTED_POLL_INTERVAL);
+    }
+
+    #[test]
+    fn test_poll_interval_backoff_by_clock() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut entries =
+            KLogEntries::with_options(false, None, Duration::from_secs(1), EntryFilter::default())
+                .unwrap()
+                .with_max_poll_interval(Duration::from_secs(4))
+                .with_clock(clock.clone())
+                .unwrap();
+
+        // The first poll is due right away, and reads what's in the buffer
+        while entries.try_next_entry().unwrap().is_some() {}
+        assert_eq!(entries.last_poll, start);
+
+        // Polls finding nothing new come further apart, up to the max
+        let mut polled_at = vec![];
+        for _ in 0..12 {
+            clock.advance(Duration::from_secs(1));
+            let last_poll = entries.last_poll;
+            assert!(entries.try_next_entry().unwrap().is_none());
+            if entries.last_poll != last_poll {
+                polled_at.push(entries.last_poll.duration_since(start).unwrap().as_secs());
+            }
+        }
+        assert_eq!(polled_at, vec![1, 3, 7, 11]);
+        assert!(clock.sleeps().is_empty());
+    }
+
+    #[test]
+    fn test_try_next_entry_waits_for_poll_interval() {
+        let clock = MockClock::new();
+        let mut entries =
+            KLogEntries::with_options(false, None, SUGGESTED_POLL_INTERVAL, EntryFilter::default())
+                .unwrap()
+                .with_clock(clock.clone())
+                .unwrap();
+        while entries.try_next_entry().unwrap().is_some() {}
+        let last_poll = entries.last_poll;
+
+        // The next poll isn't due yet: no sleeping or polling until it is
+        clock.advance(SUGGESTED_POLL_INTERVAL - Duration::from_millis(1));
+        assert!(entries.try_next_entry().unwrap().is_none());
+        assert_eq!(entries.last_poll, last_poll);
+        assert!(clock.sleeps().is_empty());
+
+        clock.advance(Duration::from_millis(1));
+        assert!(entries.try_next_entry().unwrap().is_none());
+        assert_eq!(entries.last_poll, clock.now());
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_sleep_unless_stopped() {
+        let clock = MockClock::new();
+        let poll_interval = Duration::from_secs(1);
+        let entries = KLogEntries::with_options(false, None, poll_interval, EntryFilter::default())
+            .unwrap()
+            .with_clock(clock.clone())
+            .unwrap();
+        entries.sleep_unless_stopped();
+        assert_eq!(clock.sleeps(), vec![poll_interval + SLEEP_MARGIN]);
+
+        // Stoppable iterators sleep in steps, checking whether they were stopped in between
+        let clock = MockClock::new();
+        let stop = Arc::new(AtomicBool::new(false));
+        let entries = KLogEntries::with_options_stoppable(
+            false,
+            None,
+            poll_interval,
+            EntryFilter::default(),
+            stop.clone(),
+        )
+        .unwrap()
+        .with_clock(clock.clone())
+        .unwrap();
+        entries.sleep_unless_stopped();
+        let sleeps = clock.sleeps();
+        assert!(sleeps.iter().all(|slept| *slept <= STOP_CHECK_INTERVAL));
+        assert_eq!(
+            sleeps.iter().sum::<Duration>(),
+            poll_interval + SLEEP_MARGIN
+        );
+
+        stop.store(true, Ordering::Relaxed);
+        entries.sleep_unless_stopped();
+        assert_eq!(clock.sleeps(), sleeps);
+    }
+
+    #[test]
+    fn test_klog_clear_action() {
+        let mocked_buffer = b"<6>[    0.004512] Command line: ro quiet\n<3>[  227.000001] Out of memory\n<4>[  228.000000] usb 1-1: reset\n";
+
+        let mut actions = vec![];
+        let result = klog_clear_with(|klogtype, buf| {
+            actions.push(klogtype.clone() as SignedInt);
+            match klogtype {
+                KLogType::SyslogActionSizeBuffer => Ok(1024),
+                _ => {
+                    assert_eq!(buf.len(), 1024);
+                    buf[..mocked_buffer.len()].copy_from_slice(mocked_buffer);
+                    Ok(mocked_buffer.len())
+                }
+            }
+        });
+        assert_eq!(result.unwrap(), ClearReport { entries_cleared: 3 });
+        // SYSLOG_ACTION_SIZE_BUFFER, then SYSLOG_ACTION_READ_CLEAR
+        assert_eq!(actions, vec![10, 4]);
+
+        // An empty buffer
+        let result = klog_clear_with(|klogtype, _| match klogtype {
+            KLogType::SyslogActionSizeBuffer => Ok(1024),
+            _ => Ok(0),
+        });
+        assert_eq!(result.unwrap().entries_cleared, 0);
+
+        let result = klog_clear_with(|klogtype, _| {
+            Err(RMesgError::OperationNotPermitted(format!("{}", klogtype)))
+        });
+        assert!(matches!(result, Err(RMesgError::OperationNotPermitted(_))));
+    }
+
+    #[test]
+    fn test_klog_clear_and_return_filtered() {
+        let mocked_buffer = b"<7>[    0.004512] noisy debugging\n<3>[  227.000001] Out of memory\n<6>[  227.500000] usb 1-1: new device\n<4>[  228.000000] usb 1-1: reset\n";
+
+        let mut actions = vec![];
+        let filter = EntryFilter {
+            min_level: Some(LogLevel::Warning),
+            ..Default::default()
+        };
+        let result = klog_clear_and_return_filtered_with(
+            |klogtype, buf| {
+                actions.push(klogtype.clone() as SignedInt);
+                match klogtype {
+                    KLogType::SyslogActionSizeBuffer => Ok(1024),
+                    _ => {
+                        buf[..mocked_buffer.len()].copy_from_slice(mocked_buffer);
+                        Ok(mocked_buffer.len())
+                    }
+                }
+            },
+            &filter,
+        );
+        let messages: Vec<String> = result.unwrap().into_iter().map(|e| e.message).collect();
+        assert_eq!(messages, vec![" Out of memory", " usb 1-1: reset"]);
+        // SYSLOG_ACTION_SIZE_BUFFER, then SYSLOG_ACTION_READ_CLEAR
+        assert_eq!(actions, vec![10, 4]);
+
+        // Refused before anything is cleared
+        let filter = EntryFilter {
+            min_seq: Some(3),
+            ..Default::default()
+        };
+        let result = klog_clear_and_return_filtered_with(
+            |klogtype, _| panic!("{} called with an unsupported filter", klogtype),
+            &filter,
+        );
+        assert!(matches!(result, Err(RMesgError::UnsupportedFilter(_))));
+    }
+
+    #[test]
+    fn test_klog_record_count() {
+        let count = |mocked_buffer: &'static [u8]| {
+            klog_record_count_with(|klogtype, buf| match klogtype {
+                KLogType::SyslogActionSizeBuffer => Ok(1024),
+                KLogType::SyslogActionReadAll => {
+                    buf[..mocked_buffer.len()].copy_from_slice(mocked_buffer);
+                    Ok(mocked_buffer.len())
+                }
+                _ => panic!("Unexpected action {}", klogtype),
+            })
+            .unwrap()
+        };
+
+        assert_eq!(count(b""), 0);
+        assert_eq!(
+            count(b"<6>[    0.004512] Command line: ro quiet\n<3>[  227.000001] Out of memory\n<4>[  228.000000] usb 1-1: reset\n"),
+            3
+        );
+        // The tail of a record truncated at the start of the buffer isn't counted
+        assert_eq!(
+            count(
+                b"e: ro quiet\n<3>[  227.000001] Out of memory\n<4>[  228.000000] usb 1-1: reset"
+            ),
+            2
+        );
+    }
+
+    #[test]
+    fn test_klog_bytes_read_clear_action() {
+        let mocked_buffer = b"<6>[    0.004512] Command line: ro quiet\n";
+        let read_with = |clear, buffer_size| {
+            let mut actions = vec![];
+            let bytes = klog_bytes_with(
+                |klogtype, buf| {
+                    actions.push(klogtype.clone() as SignedInt);
+                    match klogtype {
+                        KLogType::SyslogActionSizeBuffer => Ok(1024),
+                        _ => {
+                            buf[..mocked_buffer.len()].copy_from_slice(mocked_buffer);
+                            Ok(mocked_buffer.len())
+                        }
+                    }
+                },
+                clear,
+                buffer_size,
+            )
+            .unwrap();
+            assert_eq!(bytes, mocked_buffer);
+            actions
+        };
+
+        // Reading and clearing is one SYSLOG_ACTION_READ_CLEAR, not a read then a clear,
+        // even with a smaller buffer size asked for
+        assert_eq!(read_with(true, None), vec![10, 4]);
+        assert_eq!(read_with(true, Some(64)), vec![10, 4]);
+
+        // Otherwise SYSLOG_ACTION_READ_ALL
+        assert_eq!(read_with(false, None), vec![10, 3]);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mocked_buffer = b"<6>[    0.004512] Command line: ro quiet\n";
+        let mut actions = vec![];
+        let mocked = |klogtype: KLogType, buf: &mut [u8]| {
+            actions.push(klogtype.clone() as SignedInt);
+            match klogtype {
+                KLogType::SyslogActionSizeBuffer => Ok(1024),
+                _ => {
+                    buf[..mocked_buffer.len()].copy_from_slice(mocked_buffer);
+                    Ok(mocked_buffer.len())
+                }
+            }
+        };
+
+        let mut read_only_mocked = read_only(mocked);
+        let result = klog_read_only_with(&mut read_only_mocked, None).unwrap();
+        assert_eq!(result.entries.len(), 1);
+
+        // Clearing actions are refused before reaching the syscall, even through the
+        // functions that clear
+        assert!(matches!(
+            klog_clear_with(&mut read_only_mocked),
+            Err(RMesgError::UnsupportedOperation(_))
+        ));
+        assert!(matches!(
+            klog_bytes_with(&mut read_only_mocked, true, None),
+            Err(RMesgError::UnsupportedOperation(_))
+        ));
+        // So are the actions that consume the buffer or change the console
+        for klogtype in [
+            KLogType::SyslogActionClear,
+            KLogType::SyslogActionRead,
+            KLogType::SyslogActionConsoleOff,
+            KLogType::SyslogActionConsoleOn,
+            KLogType::SyslogActionConsoleLevel,
+        ] {
+            assert!(matches!(
+                read_only_mocked(klogtype, &mut []),
+                Err(RMesgError::UnsupportedOperation(_))
+            ));
+        }
+        drop(read_only_mocked);
+
+        // SYSLOG_ACTION_SIZE_BUFFER and SYSLOG_ACTION_READ_ALL only, none of the refused
+        // actions
+        assert!(actions.iter().all(|action| *action == 10 || *action == 3));
+        assert_eq!(actions, vec![10, 3, 10, 10]);
+
+        assert!(KLogType::SyslogActionReadAll.is_read_only());
+        assert!(KLogType::SyslogActionSizeBuffer.is_read_only());
+        assert!(KLogType::SyslogActionSizeUnread.is_read_only());
+        assert!(!KLogType::SyslogActionRead.is_read_only());
+        assert!(!KLogType::SyslogActionReadClear.is_read_only());
+        assert!(!KLogType::SyslogActionClear.is_read_only());
+        assert!(!KLogType::SyslogActionConsoleLevel.is_read_only());
+    }
+
+    #[test]
+    fn test_klogctl_failures() {
+        // A shim of libc's klogctl, failing the actions in `failing` with errno
+        let shim = |failing: SignedInt, errno: i32| {
+            move |klogtype: KLogType, buf: &mut [u8]| {
+                safely_wrapped_klogctl_with(
+                    |klt, _, _| match klt == failing {
+                        true => {
+                            errno::set_errno(errno::Errno(errno));
+                            -1
+                        }
+                        false if klt == KLogType::SyslogActionSizeBuffer as SignedInt => 1024,
+                        false => 0,
+                    },
+                    klogtype,
+                    buf,
+                )
+            }
+        };
+
+        // A failed read is an error, not an empty read
+        match klog_bytes_with(shim(3, libc::EIO), false, None) {
+            Err(RMesgError::InternalError(s)) => {
+                assert!(s.contains("action 3"), "{}", s);
+                assert!(s.contains(&format!("errno={}", libc::EIO)), "{}", s);
+            }
+            other => panic!("Expected an InternalError, got {:?}", other),
+        }
+        assert!(matches!(
+            klog_bytes_with(shim(10, libc::EINVAL), true, None),
+            Err(RMesgError::InternalError(s)) if s.contains("action 10")
+        ));
+        assert!(matches!(
+            klog_clear_with(shim(4, libc::EPERM)),
+            Err(RMesgError::OperationNotPermitted(_))
+        ));
+        assert!(matches!(
+            klog_record_count_with(shim(3, libc::ENOSYS)),
+            Err(RMesgError::InternalError(_))
+        ));
+
+        // While successful calls read what the shim returned: nothing
+        assert_eq!(klog_bytes_with(shim(-1, 0), false, None).unwrap(), b"");
+    }
+
+    // A klogctl that fails every action with EPERM, as for a user without CAP_SYSLOG
+    fn eperm_klogctl(klogtype: KLogType, buf: &mut [u8]) -> Result<usize, RMesgError> {
+        safely_wrapped_klogctl_with(
+            |_, _, _| {
+                errno::set_errno(errno::Errno(libc::EPERM));
+                -1
+            },
+            klogtype,
+            buf,
+        )
+    }
+
+    #[test]
+    fn test_klog_check_access() {
+        let mut actions = vec![];
+        let result = klog_check_access_with(|klogtype, buf| {
+            actions.push(klogtype.clone() as SignedInt);
+            eperm_klogctl(klogtype, buf)
+        });
+        assert!(matches!(result, Err(RMesgError::OperationNotPermitted(_))));
+        // Only the size is asked for, nothing is read
+        assert_eq!(actions, vec![10]);
+
+        assert!(klog_check_access_with(|_, _| Ok(1024)).is_ok());
+        assert!(klog_check_access().is_ok());
+    }
+
+    #[test]
+    fn test_klog_check_clear_access() {
+        let mut actions = vec![];
+        let result = klog_check_clear_access_with(|klogtype, buf| {
+            actions.push(klogtype.clone() as SignedInt);
+            eperm_klogctl(klogtype, buf)
+        });
+        assert!(matches!(result, Err(RMesgError::OperationNotPermitted(_))));
+        // Only the unread size is asked for, nothing is read or cleared
+        assert_eq!(actions, vec![9]);
+
+        assert!(klog_check_clear_access_with(|_, _| Ok(0)).is_ok());
+    }
+
+    #[test]
+    fn test_klog_console_level_set() {
+        let mut calls = vec![];
+        for level in [LogLevel::Emergency, LogLevel::Error, LogLevel::Debug].iter() {
+            klog_console_level_set_with(
+                |klogtype, buf| {
+                    calls.push((klogtype.clone() as SignedInt, buf.len()));
+                    Ok(0)
+                },
+                *level,
+            )
+            .unwrap();
+        }
+        // SYSLOG_ACTION_CONSOLE_LEVEL, with the level above the least severe to print
+        assert_eq!(calls, vec![(8, 1), (8, 4), (8, 8)]);
+
+        let result = klog_console_level_set_with(eperm_klogctl, LogLevel::Warning);
+        assert!(matches!(result, Err(RMesgError::OperationNotPermitted(_))));
+    }
+
+    #[test]
+    fn test_klog_console_output_set() {
+        let mut actions = vec![];
+        for enabled in [true, false].iter() {
+            klog_console_output_set_with(
+                |klogtype, _| {
+                    actions.push(klogtype.clone() as SignedInt);
+                    Ok(0)
+                },
+                *enabled,
+            )
+            .unwrap();
+        }
+        // SYSLOG_ACTION_CONSOLE_ON, then SYSLOG_ACTION_CONSOLE_OFF
+        assert_eq!(actions, vec![7, 6]);
+
+        let result = klog_console_output_set_with(eperm_klogctl, false);
+        assert!(matches!(result, Err(RMesgError::OperationNotPermitted(_))));
+    }
+
+    #[test]
+    fn test_console_level_from_printk() {
+        assert_eq!(
+            console_level_from_printk("4\t4\t1\t7\n").unwrap(),
+            LogLevel::Error
+        );
+        assert_eq!(
+            console_level_from_printk("1 4 1 7").unwrap(),
+            LogLevel::Emergency
+        );
+        assert_eq!(
+            console_level_from_printk("8\t4\t1\t7\n").unwrap(),
+            LogLevel::Debug
+        );
+        assert_eq!(
+            console_level_from_printk("15\t4\t1\t7\n").unwrap(),
+            LogLevel::Debug
+        );
+        assert!(matches!(
+            console_level_from_printk("0\t4\t1\t7\n"),
+            Err(RMesgError::IntegerOutOfBound(_))
+        ));
+        assert!(matches!(
+            console_level_from_printk(""),
+            Err(RMesgError::InternalError(_))
+        ));
+    }
+
+    #[test]
+    fn test_userspace_filter_unsupported() {
+        let filter = EntryFilter {
+            source: Some(MessageSource::Userspace),
+            ..Default::default()
+        };
+        assert!(matches!(
+            ensure_filter_supported(&filter),
+            Err(RMesgError::UnsupportedFilter(_))
+        ));
+        assert!(matches!(
+            KLogEntries::with_options(false, None, SUGGESTED_POLL_INTERVAL, filter),
+            Err(RMesgError::UnsupportedFilter(_))
+        ));
+
+        let filter = EntryFilter {
+            source: Some(MessageSource::Kernel),
+            ..Default::default()
+        };
+        assert!(ensure_filter_supported(&filter).is_ok());
+
+        let filter = EntryFilter {
+            min_seq: Some(11),
+            ..Default::default()
+        };
+        assert!(matches!(
+            ensure_filter_supported(&filter),
+            Err(RMesgError::UnsupportedFilter(_))
+        ));
+    }
+
     #[test]
     fn test_parse_serialize() {
         let line1 = "<6>a.out[4054]: segfault at 7ffd5503d358 ip 00007ffd5503d358 sp 00007ffd5503d258 error 15";
@@ -538,6 +1926,118 @@ mod test {
         assert_eq!(line3, line3again);
     }
 
+    #[test]
+    fn test_read_result_truncated() {
+        // Starts partway through "<4>[    5.237500] usb 1-1: device descriptor read/64, error -71"
+        let lines = "ice descriptor read/64, error -71\n<6>[    5.140900] NET: Registered protocol family 10\n<3>[  227.000001] Out of memory\n";
+        let result = read_result_from_lines(lines).unwrap();
+        assert!(result.truncated);
+        assert_eq!(result.bytes_read, lines.len());
+        assert_eq!(result.entries.len(), 2);
+        assert_eq!(
+            result.entries[0].timestamp_from_system_start,
+            Some(Duration::from_micros(5140900))
+        );
+
+        let lines = "<6>[    5.140900] NET: Registered protocol family 10\n<3>[  227.000001] Out of memory\n";
+        let result = read_result_from_lines(lines).unwrap();
+        assert!(!result.truncated);
+        assert_eq!(result.entries.len(), 2);
+
+        assert!(!read_result_from_lines("").unwrap().truncated);
+    }
+
+    #[test]
+    fn test_entries_from_bytes() {
+        let lines = [
+            "<6>[    5.140900] NET: Registered protocol family 10",
+            "  <4>[  5.237500]usb 1-1: device descriptor read/64, error -71",
+            "<3>Out of memory: Killed process 1234 (stress)",
+            "<30>[abc] systemd[1]: Started Journal Service.",
+            "<14>[    6.000000] [bracketed] message\r",
+            "a line without priority",
+            "",
+            "<6>[    7.5] last line",
+        ]
+        .join("\n");
+
+        let from_bytes: Vec<Entry> = entries_from_bytes(lines.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(from_bytes, entries_from_lines(&lines).unwrap());
+        assert_eq!(from_bytes.len(), 8);
+        assert_eq!(from_bytes[3].facility, Some(LogFacility::Daemon));
+        assert_eq!(from_bytes[3].level, Some(LogLevel::Info));
+        assert_eq!(
+            from_bytes[3].message,
+            "[abc] systemd[1]: Started Journal Service."
+        );
+
+        // Parsing fails the same way too
+        assert!(entries_from_bytes(b"<>[    5.1] no priority")
+            .next()
+            .unwrap()
+            .is_err());
+        assert!(entries_from_bytes(b"<6>[  5.1] \xff")
+            .next()
+            .unwrap()
+            .is_err());
+        assert_eq!(entries_from_bytes(b"").count(), 0);
+        assert_eq!(entries_from_bytes(b"<6>one\n").count(), 1);
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        let lines: &[&[u8]] = &[
+            b"<6>[    5.140900] NET: Registered protocol family 10",
+            b"<4>[    5.237500] usb 1-1: bad \xff string descriptor",
+            b"<3>[  227.000001] Out of memory",
+        ];
+        let parse = |parse_mode| -> Result<Vec<Option<Entry>>, RMesgError> {
+            let parse_options = ParseOptions {
+                parse_mode,
+                ..Default::default()
+            };
+            lines
+                .iter()
+                .map(|line| entry_from_bytes_with_options(line, &parse_options))
+                .collect()
+        };
+
+        assert!(matches!(
+            parse(ParseMode::Strict),
+            Err(RMesgError::EntryParsingError(_))
+        ));
+
+        let lenient = parse(ParseMode::Lenient).unwrap();
+        assert!(lenient[1].is_none());
+        assert_eq!(lenient.iter().flatten().count(), 2);
+
+        let lossy = parse(ParseMode::Lossy).unwrap();
+        let entry = lossy[1].as_ref().unwrap();
+        assert_eq!(entry.level, Some(LogLevel::Warning));
+        assert_eq!(entry.message, " usb 1-1: bad \u{FFFD} string descriptor");
+        assert_eq!(lossy.iter().flatten().count(), 3);
+
+        // Kept whole when even that doesn't parse
+        let parse_options = ParseOptions {
+            parse_mode: ParseMode::Lossy,
+            ..Default::default()
+        };
+        let entry = entry_from_bytes_with_options(b"<9999999999>[ 1.0] \xff", &parse_options);
+        assert_eq!(
+            entry.unwrap().unwrap().message,
+            "<9999999999>[ 1.0] \u{FFFD}"
+        );
+    }
+
+    #[test]
+    fn test_klog_read() {
+        let result = klog_read(false, None).unwrap();
+        assert!(!result.entries.is_empty(), "Should have non-empty logs");
+        assert!(result.bytes_read > 0);
+    }
+
     #[test]
     fn test_parse_multiline() {
         let line1 = "<6>a.out[4054]: segfault at 7ffd5503d358 ip 00007ffd5503d358 sp 00007ffd5503d258 error 15";
@@ -1,5 +1,9 @@
 use crate::common;
-use crate::entry::{Entry, EntryParsingError};
+#[cfg(any(feature = "sync", feature = "async"))]
+use crate::cursor::Cursor;
+#[cfg(any(feature = "sync", feature = "async"))]
+use crate::entry::ParseMode;
+use crate::entry::{Entry, EntryFlags, EntryParsingError, ParseOptions};
 /// This crate provides a /dev/kmsg file interface from Rust. Reading from this
 /// virtual device is the more modern and simpler way to read the kernel
 /// log buffer than making syscalls directly.
@@ -7,11 +11,13 @@ use crate::entry::{Entry, EntryParsingError};
 /// This allows Rust programs to consume dmesg-like output programmatically.
 ///
 use crate::error::RMesgError;
+#[cfg(any(feature = "sync", feature = "async"))]
+use crate::filter::EntryFilter;
+use crate::parse;
 
-use lazy_static::lazy_static;
 use nonblock::NonBlockingReader;
-use regex::Regex;
 use std::fs as stdfs;
+use std::path::PathBuf;
 
 #[cfg(feature = "sync")]
 use std::io as stdio;
@@ -27,27 +33,23 @@ use futures::stream::Stream;
 #[cfg(feature = "async")]
 use futures::task::{Context, Poll};
 #[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "async")]
+use std::sync::Arc;
+#[cfg(feature = "async")]
 use tokio::fs as tokiofs;
 #[cfg(feature = "async")]
 use tokio::io as tokioio;
 #[cfg(feature = "async")]
-use tokio::io::AsyncBufReadExt;
-
-const DEV_KMSG_PATH: &str = "/dev/kmsg";
-lazy_static! {
-    static ref RE_ENTRY_WITH_TIMESTAMP: Regex = Regex::new(
-        r"(?x)^
-            [[:space:]]*(?P<faclevstr>[[:digit:]]*)[[:space:]]*,
-            # Sequence is a 64-bit integer: https://www.kernel.org/doc/Documentation/ABI/testing/dev-kmsg
-            [[:space:]]*(?P<sequencenum>[[:digit:]]*)[[:space:]]*,
-            [[:space:]]*(?P<timestampstr>[[:digit:]]*)[[:space:]]*,
-            # Ignore everything until the semi-colon and then the semicolon
-            [[^;]]*;
-            (?P<message>.*)
-            $"
-    )
-    .unwrap();
-}
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+#[cfg(feature = "async")]
+use tokio::sync::mpsc;
+#[cfg(feature = "async")]
+use tokio::task::JoinHandle;
+
+pub(crate) const DEV_KMSG_PATH: &str = "/dev/kmsg";
 
 /// While reading the kernel log buffer is very useful in and of itself (especially when running the CLI),
 /// a lot more value is unlocked when it can be tailed line-by-line.
@@ -57,40 +59,125 @@ lazy_static! {
 ///
 /// Implements the synchronous std::iter::Iterator trait
 ///
+/// It is `Send`, so it can be moved into a spawned thread to read off the main thread.
+/// It isn't `Sync` (the reader it wraps needn't be), which costs nothing since iterating
+/// takes `&mut self` anyway: share the entries it yields, e.g. over a channel.
+///
 #[cfg(feature = "sync")]
 pub struct KMsgEntriesIter {
     raw: bool,
-    lines_iter: stdio::Lines<stdio::BufReader<stdfs::File>>,
+    filter: EntryFilter,
+    parse_options: ParseOptions,
+    cursor: Cursor,
+    reader: stdio::BufReader<Box<dyn BufRead + Send>>,
 }
 
 #[cfg(feature = "sync")]
 impl KMsgEntriesIter {
-    /// Create a new KMsgEntries with two specific options
+    /// Create a new KMsgEntries with three specific options
     /// `file_override`: When `Some`, overrides the path from where to read the kernel logs
     /// `raw: bool` When set, does not parse the message and instead sets the entire log entry in the "message" field
-    pub fn with_options(file_override: Option<String>, raw: bool) -> Result<Self, RMesgError> {
+    /// `filter: EntryFilter` Entries not accepted by this filter are skipped
+    pub fn with_options(
+        file_override: Option<String>,
+        raw: bool,
+        filter: EntryFilter,
+    ) -> Result<Self, RMesgError> {
+        ensure_platform_supported()?;
         let path = file_override.as_deref().unwrap_or(DEV_KMSG_PATH);
 
-        let file = match stdfs::File::open(path) {
-            Ok(fc) => fc,
-            Err(e) => {
-                if e.raw_os_error() == Some(libc::EPERM) {
-                    return Err(RMesgError::OperationNotPermitted(format!(
-                        "Open File {}",
-                        path
-                    )));
-                } else {
-                    return Err(RMesgError::DevKMsgFileOpenError(format!(
-                        "Unable to open file {}: {}",
-                        path, e
-                    )));
-                }
-            }
-        };
+        let file = stdfs::File::open(path).map_err(|e| device_error("Open", path, e))?;
+
+        Ok(Self::from_reader(stdio::BufReader::new(file), raw, filter))
+    }
+
+    /// Create a new KMsgEntries reading lines in the /dev/kmsg format from any source,
+    /// such as a socket or an in-memory buffer, rather than /dev/kmsg itself.
+    /// `raw` and `filter` are the same as for `with_options`.
+    /// Iteration ends at the end of the reader.
+    pub fn from_reader<R: BufRead + Send + 'static>(
+        reader: R,
+        raw: bool,
+        filter: EntryFilter,
+    ) -> Self {
+        let reader: Box<dyn BufRead + Send> = Box::new(reader);
+
+        Self {
+            raw,
+            filter,
+            parse_options: ParseOptions::default(),
+            cursor: Cursor::new(),
+            // Wrapped again to peek at what's buffered without blocking (see `read_record`)
+            reader: stdio::BufReader::new(reader),
+        }
+    }
 
-        let lines_iter = stdio::BufReader::new(file).lines();
+    /// Whether lines that can't be parsed are skipped (the default), so one bad line doesn't
+    /// end iteration, or returned as `RMesgError::KMsgParseError`. Same as `with_parse_mode`
+    /// with `ParseMode::Lenient` or `ParseMode::Strict`.
+    pub fn with_skip_bad_lines(self, skip_bad_lines: bool) -> Self {
+        self.with_parse_mode(match skip_bad_lines {
+            true => ParseMode::Lenient,
+            false => ParseMode::Strict,
+        })
+    }
+
+    /// Sets all of the `ParseOptions` at once, rather than one at a time with the setters below
+    pub fn with_parse_options(mut self, parse_options: ParseOptions) -> Self {
+        self.parse_options = parse_options;
+        self
+    }
+
+    /// Sets `ParseOptions::parse_mode`
+    pub fn with_parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_options.parse_mode = parse_mode;
+        self
+    }
+
+    /// Sets `ParseOptions::raw_lines`. Entries carry the whole record, continuation lines included.
+    pub fn with_raw_lines(mut self, raw_lines: bool) -> Self {
+        self.parse_options.raw_lines = raw_lines;
+        self
+    }
+
+    /// Skips the records up to and including the one with this sequence number, e.g. the
+    /// `Cursor::last_sequence_num` of a previous reader (see `KMsgEntriesStream::resume_from`).
+    pub fn resume_from(mut self, sequence_num: usize) -> Self {
+        self.cursor = Cursor::after(sequence_num);
+        self
+    }
+
+    /// Sets `ParseOptions::max_message_len`
+    pub fn with_max_message_len(mut self, max_message_len: Option<usize>) -> Self {
+        self.parse_options.max_message_len = max_message_len;
+        self
+    }
 
-        Ok(Self { raw, lines_iter })
+    /// Sets `ParseOptions::escape_messages`
+    pub fn with_escape_messages(mut self, escape_messages: bool) -> Self {
+        self.parse_options.escape_messages = escape_messages;
+        self
+    }
+
+    /// How far reading got: past the last record read, whether or not the filter accepted it
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    // Reads a line, along with its continuation lines unless raw. /dev/kmsg returns a whole
+    // record per read, so continuation lines are either buffered already or not coming:
+    // waiting for more would hold the record back until the next one gets logged.
+    fn read_record(&mut self) -> stdio::Result<Option<String>> {
+        let mut record = Vec::new();
+        if self.reader.read_until(b'\n', &mut record)? == 0 {
+            return Ok(None);
+        }
+
+        while !self.raw && self.reader.buffer().first() == Some(&b' ') {
+            self.reader.read_until(b'\n', &mut record)?;
+        }
+
+        Ok(Some(decode_line(record)))
     }
 }
 
@@ -103,24 +190,34 @@ impl Iterator for KMsgEntriesIter {
     /// NOT a thread-safe method either. It is suggested this method be always
     /// blocked on to ensure no messages are missed.
     fn next(&mut self) -> Option<Self::Item> {
-        match self.lines_iter.next() {
-            None => None,
-            Some(Err(e)) => Some(Err(RMesgError::IOError(format!(
-                "Error reading next line from kernel log device file: {}",
-                e
-            )))),
-            Some(Ok(line)) => {
-                if self.raw {
-                    Some(Ok(Entry {
-                        facility: None,
-                        level: None,
-                        timestamp_from_system_start: None,
-                        sequence_num: None,
-                        message: line,
-                    }))
-                } else {
-                    Some(entry_from_line(&line).map_err(|e| e.into()))
+        loop {
+            let entry = match self.read_record() {
+                Ok(None) => return None,
+                Err(e) => {
+                    return Some(Err(RMesgError::IOError(format!(
+                        "Error reading next line from kernel log device file: {}",
+                        e
+                    ))))
                 }
+                Ok(Some(line)) => match entry_from_record(line, self.raw, &self.parse_options) {
+                    Ok(Some(entry)) => entry,
+                    Ok(None) => continue,
+                    Err(e) => return Some(Err(e)),
+                },
+            };
+
+            // Already read before resuming
+            if !self.cursor.is_before(&entry) {
+                continue;
+            }
+            self.cursor.advance(&entry);
+
+            if self.filter.is_past_until(&entry) {
+                return None;
+            }
+
+            if self.filter.accepts(&entry) {
+                return Some(Ok(entry));
             }
         }
     }
@@ -134,57 +231,415 @@ impl Iterator for KMsgEntriesIter {
 ///
 /// Implements the tokio::stream::Stream trait
 ///
+/// It is `Send`, so it can be polled from a multi-threaded runtime (e.g. in a spawned task),
+/// but not `Sync`, for the same reasons as `KMsgEntriesIter`.
+///
 #[cfg(feature = "async")]
 pub struct KMsgEntriesStream {
     raw: bool,
+    filter: EntryFilter,
+    parse_options: ParseOptions,
+    cursor: Cursor,
 
-    lines_stream: Pin<Box<tokioio::Lines<tokioio::BufReader<tokiofs::File>>>>,
+    records: RecordSource,
+    channel_capacity: Option<usize>,
+    dropped_records: Arc<AtomicUsize>,
+
+    // The path opened by with_options, for with_reconnect to reopen
+    path: Option<String>,
+    reopen: Option<Reopen>,
+    reopening: Option<ReopenFuture>,
+    // Reconnections since the last record read
+    reconnect_attempts: usize,
+}
+
+// How many times in a row to reconnect without reading a record in between, before
+// giving up and returning the error
+#[cfg(feature = "async")]
+const MAX_RECONNECT_ATTEMPTS: usize = 3;
+
+#[cfg(feature = "async")]
+type Reopen = Box<dyn Fn() -> ReopenFuture + Send>;
+
+#[cfg(feature = "async")]
+type ReopenFuture =
+    Pin<Box<dyn Future<Output = Result<Box<dyn AsyncBufRead + Send + Unpin>, RMesgError>> + Send>>;
+
+// Errors reading the device that reopening it can get past, like the device going away and
+// coming back (e.g. after a namespace change). Not EPIPE, which only means records were
+// overwritten before they were read, and which reading just continues past.
+#[cfg(feature = "async")]
+fn is_recoverable(e: &std::io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::EIO)
+            | Some(libc::ENODEV)
+            | Some(libc::ENXIO)
+            | Some(libc::EBADF)
+            | Some(libc::ESTALE)
+    )
+}
+
+// Where KMsgEntriesStream takes its records from: straight from the reader, or from a
+// bounded channel a background task fills from the reader.
+#[cfg(feature = "async")]
+enum RecordSource {
+    Direct {
+        reader: Box<dyn AsyncBufRead + Send + Unpin>,
+        // The line read so far, while waiting for the rest of it
+        line: Vec<u8>,
+        // Whether to read continuation lines along with their record (unless raw)
+        with_continuation_lines: bool,
+        // The record read so far, while waiting for its continuation lines
+        record: Option<String>,
+    },
+    Channel {
+        receiver: mpsc::Receiver<std::io::Result<String>>,
+        reader_task: JoinHandle<()>,
+    },
+    // While switching from one to the other
+    Empty,
+}
+
+#[cfg(feature = "async")]
+impl RecordSource {
+    // Like `KMsgEntriesIter::read_record`: continuation lines not ready yet aren't waited for.
+    fn poll_next_record(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<Option<String>>> {
+        let (reader, line, with_continuation_lines, record) = match self {
+            RecordSource::Direct {
+                reader,
+                line,
+                with_continuation_lines,
+                record,
+            } => (reader, line, *with_continuation_lines, record),
+            RecordSource::Channel { receiver, .. } => {
+                return match receiver.poll_recv(cx) {
+                    Poll::Pending => Poll::Pending,
+                    Poll::Ready(None) => Poll::Ready(Ok(None)),
+                    Poll::Ready(Some(record)) => Poll::Ready(record.map(Some)),
+                }
+            }
+            RecordSource::Empty => return Poll::Ready(Ok(None)),
+        };
+
+        loop {
+            let next_line = match poll_read_line(reader, line, cx) {
+                Poll::Ready(Ok(Some(line))) => line,
+                Poll::Ready(Ok(None)) => return Poll::Ready(Ok(record.take())),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending if record.is_some() => return Poll::Ready(Ok(record.take())),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match record {
+                Some(record) => {
+                    record.push('\n');
+                    record.push_str(&next_line);
+                }
+                None if !with_continuation_lines => return Poll::Ready(Ok(Some(next_line))),
+                None => *record = Some(next_line),
+            }
+
+            let continued = matches!(
+                Pin::new(&mut **reader).poll_fill_buf(cx),
+                Poll::Ready(Ok(buf)) if buf.first() == Some(&b' ')
+            );
+            if !continued {
+                return Poll::Ready(Ok(record.take()));
+            }
+        }
+    }
+}
+
+// Reads a line like `AsyncBufReadExt::lines` does, except that invalid UTF-8 is replaced
+// rather than failing the read (see `decode_line`). What's read of a line that isn't complete
+// yet is kept in `line` until the rest comes.
+#[cfg(feature = "async")]
+fn poll_read_line(
+    reader: &mut Box<dyn AsyncBufRead + Send + Unpin>,
+    line: &mut Vec<u8>,
+    cx: &mut Context<'_>,
+) -> Poll<std::io::Result<Option<String>>> {
+    loop {
+        let buf = match Pin::new(&mut **reader).poll_fill_buf(cx) {
+            Poll::Ready(Ok(buf)) => buf,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(match line.is_empty() {
+                true => None,
+                false => Some(decode_line(std::mem::take(line))),
+            }));
+        }
+
+        let (used, complete) = match buf.iter().position(|b| *b == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (buf.len(), false),
+        };
+        line.extend_from_slice(&buf[..used]);
+        Pin::new(&mut **reader).consume(used);
+
+        if complete {
+            return Poll::Ready(Ok(Some(decode_line(std::mem::take(line)))));
+        }
+    }
+}
+
+// Keeps reading records into the channel, so the kernel doesn't overwrite them while the
+// consumer is busy. Records that don't fit are dropped and counted; errors wait for room so
+// they are never lost. Ends at the end of the reader, or once the receiver is gone.
+#[cfg(feature = "async")]
+async fn drain_records(
+    mut records: RecordSource,
+    sender: mpsc::Sender<std::io::Result<String>>,
+    dropped_records: Arc<AtomicUsize>,
+) {
+    loop {
+        match std::future::poll_fn(|cx| records.poll_next_record(cx)).await {
+            Ok(None) => return,
+            Ok(Some(record)) => match sender.try_send(Ok(record)) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    dropped_records.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => return,
+            },
+            Err(e) => {
+                if sender.send(Err(e)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
 }
 
 #[cfg(feature = "async")]
 impl KMsgEntriesStream {
-    /// Create a new KMsgEntries with two specific options
+    /// Create a new KMsgEntries with three specific options
     /// `file_override`: When `Some`, overrides the path from where to read the kernel logs
     /// `raw: bool` When set, does not parse the message and instead sets the entire log entry in the "message" field
+    /// `filter: EntryFilter` Entries not accepted by this filter are skipped
     pub async fn with_options(
         file_override: Option<String>,
         raw: bool,
+        filter: EntryFilter,
     ) -> Result<Self, RMesgError> {
+        ensure_platform_supported()?;
         let path = file_override.as_deref().unwrap_or(DEV_KMSG_PATH);
 
-        let file = match tokiofs::File::open(path).await {
-            Ok(fc) => fc,
-            Err(e) => {
-                if e.raw_os_error() == Some(libc::EPERM) {
-                    return Err(RMesgError::OperationNotPermitted(format!(
-                        "Open File {}",
-                        path
-                    )));
-                } else {
-                    return Err(RMesgError::DevKMsgFileOpenError(format!(
-                        "Unable to open file {}: {}",
-                        path, e
-                    )));
+        let file = tokiofs::File::open(path)
+            .await
+            .map_err(|e| device_error("Open", path, e))?;
+
+        // try to read from file
+        if let Err(e) = tokioio::BufReader::new(file).fill_buf().await {
+            return Err(device_error("Read from", path, e));
+        }
+
+        // read from a new file, from the start
+        let mut stream = Self::from_async_reader(
+            tokioio::BufReader::new(
+                tokiofs::File::open(path)
+                    .await
+                    .map_err(|e| device_error("Open", path, e))?,
+            ),
+            raw,
+            filter,
+        );
+        stream.path = Some(path.to_owned());
+
+        Ok(stream)
+    }
+
+    /// Create a new KMsgEntriesStream reading lines in the /dev/kmsg format from any
+    /// asynchronous source, such as a socket, rather than /dev/kmsg itself.
+    /// `raw` and `filter` are the same as for `with_options`.
+    /// The stream ends at the end of the reader.
+    pub fn from_async_reader<R: AsyncBufRead + Send + Unpin + 'static>(
+        reader: R,
+        raw: bool,
+        filter: EntryFilter,
+    ) -> Self {
+        let reader: Box<dyn AsyncBufRead + Send + Unpin> = Box::new(reader);
+
+        Self {
+            raw,
+            filter,
+            parse_options: ParseOptions::default(),
+            cursor: Cursor::new(),
+            records: RecordSource::Direct {
+                reader,
+                line: Vec::new(),
+                with_continuation_lines: !raw,
+                record: None,
+            },
+            channel_capacity: None,
+            dropped_records: Arc::new(AtomicUsize::new(0)),
+            path: None,
+            reopen: None,
+            reopening: None,
+            reconnect_attempts: 0,
+        }
+    }
+
+    /// Read lines on a background task into a bounded buffer of `capacity` lines, rather than
+    /// only when the stream is polled. This keeps draining the kernel's ring buffer while the
+    /// consumer is slow, at the cost of dropping lines when the buffer is full (counted by
+    /// `dropped_records`) rather than the kernel overwriting them.
+    ///
+    /// Must be called from within a tokio runtime. The task is stopped when the stream is dropped.
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        if let RecordSource::Direct { .. } = self.records {
+            self.drain_into_channel(capacity);
+        }
+        self
+    }
+
+    // Moves reading the records onto a task filling a channel of `capacity` records
+    fn drain_into_channel(&mut self, capacity: usize) {
+        let records = std::mem::replace(&mut self.records, RecordSource::Empty);
+        let (sender, receiver) = mpsc::channel(capacity);
+        let reader_task =
+            tokio::spawn(drain_records(records, sender, self.dropped_records.clone()));
+        self.records = RecordSource::Channel {
+            receiver,
+            reader_task,
+        };
+        self.channel_capacity = Some(capacity);
+    }
+
+    /// When set, /dev/kmsg is reopened when reading it fails in a way reopening can get past
+    /// (e.g. the device going away after a namespace change), rather than the error being
+    /// returned. Reading resumes after the last record read (see `resume_from`). Other errors,
+    /// and errors that persist after a few reconnections in a row, are still returned.
+    ///
+    /// Only streams made with `with_options` know what to reopen. Use `with_reopen` for others.
+    pub fn with_reconnect(self, reconnect: bool) -> Self {
+        match (reconnect, self.path.clone()) {
+            (true, Some(path)) => self.with_reopen(move || {
+                let path = path.clone();
+                async move {
+                    tokiofs::File::open(&path)
+                        .await
+                        .map(tokioio::BufReader::new)
+                        .map_err(|e| device_error("Open", &path, e))
                 }
-            }
+            }),
+            _ => self,
+        }
+    }
+
+    /// Like `with_reconnect`, but reconnects to the reader `reopen` opens, e.g. to reconnect
+    /// the socket a stream made with `from_async_reader` reads from.
+    pub fn with_reopen<F, Fut, R>(mut self, reopen: F) -> Self
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<R, RMesgError>> + Send + 'static,
+        R: AsyncBufRead + Send + Unpin + 'static,
+    {
+        self.reopen = Some(Box::new(move || {
+            let reopened = reopen();
+            Box::pin(async move {
+                let reader: Box<dyn AsyncBufRead + Send + Unpin> = Box::new(reopened.await?);
+                Ok(reader)
+            })
+        }));
+        self
+    }
+
+    // Starts reopening the reader, if that can get past this error
+    fn start_reconnect(&mut self, e: &std::io::Error) -> bool {
+        let reopen = match &self.reopen {
+            Some(reopen) if is_recoverable(e) => reopen,
+            _ => return false,
         };
+        if self.reconnect_attempts >= MAX_RECONNECT_ATTEMPTS {
+            return false;
+        }
 
-        // try to read from file
-        let mut lines_stream = Box::pin(tokioio::BufReader::new(file).lines());
-
-        //read a line
-        if let Err(e) = lines_stream.next_line().await {
-            return Err(RMesgError::DevKMsgFileOpenError(format!(
-                "Unable to read from file {}: {}",
-                path, e
-            )));
+        self.reconnect_attempts += 1;
+        self.reopening = Some(reopen());
+        true
+    }
+
+    // Reads from the reopened reader from now on, through a new channel if there was one
+    fn reconnected(&mut self, reader: Box<dyn AsyncBufRead + Send + Unpin>) {
+        if let RecordSource::Channel { reader_task, .. } = &self.records {
+            reader_task.abort();
+        }
+        self.records = RecordSource::Direct {
+            reader,
+            line: Vec::new(),
+            with_continuation_lines: !self.raw,
+            record: None,
+        };
+
+        if let Some(capacity) = self.channel_capacity {
+            self.drain_into_channel(capacity);
         }
+    }
+
+    /// How many lines were dropped because the buffer set by `with_channel_capacity` was full
+    pub fn dropped_records(&self) -> usize {
+        self.dropped_records.load(Ordering::Relaxed)
+    }
+
+    /// Whether lines that can't be parsed are skipped (the default), so one bad line doesn't
+    /// end a follow session, or returned as `RMesgError::KMsgParseError`. Same as
+    /// `with_parse_mode` with `ParseMode::Lenient` or `ParseMode::Strict`.
+    pub fn with_skip_bad_lines(self, skip_bad_lines: bool) -> Self {
+        self.with_parse_mode(match skip_bad_lines {
+            true => ParseMode::Lenient,
+            false => ParseMode::Strict,
+        })
+    }
+
+    /// Sets all of the `ParseOptions` at once, rather than one at a time with the setters below
+    pub fn with_parse_options(mut self, parse_options: ParseOptions) -> Self {
+        self.parse_options = parse_options;
+        self
+    }
+
+    /// Sets `ParseOptions::parse_mode`
+    pub fn with_parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_options.parse_mode = parse_mode;
+        self
+    }
+
+    /// Sets `ParseOptions::raw_lines`. Entries carry the whole record, continuation lines included.
+    pub fn with_raw_lines(mut self, raw_lines: bool) -> Self {
+        self.parse_options.raw_lines = raw_lines;
+        self
+    }
+
+    /// Skips the records up to and including the one with this sequence number, e.g. the
+    /// `Cursor::last_sequence_num` of a previous reader, to resume reading after a restart.
+    ///
+    /// /dev/kmsg can only be seeked to its oldest record, the first record after the last
+    /// clear, or its end (SEEK_SET, SEEK_DATA and SEEK_END), not to a sequence number. So the
+    /// records still in the buffer that were already read are read again, and skipped.
+    pub fn resume_from(mut self, sequence_num: usize) -> Self {
+        self.cursor = Cursor::after(sequence_num);
+        self
+    }
+
+    /// Sets `ParseOptions::max_message_len`
+    pub fn with_max_message_len(mut self, max_message_len: Option<usize>) -> Self {
+        self.parse_options.max_message_len = max_message_len;
+        self
+    }
 
-        // create a new lines_stream with a new file
-        let lines_stream =
-            Box::pin(tokioio::BufReader::new(tokiofs::File::open(path).await?).lines());
+    /// Sets `ParseOptions::escape_messages`
+    pub fn with_escape_messages(mut self, escape_messages: bool) -> Self {
+        self.parse_options.escape_messages = escape_messages;
+        self
+    }
 
-        Ok(Self { raw, lines_stream })
+    /// How far reading got: past the last record read, whether or not the filter accepted it
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
     }
 }
 
@@ -194,70 +649,102 @@ impl Stream for KMsgEntriesStream {
     type Item = Result<Entry, RMesgError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match self.lines_stream.as_mut().poll_next_line(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e.into()))),
-            Poll::Ready(Ok(None)) => Poll::Ready(None),
-            Poll::Ready(Ok(Some(line))) => {
-                let value = if self.raw {
-                    Some(Ok(Entry {
-                        facility: None,
-                        level: None,
-                        timestamp_from_system_start: None,
-                        sequence_num: None,
-                        message: line,
-                    }))
-                } else {
-                    Some(entry_from_line(&line).map_err(|e| e.into()))
+        loop {
+            if let Some(reopening) = self.reopening.as_mut() {
+                let reopened = match reopening.as_mut().poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(reopened) => reopened,
                 };
+                self.reopening = None;
+                match reopened {
+                    Ok(reader) => self.reconnected(reader),
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                }
+            }
 
-                Poll::Ready(value)
+            let entry = match self.records.poll_next_record(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) if self.start_reconnect(&e) => continue,
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(Ok(None)) => return Poll::Ready(None),
+                Poll::Ready(Ok(Some(line))) => {
+                    self.reconnect_attempts = 0;
+                    match entry_from_record(line, self.raw, &self.parse_options) {
+                        Ok(Some(entry)) => entry,
+                        Ok(None) => continue,
+                        Err(e) => return Poll::Ready(Some(Err(e))),
+                    }
+                }
+            };
+
+            // Already read before resuming
+            if !self.cursor.is_before(&entry) {
+                continue;
+            }
+            self.cursor.advance(&entry);
+
+            if self.filter.is_past_until(&entry) {
+                return Poll::Ready(None);
+            }
+
+            // keep polling lines until one is accepted (or none are ready)
+            if self.filter.accepts(&entry) {
+                return Poll::Ready(Some(Ok(entry)));
             }
         }
     }
 }
 
+#[cfg(feature = "async")]
+impl Drop for KMsgEntriesStream {
+    fn drop(&mut self) {
+        if let RecordSource::Channel { reader_task, .. } = &self.records {
+            reader_task.abort();
+        }
+    }
+}
+
 pub fn kmsg_raw(file_override: Option<String>) -> Result<String, RMesgError> {
+    ensure_platform_supported()?;
     let path = file_override.as_deref().unwrap_or(DEV_KMSG_PATH);
 
-    let file = match stdfs::File::open(path) {
-        Ok(fc) => fc,
-        Err(e) => {
-            if e.raw_os_error() == Some(libc::EPERM) {
-                return Err(RMesgError::OperationNotPermitted(format!(
-                    "Open File {}",
-                    path
-                )));
-            } else {
-                return Err(RMesgError::DevKMsgFileOpenError(format!(
-                    "Unable to open file {}: {}",
-                    path, e
-                )));
-            }
-        }
-    };
+    let file = stdfs::File::open(path).map_err(|e| device_error("Open", path, e))?;
 
     let mut noblock_file = NonBlockingReader::from_fd(file)?;
 
-    let mut file_contents = String::new();
-    match noblock_file.read_available_to_string(&mut file_contents) {
-        Ok(_) => {}
-        Err(e) => {
-            if e.raw_os_error() == Some(libc::EPERM) {
-                return Err(RMesgError::OperationNotPermitted(format!(
-                    "Read from File {}",
-                    path
-                )));
-            } else {
-                return Err(RMesgError::DevKMsgFileOpenError(format!(
-                    "Unable to read from file {}: {}",
-                    path, e
-                )));
-            }
+    let mut file_contents = Vec::new();
+    noblock_file
+        .read_available(&mut file_contents)
+        .map_err(|e| device_error("Read from", path, e))?;
+
+    Ok(String::from_utf8_lossy(&file_contents).into_owned())
+}
+
+/// Checks that /dev/kmsg (or `file_override`) can be read, by opening it. Reads nothing, so
+/// it doesn't move past any records.
+pub fn kmsg_check_access(file_override: Option<String>) -> Result<(), RMesgError> {
+    ensure_platform_supported()?;
+    let path = file_override.as_deref().unwrap_or(DEV_KMSG_PATH);
+
+    stdfs::File::open(path).map_err(|e| device_error("Open", path, e))?;
+    Ok(())
+}
+
+// Maps an error opening or reading the device file (the `action`) to the error to return:
+// a missing device (as in containers without it) and a lack of privileges each get their own
+fn device_error(action: &str, path: &str, e: std::io::Error) -> RMesgError {
+    match e.raw_os_error() {
+        Some(libc::ENOENT) => RMesgError::DeviceNotFound(PathBuf::from(path)),
+        Some(libc::EPERM) | Some(libc::EACCES) => {
+            RMesgError::OperationNotPermitted(format!("{} File {}", action, path))
         }
+        _ => RMesgError::DevKMsgFileOpenError(format!(
+            "Unable to {} file {}: {}",
+            action.to_lowercase(),
+            path,
+            e
+        )),
     }
-
-    Ok(file_contents)
 }
 
 /// This is the key safe function that makes the klogctl syslog call with parameters.
@@ -270,10 +757,165 @@ pub fn kmsg_raw(file_override: Option<String>) -> Result<String, RMesgError> {
 ///
 pub fn kmsg(file_override: Option<String>) -> Result<Vec<Entry>, RMesgError> {
     let file_contents = kmsg_raw(file_override)?;
-    let entry_results: Result<Vec<Entry>, EntryParsingError> =
-        file_contents.lines().map(entry_from_line).collect();
+    records(&file_contents).map(parse_record).collect()
+}
+
+// Reads /dev/kmsg like `kmsg`, with the messages made as `parse_options` say
+pub(crate) fn kmsg_with_options(
+    file_override: Option<String>,
+    parse_options: &ParseOptions,
+) -> Result<Vec<Entry>, RMesgError> {
+    let file_contents = kmsg_raw(file_override)?;
+    records(&file_contents)
+        .map(|record| parse_record_with(record, parse_options))
+        .collect()
+}
+
+/// Splits the contents of /dev/kmsg into records: each line, along with the continuation
+/// lines (starting with a space) that follow it.
+pub fn records(contents: &str) -> impl Iterator<Item = &str> {
+    let mut rest = contents;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+
+        let mut end = 0;
+        loop {
+            end += rest[end..].find('\n').map_or(rest.len() - end, |i| i + 1);
+            if !rest[end..].starts_with(' ') {
+                break;
+            }
+        }
 
-    Ok(entry_results?)
+        let (record, remaining) = rest.split_at(end);
+        rest = remaining;
+        Some(record)
+    })
+}
+
+/// Counts the records in /dev/kmsg (or `file_override`) without parsing them.
+///
+/// The count is of the records read, which are all those in the buffer, except for any the
+/// kernel overwrote while they were being read.
+pub fn kmsg_record_count(file_override: Option<String>) -> Result<usize, RMesgError> {
+    Ok(records(&kmsg_raw(file_override)?).count())
+}
+
+/// Parses a record like `parse_line`, with the KEY=value pairs on its continuation lines
+/// (if any) into the entry's `metadata`. Continuation lines without a `=` are ignored.
+pub fn parse_record(record: &str) -> Result<Entry, RMesgError> {
+    parse_record_with(record, &ParseOptions::default())
+}
+
+// Parses a record like `parse_record`, with the message made as `parse_options` say
+pub(crate) fn parse_record_with(
+    record: &str,
+    parse_options: &ParseOptions,
+) -> Result<Entry, RMesgError> {
+    let mut lines = record.lines();
+    let mut entry = parse_line_with(lines.next().unwrap_or_default(), parse_options)?;
+
+    for line in lines {
+        if let Some((key, value)) = line.trim_start().split_once('=') {
+            entry.metadata.insert(key.to_owned(), value.to_owned());
+        }
+    }
+
+    Ok(entry)
+}
+
+// Turns a record read by KMsgEntriesIter or KMsgEntriesStream into an entry, parsed unless
+// `raw`, as `parse_options` say. Records that can't be parsed are handled as its
+// `parse_mode` says, with None for those to skip.
+#[cfg(any(feature = "sync", feature = "async"))]
+fn entry_from_record(
+    record: String,
+    raw: bool,
+    parse_options: &ParseOptions,
+) -> Result<Option<Entry>, RMesgError> {
+    if raw {
+        return Ok(Some(Entry {
+            raw_line: parse_options.raw_line(&record),
+            message: parse_options.message(&record),
+            ..Default::default()
+        }));
+    }
+
+    let mut entry = match (
+        parse_record_with(&record, parse_options),
+        parse_options.parse_mode,
+    ) {
+        (Ok(entry), _) => entry,
+        (Err(e), ParseMode::Strict) => return Err(e),
+        (Err(_), ParseMode::Lenient) => return Ok(None),
+        (Err(_), ParseMode::Lossy) => lossy_entry(&record, parse_options),
+    };
+    entry.raw_line = parse_options.raw_line(&record);
+    Ok(Some(entry))
+}
+
+// What can be made out of a record that doesn't parse: its message (the whole first line
+// when there's no `;`), and whichever of its sequence number and timestamp parse
+#[cfg(any(feature = "sync", feature = "async"))]
+fn lossy_entry(record: &str, parse_options: &ParseOptions) -> Entry {
+    let first_line = record.lines().next().unwrap_or_default();
+    let (prefix, message) = first_line.split_once(';').unwrap_or(("", first_line));
+    let mut fields = prefix.split(',').skip(1);
+
+    Entry {
+        sequence_num: fields.next().and_then(|s| s.parse().ok()),
+        timestamp_from_system_start: fields
+            .next()
+            .and_then(|s| s.parse().ok())
+            .map(std::time::Duration::from_micros),
+        message: parse_options.message(message),
+        ..Default::default()
+    }
+}
+
+// Makes a String of a line read as bytes, and strips its line ending like `BufRead::lines`
+// does. Invalid UTF-8 (as drivers sometimes log) is replaced with U+FFFD rather than being an
+// error, since a read error would end a follow session over a single bad byte.
+#[cfg(any(feature = "sync", feature = "async"))]
+fn decode_line(line: Vec<u8>) -> String {
+    let mut line = match String::from_utf8(line) {
+        Ok(line) => line,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    };
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    line
+}
+
+/// /dev/kmsg only exists on Linux. Other platforms get a clear error instead of
+/// a confusing failure to open the file.
+pub fn ensure_platform_supported() -> Result<(), RMesgError> {
+    match cfg!(target_os = "linux") {
+        true => Ok(()),
+        false => Err(RMesgError::NotSupportedOnThisPlatform(format!(
+            "{} is only available on Linux",
+            DEV_KMSG_PATH
+        ))),
+    }
+}
+
+/// Parses a line like `entry_from_line`, but returns a `RMesgError::KMsgParseError`
+/// carrying the offending line when it can't be parsed.
+pub fn parse_line(line: &str) -> Result<Entry, RMesgError> {
+    parse_line_with(line, &ParseOptions::default())
+}
+
+// Parses a line like `parse_line`, with the message made as `parse_options` say
+fn parse_line_with(line: &str, parse_options: &ParseOptions) -> Result<Entry, RMesgError> {
+    entry_from_line_with(line, parse_options).map_err(|e| RMesgError::KMsgParseError {
+        line: line.to_owned(),
+        reason: format!("{}", e),
+    })
 }
 
 // Message spec: https://github.com/torvalds/linux/blob/master/Documentation/ABI/testing/dev-kmsg
@@ -284,39 +926,33 @@ pub fn kmsg(file_override: Option<String>) -> Result<Vec<Entry>, RMesgError> {
 //  LINE 3 = foobar ; with semicolon
 // 6,2,0,-;x86/fpu: Supporting XSAVE feature 0x001: 'x87 floating point registers'
 // 6,3,0,-,more,deets;x86/fpu: Supporting XSAVE; feature 0x002: 'SSE registers'
+//
+// The parsing itself is in `parse::parse_kmsg_line`, which also builds without std.
 pub fn entry_from_line(line: &str) -> Result<Entry, EntryParsingError> {
-    if let Some(kmsgparts) = RE_ENTRY_WITH_TIMESTAMP.captures(line) {
-        let (facility, level) = match kmsgparts.name("faclevstr") {
-            Some(faclevstr) => common::parse_favlecstr(faclevstr.as_str(), line)?,
-            None => (None, None),
-        };
-
-        let sequence_num = match kmsgparts.name("sequencenum") {
-            Some(sequencestr) => Some(common::parse_fragment::<usize>(sequencestr.as_str(), line)?),
-            None => None,
-        };
-
-        let timestamp_from_system_start = match kmsgparts.name("timestampstr") {
-            Some(timestampstr) => common::parse_timestamp_microsecs(timestampstr.as_str(), line)?,
-            None => None,
-        };
+    entry_from_line_with(line, &ParseOptions::default())
+}
 
-        let message = kmsgparts["message"].to_owned();
+// Parses a line like `entry_from_line`, with the message made as `parse_options` say
+pub(crate) fn entry_from_line_with(
+    line: &str,
+    parse_options: &ParseOptions,
+) -> Result<Entry, EntryParsingError> {
+    if let Some(record) = parse::parse_kmsg_line(line)? {
+        let (facility, level) = common::decode_faclev(record.priority, line)?;
 
         Ok(Entry {
-            facility,
-            level,
-            sequence_num,
-            timestamp_from_system_start,
-            message,
+            facility: Some(facility),
+            level: Some(level),
+            sequence_num: Some(record.sequence_num),
+            timestamp_from_system_start: Some(record.timestamp_from_system_start),
+            message: parse_options.message(record.message),
+            flags: EntryFlags::from_kmsg_field(record.flags),
+            ..Default::default()
         })
     } else {
         Ok(Entry {
-            facility: None,
-            level: None,
-            sequence_num: None,
-            timestamp_from_system_start: None,
-            message: line.to_owned(),
+            message: parse_options.message(line),
+            ..Default::default()
         })
     }
 }
@@ -326,6 +962,9 @@ pub fn entry_from_line(line: &str) -> Result<Entry, EntryParsingError> {
 #[cfg(all(test, target_os = "linux"))]
 mod test {
     use super::*;
+    use crate::entry::{LogFacility, LogLevel};
+    use std::collections::HashMap;
+    use std::time::Duration;
     #[cfg(feature = "async")]
     use tokio_stream::StreamExt;
 
@@ -336,6 +975,53 @@ mod test {
         assert!(!entries.unwrap().is_empty(), "Should have non-empty logs");
     }
 
+    #[test]
+    fn test_device_error() {
+        use std::path::Path;
+
+        let error = |errno| {
+            device_error(
+                "Open",
+                "/dev/kmsg",
+                std::io::Error::from_raw_os_error(errno),
+            )
+        };
+
+        assert!(matches!(
+            error(libc::ENOENT),
+            RMesgError::DeviceNotFound(path) if path == Path::new("/dev/kmsg")
+        ));
+        assert!(matches!(
+            error(libc::EPERM),
+            RMesgError::OperationNotPermitted(_)
+        ));
+        assert!(matches!(
+            error(libc::EACCES),
+            RMesgError::OperationNotPermitted(_)
+        ));
+        assert!(matches!(
+            error(libc::EINVAL),
+            RMesgError::DevKMsgFileOpenError(_)
+        ));
+
+        assert!(matches!(
+            kmsg_check_access(Some("/nonexistent/kmsg".to_owned())),
+            Err(RMesgError::DeviceNotFound(_))
+        ));
+        assert!(matches!(
+            kmsg_raw(Some("/nonexistent/kmsg".to_owned())),
+            Err(RMesgError::DeviceNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_kmsg_record_count() {
+        // 5 lines, one of them a continuation line
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/kmsg.txt");
+        assert_eq!(kmsg_record_count(Some(fixture.to_owned())).unwrap(), 4);
+        assert_eq!(kmsg(Some(fixture.to_owned())).unwrap().len(), 4);
+    }
+
     #[cfg(feature = "sync")]
     #[test]
     fn test_iterator() {
@@ -344,7 +1030,7 @@ mod test {
         //assert!(enable_timestamp_result.is_ok());
 
         // Don't clear the buffer. Poll every second.
-        let iterator_result = KMsgEntriesIter::with_options(None, false);
+        let iterator_result = KMsgEntriesIter::with_options(None, false, EntryFilter::default());
         assert!(iterator_result.is_ok());
 
         let iterator = iterator_result.unwrap();
@@ -366,7 +1052,8 @@ mod test {
         //assert!(enable_timestamp_result.is_ok());
 
         // Don't clear the buffer. Poll every second.
-        let stream_result = KMsgEntriesStream::with_options(None, false).await;
+        let stream_result =
+            KMsgEntriesStream::with_options(None, false, EntryFilter::default()).await;
         //assert!(stream_result.is_ok());
 
         let mut stream = stream_result.unwrap();
@@ -382,6 +1069,568 @@ mod test {
         }
     }
 
+    const KMSG_LINES: &str = "6,339,5140900,-;NET: Registered protocol family 10
+4,340,5237500,-;usb 1-1: device descriptor read/64, error -71
+ SUBSYSTEM=usb
+3,341,227000001,-;Out of memory: Killed process 1234 (stress)
+";
+
+    fn assert_kmsg_lines_entries(entries: Vec<Entry>) {
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0],
+            Entry {
+                facility: Some(LogFacility::Kern),
+                level: Some(LogLevel::Info),
+                sequence_num: Some(339),
+                timestamp_from_system_start: Some(Duration::from_micros(5140900)),
+                message: "NET: Registered protocol family 10".to_owned(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(entries[1].level, Some(LogLevel::Warning));
+        assert_eq!(entries[1].metadata["SUBSYSTEM"], "usb");
+        assert_eq!(entries[2].sequence_num, Some(341));
+        assert_eq!(
+            entries[2].message,
+            "Out of memory: Killed process 1234 (stress)"
+        );
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_from_reader() {
+        let reader = std::io::Cursor::new(KMSG_LINES);
+        let entries: Result<Vec<Entry>, RMesgError> =
+            KMsgEntriesIter::from_reader(reader, false, EntryFilter::default()).collect();
+        assert_kmsg_lines_entries(entries.unwrap());
+
+        let reader = std::io::Cursor::new(KMSG_LINES);
+        let raw_entries: Result<Vec<Entry>, RMesgError> =
+            KMsgEntriesIter::from_reader(reader, true, EntryFilter::default()).collect();
+        assert_eq!(
+            raw_entries.unwrap()[1].message,
+            "4,340,5237500,-;usb 1-1: device descriptor read/64, error -71"
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_from_async_reader() {
+        let reader = std::io::Cursor::new(KMSG_LINES);
+        let entries: Result<Vec<Entry>, RMesgError> =
+            KMsgEntriesStream::from_async_reader(reader, false, EntryFilter::default())
+                .collect()
+                .await;
+        assert_kmsg_lines_entries(entries.unwrap());
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_resume_from_iter() {
+        let reader = std::io::Cursor::new(KMSG_LINES);
+        let mut entries = KMsgEntriesIter::from_reader(reader, false, EntryFilter::default());
+        assert_eq!(entries.next().unwrap().unwrap().sequence_num, Some(339));
+        assert_eq!(entries.cursor(), Cursor::after(339));
+
+        let reader = std::io::Cursor::new(KMSG_LINES);
+        let resumed: Vec<_> = KMsgEntriesIter::from_reader(reader, false, EntryFilter::default())
+            .resume_from(entries.cursor().last_sequence_num().unwrap())
+            .map(|e| e.unwrap().sequence_num)
+            .collect();
+        assert_eq!(resumed, vec![Some(340), Some(341)]);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_max_message_len() {
+        let long_message = "x".repeat(100_000);
+        let reader = std::io::Cursor::new(format!(
+            "6,339,5140900,-;{}\n6,340,5237500,-;short\n",
+            long_message
+        ));
+        let entries: Vec<Entry> =
+            KMsgEntriesStream::from_async_reader(reader, false, EntryFilter::default())
+                .with_max_message_len(Some(16))
+                .with_raw_lines(true)
+                .map(Result::unwrap)
+                .collect()
+                .await;
+        assert_eq!(entries[0].message, format!("{}…", &long_message[..16]));
+        assert_eq!(entries[1].message, "short");
+
+        // Nor is the whole record kept in its raw line, which is truncated to the same length
+        assert_eq!(entries[0].raw_line.as_deref(), Some("6,339,5140900,-;…"));
+        assert_eq!(entries[1].raw_line.as_deref(), Some("6,340,5237500,-;…"));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_resume_from() {
+        // The cursor moves past entries the filter skips too
+        let filter = EntryFilter {
+            min_level: Some(LogLevel::Error),
+            ..Default::default()
+        };
+        let reader = std::io::Cursor::new(KMSG_LINES);
+        let mut stream = KMsgEntriesStream::from_async_reader(reader, false, filter);
+        assert_eq!(stream.cursor(), Cursor::new());
+        assert_eq!(
+            stream.next().await.unwrap().unwrap().sequence_num,
+            Some(341)
+        );
+        assert_eq!(stream.cursor().last_sequence_num(), Some(341));
+
+        let reader = std::io::Cursor::new(KMSG_LINES);
+        let resumed: Vec<_> =
+            KMsgEntriesStream::from_async_reader(reader, false, EntryFilter::default())
+                .resume_from(339)
+                .map(|e| e.unwrap().sequence_num)
+                .collect()
+                .await;
+        assert_eq!(resumed, vec![Some(340), Some(341)]);
+    }
+
+    // The middle line has a facility/level of 300000000000, which doesn't fit
+    const KMSG_LINES_WITH_BAD_LINE: &str = "6,339,5140900,-;NET: Registered protocol family 10
+300000000000,340,5237500,-;usb 1-1: device descriptor read/64, error -71
+3,341,227000001,-;Out of memory: Killed process 1234 (stress)
+";
+
+    const KMSG_RECORD_WITH_CONTINUATION_LINES: &str =
+        "6,339,5140900,-;NET: Registered protocol family 10
+6,342,5291300,-;usb 1-1: new high-speed USB device number 2 using ehci-pci
+ SUBSYSTEM=usb
+ DEVICE=c189:1
+6,343,5291400,-;usb 1-1: New USB device found
+";
+
+    fn assert_metadata(entries: &[Entry]) {
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].metadata.is_empty());
+        assert_eq!(
+            entries[1].message,
+            "usb 1-1: new high-speed USB device number 2 using ehci-pci"
+        );
+        let expected: HashMap<String, String> = vec![
+            ("SUBSYSTEM".to_owned(), "usb".to_owned()),
+            ("DEVICE".to_owned(), "c189:1".to_owned()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(entries[1].metadata, expected);
+        assert!(entries[2].metadata.is_empty());
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let entries: Vec<Entry> = records(KMSG_RECORD_WITH_CONTINUATION_LINES)
+            .map(|record| parse_record(record).unwrap())
+            .collect();
+        assert_metadata(&entries);
+        assert_eq!(
+            entries[1].to_kmsg_str().unwrap(),
+            "6,342,5291300,-;usb 1-1: new high-speed USB device number 2 using ehci-pci\n SUBSYSTEM=usb\n DEVICE=c189:1"
+        );
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_continuation_lines() {
+        let reader = std::io::Cursor::new(KMSG_RECORD_WITH_CONTINUATION_LINES);
+        let entries: Vec<Entry> =
+            KMsgEntriesIter::from_reader(reader, false, EntryFilter::default())
+                .map(Result::unwrap)
+                .collect();
+        assert_metadata(&entries);
+
+        // Raw keeps every line as is
+        let reader = std::io::Cursor::new(KMSG_RECORD_WITH_CONTINUATION_LINES);
+        let entries: Vec<Entry> =
+            KMsgEntriesIter::from_reader(reader, true, EntryFilter::default())
+                .map(Result::unwrap)
+                .collect();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[2].message, " SUBSYSTEM=usb");
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_raw_lines() {
+        let reader = std::io::Cursor::new(KMSG_RECORD_WITH_CONTINUATION_LINES);
+        let entries: Vec<Entry> =
+            KMsgEntriesIter::from_reader(reader, false, EntryFilter::default())
+                .with_raw_lines(true)
+                .map(Result::unwrap)
+                .collect();
+        assert_eq!(
+            entries[0].raw_line.as_deref(),
+            Some("6,339,5140900,-;NET: Registered protocol family 10")
+        );
+        assert_eq!(
+            entries[1].raw_line.as_deref(),
+            Some("6,342,5291300,-;usb 1-1: new high-speed USB device number 2 using ehci-pci\n SUBSYSTEM=usb\n DEVICE=c189:1")
+        );
+
+        // Off by default
+        let reader = std::io::Cursor::new(KMSG_RECORD_WITH_CONTINUATION_LINES);
+        assert!(
+            KMsgEntriesIter::from_reader(reader, false, EntryFilter::default())
+                .all(|e| e.unwrap().raw_line.is_none())
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_continuation_lines_stream() {
+        let reader = std::io::Cursor::new(KMSG_RECORD_WITH_CONTINUATION_LINES);
+        let entries: Vec<Entry> =
+            KMsgEntriesStream::from_async_reader(reader, false, EntryFilter::default())
+                .map(Result::unwrap)
+                .collect()
+                .await;
+        assert_metadata(&entries);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_bad_lines() {
+        let reader = std::io::Cursor::new(KMSG_LINES_WITH_BAD_LINE);
+        let entries: Vec<Result<Entry, RMesgError>> =
+            KMsgEntriesIter::from_reader(reader, false, EntryFilter::default()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].as_ref().unwrap().sequence_num, Some(341));
+
+        let reader = std::io::Cursor::new(KMSG_LINES_WITH_BAD_LINE);
+        let entries: Vec<Result<Entry, RMesgError>> =
+            KMsgEntriesIter::from_reader(reader, false, EntryFilter::default())
+                .with_skip_bad_lines(false)
+                .collect();
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].is_ok());
+        match &entries[1] {
+            Err(RMesgError::KMsgParseError { line, .. }) => assert_eq!(
+                line,
+                "300000000000,340,5237500,-;usb 1-1: device descriptor read/64, error -71"
+            ),
+            other => panic!("Expected a KMsgParseError, got {:?}", other),
+        }
+        assert!(entries[2].is_ok());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_bad_lines_stream() {
+        let reader = std::io::Cursor::new(KMSG_LINES_WITH_BAD_LINE);
+        let entries: Vec<Result<Entry, RMesgError>> =
+            KMsgEntriesStream::from_async_reader(reader, false, EntryFilter::default())
+                .collect()
+                .await;
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|e| e.is_ok()));
+
+        let reader = std::io::Cursor::new(KMSG_LINES_WITH_BAD_LINE);
+        let entries: Vec<Result<Entry, RMesgError>> =
+            KMsgEntriesStream::from_async_reader(reader, false, EntryFilter::default())
+                .with_skip_bad_lines(false)
+                .collect()
+                .await;
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(entries[1], Err(RMesgError::KMsgParseError { .. })));
+    }
+
+    fn assert_parse_modes(entries: &[Vec<Result<Entry, RMesgError>>; 3]) {
+        let [strict, lenient, lossy] = entries;
+
+        assert_eq!(strict.len(), 3);
+        assert!(matches!(strict[1], Err(RMesgError::KMsgParseError { .. })));
+
+        assert_eq!(lenient.len(), 2);
+        assert!(lenient.iter().all(|e| e.is_ok()));
+
+        assert_eq!(lossy.len(), 3);
+        let entry = lossy[1].as_ref().unwrap();
+        assert_eq!(entry.level, None);
+        assert_eq!(entry.sequence_num, Some(340));
+        assert_eq!(
+            entry.timestamp_from_system_start,
+            Some(Duration::from_micros(5237500))
+        );
+        assert_eq!(
+            entry.message,
+            "usb 1-1: device descriptor read/64, error -71"
+        );
+        assert_eq!(lossy[2].as_ref().unwrap().sequence_num, Some(341));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_parse_mode() {
+        let entries = [ParseMode::Strict, ParseMode::Lenient, ParseMode::Lossy].map(|parse_mode| {
+            let reader = std::io::Cursor::new(KMSG_LINES_WITH_BAD_LINE);
+            KMsgEntriesIter::from_reader(reader, false, EntryFilter::default())
+                .with_parse_mode(parse_mode)
+                .collect()
+        });
+        assert_parse_modes(&entries);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_parse_mode_stream() {
+        let mut entries: [Vec<Result<Entry, RMesgError>>; 3] = Default::default();
+        for (parse_mode, entries) in [ParseMode::Strict, ParseMode::Lenient, ParseMode::Lossy]
+            .iter()
+            .zip(entries.iter_mut())
+        {
+            let reader = std::io::Cursor::new(KMSG_LINES_WITH_BAD_LINE);
+            *entries = KMsgEntriesStream::from_async_reader(reader, false, EntryFilter::default())
+                .with_parse_mode(*parse_mode)
+                .collect()
+                .await;
+        }
+        assert_parse_modes(&entries);
+    }
+
+    // A record with a byte that isn't UTF-8, between two good ones
+    const KMSG_LINES_WITH_INVALID_UTF8: &[u8] =
+        b"6,339,5140900,-;NET: Registered protocol family 10
+4,340,5237500,-;usb 1-1: bad \xff\xfe string descriptor
+3,341,227000001,-;Out of memory: Killed process 1234 (stress)
+";
+
+    fn assert_invalid_utf8_replaced(entries: &[Entry]) {
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[1].message,
+            "usb 1-1: bad \u{FFFD}\u{FFFD} string descriptor"
+        );
+        assert_eq!(entries[2].sequence_num, Some(341));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_invalid_utf8() {
+        let reader = std::io::Cursor::new(KMSG_LINES_WITH_INVALID_UTF8);
+        let entries: Vec<Entry> =
+            KMsgEntriesIter::from_reader(reader, false, EntryFilter::default())
+                .with_skip_bad_lines(false)
+                .map(Result::unwrap)
+                .collect();
+        assert_invalid_utf8_replaced(&entries);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_invalid_utf8_stream() {
+        let reader = std::io::Cursor::new(KMSG_LINES_WITH_INVALID_UTF8);
+        let entries: Vec<Entry> =
+            KMsgEntriesStream::from_async_reader(reader, false, EntryFilter::default())
+                .with_skip_bad_lines(false)
+                .map(Result::unwrap)
+                .collect()
+                .await;
+        assert_invalid_utf8_replaced(&entries);
+    }
+
+    // Reads its contents, then fails with an OS error
+    #[cfg(feature = "async")]
+    struct FailingReader {
+        contents: std::io::Cursor<&'static str>,
+        errno: i32,
+    }
+
+    #[cfg(feature = "async")]
+    impl tokio::io::AsyncRead for FailingReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            if self.contents.position() as usize == self.contents.get_ref().len() {
+                return Poll::Ready(Err(std::io::Error::from_raw_os_error(self.errno)));
+            }
+            Pin::new(&mut self.contents).poll_read(cx, buf)
+        }
+    }
+
+    #[cfg(feature = "async")]
+    fn failing_reader(contents: &'static str, errno: i32) -> tokioio::BufReader<FailingReader> {
+        tokioio::BufReader::new(FailingReader {
+            contents: std::io::Cursor::new(contents),
+            errno,
+        })
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_reconnect() {
+        let reopened = Arc::new(AtomicUsize::new(0));
+        let reopen = {
+            let reopened = reopened.clone();
+            move || {
+                reopened.fetch_add(1, Ordering::Relaxed);
+                // The buffer as it is when reopened: the records already read, then a new one
+                async {
+                    Ok(std::io::Cursor::new(format!(
+                        "{}6,342,228000000,-;usb 1-1: reset\n",
+                        KMSG_LINES
+                    )))
+                }
+            }
+        };
+
+        // The device goes away after the first record
+        let entries: Vec<_> = KMsgEntriesStream::from_async_reader(
+            failing_reader(
+                "6,339,5140900,-;NET: Registered protocol family 10\n",
+                libc::ENODEV,
+            ),
+            false,
+            EntryFilter::default(),
+        )
+        .with_reopen(reopen.clone())
+        .map(|e| e.unwrap().sequence_num)
+        .collect()
+        .await;
+        assert_eq!(entries, vec![Some(339), Some(340), Some(341), Some(342)]);
+        assert_eq!(reopened.load(Ordering::Relaxed), 1);
+
+        // Reconnecting through the channel too
+        let entries: Vec<_> = KMsgEntriesStream::from_async_reader(
+            failing_reader(
+                "6,339,5140900,-;NET: Registered protocol family 10\n",
+                libc::EIO,
+            ),
+            false,
+            EntryFilter::default(),
+        )
+        .with_channel_capacity(16)
+        .with_reopen(reopen.clone())
+        .map(|e| e.unwrap().sequence_num)
+        .collect()
+        .await;
+        assert_eq!(entries, vec![Some(339), Some(340), Some(341), Some(342)]);
+        assert_eq!(reopened.load(Ordering::Relaxed), 2);
+
+        // Errors reopening can't get past are returned
+        let mut stream = KMsgEntriesStream::from_async_reader(
+            failing_reader("", libc::EACCES),
+            false,
+            EntryFilter::default(),
+        )
+        .with_reopen(reopen.clone());
+        assert!(stream.next().await.unwrap().is_err());
+        assert_eq!(reopened.load(Ordering::Relaxed), 2);
+
+        // As are errors that persist after reconnecting
+        let mut stream = KMsgEntriesStream::from_async_reader(
+            failing_reader("", libc::ENODEV),
+            false,
+            EntryFilter::default(),
+        )
+        .with_reopen(|| async { Ok(failing_reader("", libc::ENODEV)) });
+        assert!(stream.next().await.unwrap().is_err());
+
+        // And errors reopening
+        let mut stream = KMsgEntriesStream::from_async_reader(
+            failing_reader("", libc::ENODEV),
+            false,
+            EntryFilter::default(),
+        )
+        .with_reopen(|| async {
+            Err::<std::io::Cursor<&[u8]>, _>(RMesgError::DeviceNotFound(DEV_KMSG_PATH.into()))
+        });
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(RMesgError::DeviceNotFound(_)))
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_channel_drops_records_when_full() {
+        let lines: String = (0..10)
+            .map(|n| format!("6,{},5140900,-;message {}\n", n, n))
+            .collect();
+        let mut stream = KMsgEntriesStream::from_async_reader(
+            std::io::Cursor::new(lines),
+            false,
+            EntryFilter::default(),
+        )
+        .with_channel_capacity(2);
+
+        // The reader task fills the channel while nothing is consuming it
+        let mut entries = vec![];
+        while let Some(entry) = stream.next().await {
+            entries.push(entry.unwrap());
+        }
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].message, "message 0");
+        assert_eq!(stream.dropped_records(), 8);
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert!(parse_line("6,339,5140900,-;NET: Registered protocol family 10").is_ok());
+        // Lines not shaped like records are continuation lines, not errors
+        assert!(matches!(
+            parse_line("6,notanumber,5140900,-;message"),
+            Ok(Entry {
+                sequence_num: None,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_line("6,339,99999999999999999999999,-;message"),
+            Err(RMesgError::KMsgParseError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_flags() {
+        let fragment = parse_line("4,342,5291300,c;usb 1-1: ").unwrap();
+        assert!(fragment.flags.contains(EntryFlags::FRAGMENT));
+        assert!(!fragment.flags.contains(EntryFlags::CONTINUATION));
+        assert_eq!(fragment.to_kmsg_str().unwrap(), "4,342,5291300,c;usb 1-1: ");
+
+        let continuation = parse_line("4,343,5291301,+;new device").unwrap();
+        assert!(continuation.flags.contains(EntryFlags::CONTINUATION));
+
+        let complete = parse_line("6,339,5140900,-;NET: Registered protocol family 10").unwrap();
+        assert!(complete.flags.is_empty());
+        assert_eq!(
+            complete.to_kmsg_str().unwrap(),
+            "6,339,5140900,-;NET: Registered protocol family 10"
+        );
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let contents = "6,2,1283919,-;usb 1-1: new high-speed USB device number 2
+ SUBSYSTEM=usb
+ DEVICE=c189:1
+4,3,1283920,-;sd 0:0:0:0: [sda] Write cache: enabled; FUA=no
+ SUBSYSTEM=scsi
+ DEVICE=+scsi:0:0:0:0
+ EXTRA=1
+ ZONE=a=b
+3,4,227000001,-;Out of memory: Killed process 1234 (stress)
+";
+        for record in records(contents).chain(records(&fixture_contents())) {
+            let entry = parse_record(record).unwrap();
+            assert_eq!(entry.to_kmsg_str().unwrap(), record.trim_end_matches('\n'));
+        }
+    }
+
+    fn fixture_contents() -> String {
+        stdfs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/kmsg.txt"
+        ))
+        .unwrap()
+    }
+
     #[test]
     fn test_parse_serialize() {
         let line1 = " LINE2=foobar";
@@ -1,14 +1,72 @@
+// The parsing core only uses alloc (and core), not std
+extern crate alloc;
+
+/// Batching of streamed entries, e.g. for bulk writes
+#[cfg(feature = "async")]
+pub mod batch;
+/// Suppression of runs of repeated entries
+pub mod coalesce;
 mod common;
 
+/// Resuming reading /dev/kmsg where a previous reader left off
+pub mod cursor;
+
+/// Detection of records lost between entries, from gaps in their sequence numbers
+pub mod dropped;
 pub mod entry;
 pub mod error;
+/// File Implementation (reads entries saved to a file, such as /var/log/kern.log)
+pub mod file;
+/// Criteria to select which entries get read
+pub mod filter;
+/// Following only entries logged from now on
+pub mod follow_new;
+/// Configurable formatting of entries for display
+pub mod formatter;
+/// Marking of idle periods in streams, with heartbeats
+#[cfg(feature = "async")]
+pub mod heartbeat;
 /// KLog Implementation (makes klogctl aka syslog system call through libc)
 pub mod klogctl;
 /// KMsg Implementation (reads from the /dev/kmsg file)
 pub mod kmsgfile;
+/// Logging of entries through the `log` facade
+#[cfg(feature = "log")]
+pub mod log_bridge;
+/// Counters of the entries read, rendered for Prometheus
+pub mod metrics;
+/// Grouping of dumps spanning many entries (like call traces) into one entry
+pub mod multiline;
+/// Parsing of /dev/kmsg records and syslog priorities using only `core` and `alloc`, so it
+/// can be reused in `no_std` crates (public with the "alloc" feature)
+#[cfg(feature = "alloc")]
+pub mod parse;
+#[cfg(not(feature = "alloc"))]
+mod parse;
+/// Looking at the next entry of a stream without consuming it
+#[cfg(feature = "async")]
+pub mod peek;
+// Helpers shared by the tests of several modules
+#[cfg(test)]
+mod test_util;
+/// Forwarding of entries as `tracing` events
+#[cfg(feature = "tracing")]
+pub mod tracing;
+/// Attaching wall-clock times to entries, from a boot time asked for once
+pub mod wall_clock;
 
+#[cfg(feature = "extra-traits")]
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+#[cfg(feature = "extra-traits")]
+use std::io::{self, Write};
 #[cfg(feature = "sync")]
 use std::iter::Iterator;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 #[cfg(feature = "async")]
 use core::pin::Pin;
@@ -19,17 +77,103 @@ use futures::task::{Context, Poll};
 #[cfg(feature = "async")]
 use pin_project::pin_project;
 
-#[derive(Clone, Copy, Debug)]
+/// The file from which the time since system boot is read
+pub const PROC_UPTIME_PATH: &str = "/proc/uptime";
+
+/// The file from which the time the system booted at is read, as its `btime` line
+pub const PROC_STAT_PATH: &str = "/proc/stat";
+
+// The first line the kernel logs at boot starts with this
+const LINUX_VERSION_BANNER: &str = "Linux version ";
+
+// Timestamps logged from different CPUs can be slightly out of order, so only going back
+// further than this is taken as a new boot
+const BOOT_RESET_MARGIN: Duration = Duration::from_secs(1);
+
+/// Where `system_boot_time` gets the time since boot (or the time of boot) from.
+///
+/// These disagree once the system has been suspended: kernel log timestamps come from a
+/// clock that stops while the system is suspended, while /proc/uptime, /proc/stat and
+/// CLOCK_BOOTTIME keep counting. So with those, entries logged after a resume come out too
+/// early, by the time spent suspended before them (which is how `dmesg -T` is off too).
+/// `Monotonic` stops while suspended like the kernel log's clock does, which makes it the
+/// most accurate for entries logged since the last resume (usually the ones of interest),
+/// while entries from before a suspend come out too late instead. Without suspends, all
+/// sources agree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClockSource {
+    /// The uptime in /proc/uptime, subtracted from the current time (like `dmesg -T`)
+    #[default]
+    Uptime,
+
+    /// The `btime` line of /proc/stat: the time of boot as the kernel has it, in whole
+    /// seconds. Agrees with `Uptime` to the second.
+    ProcStatBtime,
+
+    /// The CLOCK_BOOTTIME clock, subtracted from the current time. The same time as /proc/uptime
+    /// counts, read without a file. Only available on Linux.
+    BootTime,
+
+    /// The CLOCK_MONOTONIC clock, subtracted from the current time. Doesn't count time
+    /// spent suspended, like kernel log timestamps (see above).
+    Monotonic,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub enum Backend {
+    /// Reads the /dev/kmsg file, and falls back to the klogctl syscall when the file
+    /// can't be opened (e.g. it doesn't exist, or reading it isn't permitted).
+    /// When klogctl fails too, the error from /dev/kmsg is returned.
+    ///
+    /// Use `resolve_backend` to find out which backend gets used.
+    #[default]
     Default,
     KLogCtl,
     DevKMsg,
+    /// Reads entries saved to a file, such as /var/log/kern.log or the output of dmesg,
+    /// instead of the live kernel log buffer. The format is detected from the first line
+    /// (see `file::FileFormat`). Following ends at the end of the file.
+    File(PathBuf),
+}
+
+/// Parses the names `Display` prints: "default", "klogctl", "devkmsg", and "file:" followed
+/// by the path of a file.
+impl FromStr for Backend {
+    type Err = error::RMesgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Backend::Default),
+            "klogctl" => Ok(Backend::KLogCtl),
+            "devkmsg" => Ok(Backend::DevKMsg),
+            _ => match s.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(Backend::File(PathBuf::from(path))),
+                _ => Err(error::RMesgError::UnknownBackend(s.to_owned())),
+            },
+        }
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Backend::Default => f.write_str("default"),
+            Backend::KLogCtl => f.write_str("klogctl"),
+            Backend::DevKMsg => f.write_str("devkmsg"),
+            Backend::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
 }
 
+/// Entries from whichever backend was read. Like every iterator in this crate, it is `Send`.
 #[cfg(feature = "sync")]
 pub enum EntriesIterator {
     KLogCtl(klogctl::KLogEntries),
     DevKMsg(kmsgfile::KMsgEntriesIter),
+    File(file::FileEntries),
+    New(Box<follow_new::FollowNew<EntriesIterator>>),
+    Coalesced(Box<coalesce::Coalesce<EntriesIterator>>),
+    Limited(Box<std::iter::Take<EntriesIterator>>),
 }
 #[cfg(feature = "sync")]
 impl Iterator for EntriesIterator {
@@ -38,15 +182,24 @@ impl Iterator for EntriesIterator {
         match self {
             Self::KLogCtl(k) => k.next(),
             Self::DevKMsg(d) => d.next(),
+            Self::File(f) => f.next(),
+            Self::New(n) => n.next(),
+            Self::Coalesced(c) => c.next(),
+            Self::Limited(l) => l.next(),
         }
     }
 }
 
+/// Entries from whichever backend was read. Like every stream in this crate, it is `Send`.
 #[pin_project(project = EntriesStreamPinnedProjection)]
 #[cfg(feature = "async")]
 pub enum EntriesStream {
     KLogCtl(#[pin] klogctl::KLogEntries),
     DevKMsg(#[pin] kmsgfile::KMsgEntriesStream),
+    File(#[pin] futures::stream::Iter<std::vec::IntoIter<Result<entry::Entry, error::RMesgError>>>),
+    New(Box<follow_new::FollowNew<EntriesStream>>),
+    Coalesced(Box<coalesce::Coalesce<EntriesStream>>),
+    Limited(Box<futures::stream::Take<EntriesStream>>),
 }
 #[cfg(feature = "async")]
 impl Stream for EntriesStream {
@@ -55,153 +208,2243 @@ impl Stream for EntriesStream {
         match self.project() {
             EntriesStreamPinnedProjection::KLogCtl(k) => k.poll_next(cx),
             EntriesStreamPinnedProjection::DevKMsg(d) => d.poll_next(cx),
+            EntriesStreamPinnedProjection::File(f) => f.poll_next(cx),
+            EntriesStreamPinnedProjection::New(n) => Pin::new(n.as_mut()).poll_next(cx),
+            EntriesStreamPinnedProjection::Coalesced(c) => Pin::new(c.as_mut()).poll_next(cx),
+            EntriesStreamPinnedProjection::Limited(l) => Pin::new(l.as_mut()).poll_next(cx),
+        }
+    }
+}
+
+/// Options for reading the kernel log, set with fluent setters and read with one of the
+/// terminal methods (`entries`, `last_n_entries`, `logs_raw`, `raw_records`, `iter` and
+/// `stream`):
+///
+/// ```no_run
+/// use rmesg::entry::LogLevel;
+/// use rmesg::{Backend, RmesgOptions};
+///
+/// let entries = RmesgOptions::new()
+///     .backend(Backend::DevKMsg)
+///     .min_level(LogLevel::Warning)
+///     .clear(false)
+///     .entries()?;
+/// # Ok::<(), rmesg::error::RMesgError>(())
+/// ```
+///
+/// The free functions (`log_entries`, `logs_iter`, ...) take the same options as parameters.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RmesgOptions {
+    backend: Backend,
+    clear: bool,
+    buffer_size: Option<usize>,
+    poll_interval: Option<Duration>,
+    max_poll_interval: Option<Duration>,
+    kmsg_path: Option<PathBuf>,
+    raw: bool,
+    filter: filter::EntryFilter,
+    coalesce: bool,
+    follow_new: bool,
+    parse_options: entry::ParseOptions,
+    max_entries: Option<usize>,
+    sorted: bool,
+    current_boot_only: bool,
+    #[cfg(feature = "async")]
+    heartbeat: Option<Duration>,
+    #[cfg(feature = "async")]
+    reconnect: bool,
+    #[cfg(feature = "async")]
+    channel_capacity: Option<usize>,
+}
+
+impl RmesgOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets where entries are read from. Defaults to `Backend::Default`.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// When set, clears the buffer after reading it (only with the klogctl backend).
+    /// Each read clears what it read in the same call, so no entries are lost in between
+    /// (see `klogctl::klog_raw`).
+    pub fn clear(mut self, clear: bool) -> Self {
+        self.clear = clear;
+        self
+    }
+
+    /// Sets the size of the buffer the klogctl backend reads into, in bytes.
+    /// Defaults to the size of the kernel log buffer.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// Sets how often iterators and streams from the klogctl backend poll the buffer for new
+    /// entries. Defaults to `klogctl::SUGGESTED_POLL_INTERVAL`.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// When set, iterators and streams from the klogctl backend back off polling while the
+    /// buffer is quiet, up to this interval (see `klogctl::KLogEntries::with_max_poll_interval`).
+    pub fn max_poll_interval(mut self, max_poll_interval: Duration) -> Self {
+        self.max_poll_interval = Some(max_poll_interval);
+        self
+    }
+
+    /// Reads /dev/kmsg from this path instead (e.g. a namespaced or bind-mounted kmsg), with
+    /// the devkmsg and default backends. Reading from a path that doesn't exist fails with
+    /// `RMesgError::DeviceNotFound` (and the default backend falls back to klogctl).
+    pub fn kmsg_path(mut self, kmsg_path: PathBuf) -> Self {
+        self.kmsg_path = Some(kmsg_path);
+        self
+    }
+
+    /// When set, iterators and streams from the devkmsg backend don't parse lines, and
+    /// yield them whole as the message of entries.
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    /// When set, iterators and streams suppress runs of repeated entries
+    /// (see `coalesce::Coalesce`).
+    pub fn coalesce(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
+    /// When set, iterators and streams skip the entries already in the buffer, and only
+    /// yield entries logged after they were created (see `follow_new::FollowNew`).
+    pub fn follow_new(mut self, follow_new: bool) -> Self {
+        self.follow_new = follow_new;
+        self
+    }
+
+    /// When set, entries from iterators and streams carry the line(s) they were parsed
+    /// from in `Entry::raw_line`. Off by default, since it's a copy of every line read.
+    pub fn raw_lines(mut self, raw_lines: bool) -> Self {
+        self.parse_options.raw_lines = raw_lines;
+        self
+    }
+
+    /// When set, messages longer than this many bytes are truncated to it as they're parsed
+    /// (see `entry::ParseOptions::max_message_len`).
+    pub fn max_message_len(mut self, max_message_len: usize) -> Self {
+        self.parse_options.max_message_len = Some(max_message_len);
+        self
+    }
+
+    /// When set, non-printable characters in messages (like the escape sequences a device
+    /// could put in its name) are escaped (see `entry::ParseOptions::escape_messages`).
+    /// Doesn't apply to `logs_raw`, which doesn't parse entries.
+    pub fn escape_messages(mut self, escape_messages: bool) -> Self {
+        self.parse_options.escape_messages = escape_messages;
+        self
+    }
+
+    /// When set, iterators and streams end after yielding this many entries (or errors),
+    /// taking them from the front (unlike `last_n_entries`). Unbounded by default.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// When set, `entries` and `last_n_entries` return entries sorted by timestamp (see
+    /// `sort_by_timestamp`), rather than in the order they were read. `last_n_entries` takes
+    /// the last entries read, then sorts them. Iterators and streams ignore it.
+    pub fn sorted(mut self, sorted: bool) -> Self {
+        self.sorted = sorted;
+        self
+    }
+
+    /// When set, `entries` and `last_n_entries` leave out entries logged before the current
+    /// boot, which the buffer keeps on some configurations (e.g. across kexec). Computing their
+    /// wall-clock times from the current boot time (see `Entry::wall_clock_time`) gives
+    /// nonsensical times. A boot starts at the `Linux version` banner the kernel logs first, or
+    /// where timestamps go back, since they're since the boot they were logged in.
+    /// Files, which needn't be in the order they were logged, and iterators and streams
+    /// ignore it.
+    pub fn current_boot_only(mut self, current_boot_only: bool) -> Self {
+        self.current_boot_only = current_boot_only;
+        self
+    }
+
+    /// How iterators and streams reading the kernel log buffer handle records they can't
+    /// parse. Skipped by default (see `entry::ParseMode`). Files are parsed strictly, and
+    /// other terminal methods fail on records they can't parse.
+    pub fn parse_mode(mut self, parse_mode: entry::ParseMode) -> Self {
+        self.parse_options.parse_mode = parse_mode;
+        self
+    }
+
+    /// Has `stream_with_heartbeat` yield `heartbeat::StreamItem::Idle` each time no entry is
+    /// logged for this long (see `heartbeat::Heartbeat`). Other terminal methods ignore it.
+    #[cfg(feature = "async")]
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// When set, streams from the devkmsg backend reopen /dev/kmsg when reading it fails in a
+    /// way reopening can get past, and resume after the last record read (see
+    /// `kmsgfile::KMsgEntriesStream::with_reconnect`). Other terminal methods ignore it.
+    #[cfg(feature = "async")]
+    pub fn reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// When set, streams from the devkmsg backend drain /dev/kmsg on a background task into a
+    /// buffer of this many lines (see `kmsgfile::KMsgEntriesStream::with_channel_capacity`).
+    /// Other terminal methods ignore it.
+    #[cfg(feature = "async")]
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = Some(capacity);
+        self
+    }
+
+    /// Replaces the whole filter. The setters below set one criterion of it at a time.
+    pub fn filter(mut self, filter: filter::EntryFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// See `EntryFilter::min_level`
+    pub fn min_level(mut self, min_level: entry::LogLevel) -> Self {
+        self.filter.min_level = Some(min_level);
+        self
+    }
+
+    /// See `EntryFilter::levels`
+    pub fn levels<I: IntoIterator<Item = entry::LogLevel>>(mut self, levels: I) -> Self {
+        self.filter.levels = Some(levels.into_iter().collect());
+        self
+    }
+
+    /// See `EntryFilter::facilities`
+    pub fn facilities<I: IntoIterator<Item = entry::LogFacility>>(mut self, facilities: I) -> Self {
+        self.filter.facilities = Some(facilities.into_iter().collect());
+        self
+    }
+
+    /// See `EntryFilter::since`
+    pub fn since(mut self, since: Duration) -> Self {
+        self.filter.since = Some(since);
+        self
+    }
+
+    /// See `EntryFilter::until`
+    pub fn until(mut self, until: Duration) -> Self {
+        self.filter.until = Some(until);
+        self
+    }
+
+    /// See `EntryFilter::source`
+    pub fn source(mut self, source: filter::MessageSource) -> Self {
+        self.filter.source = Some(source);
+        self
+    }
+
+    /// See `EntryFilter::message_regex`
+    pub fn message_regex(mut self, message_regex: regex::Regex) -> Self {
+        self.filter.message_regex = Some(message_regex);
+        self
+    }
+
+    /// See `EntryFilter::invert_message_regex`
+    pub fn invert_message_regex(mut self, invert_message_regex: bool) -> Self {
+        self.filter.invert_message_regex = invert_message_regex;
+        self
+    }
+
+    /// See `EntryFilter::min_seq`
+    pub fn min_seq(mut self, min_seq: usize) -> Self {
+        self.filter.min_seq = Some(min_seq);
+        self
+    }
+
+    /// See `EntryFilter::max_seq`
+    pub fn max_seq(mut self, max_seq: usize) -> Self {
+        self.filter.max_seq = Some(max_seq);
+        self
+    }
+
+    /// The filter entries are read with
+    pub fn entry_filter(&self) -> &filter::EntryFilter {
+        &self.filter
+    }
+
+    /// Reads all entries currently in the buffer
+    pub fn entries(self) -> Result<Vec<entry::Entry>, error::RMesgError> {
+        let Self {
+            backend,
+            clear,
+            buffer_size,
+            kmsg_path,
+            filter,
+            parse_options,
+            sorted,
+            current_boot_only,
+            ..
+        } = self;
+        let kmsg_file = kmsg_file_override(&kmsg_path);
+        let parse_options = whole_buffer_parse_options(parse_options);
+
+        let current_boot_only = current_boot_only && !matches!(backend, Backend::File(_));
+
+        let mut entries = match backend {
+            Backend::Default => fall_back_to_klogctl(
+                kmsgfile::kmsg_with_options(kmsg_file, &parse_options),
+                || {
+                    klogctl::ensure_filter_supported(&filter)?;
+                    klogctl::klog_with_options(clear, buffer_size, &parse_options)
+                },
+            ),
+            Backend::KLogCtl => {
+                klogctl::ensure_filter_supported(&filter)?;
+                klogctl::klog_with_options(clear, buffer_size, &parse_options)
+            }
+            Backend::DevKMsg => kmsgfile::kmsg_with_options(kmsg_file, &parse_options),
+            Backend::File(path) => file::FileEntries::with_options(path, filter.clone())?
+                .with_parse_options(parse_options)
+                .collect(),
+        }?;
+
+        if current_boot_only {
+            let mut boots = BootStarts::default();
+            // In order, since where a boot starts depends on the entries before it
+            let current_boot =
+                entries
+                    .iter()
+                    .enumerate()
+                    .fold(0, |start, (i, e)| match boots.starts_boot(e) {
+                        true => i,
+                        false => start,
+                    });
+            entries.drain(..current_boot);
+        }
+        entries.retain(|e| filter.accepts(e));
+        if sorted {
+            sort_by_timestamp(&mut entries);
+        }
+        Ok(entries)
+    }
+
+    /// Reads entries like `entries`, but only returns the last `n` entries accepted by
+    /// the filter, in their original order (like `dmesg | tail -n`).
+    ///
+    /// The buffer is still read whole, like `entries` does, but records are parsed one at a
+    /// time and only the last `n` entries accepted are kept.
+    pub fn last_n_entries(self, n: usize) -> Result<Vec<entry::Entry>, error::RMesgError> {
+        let Self {
+            backend,
+            clear,
+            buffer_size,
+            kmsg_path,
+            filter,
+            parse_options,
+            sorted,
+            current_boot_only,
+            ..
+        } = self;
+        let kmsg_file = kmsg_file_override(&kmsg_path);
+        let parse_options = whole_buffer_parse_options(parse_options);
+
+        let current_boot_only = current_boot_only && !matches!(backend, Backend::File(_));
+        let accepts = |e: &entry::Entry| filter.accepts(e);
+
+        let mut entries = match backend {
+            Backend::Default => fall_back_to_klogctl(
+                kmsgfile::kmsg_raw(kmsg_file).and_then(|raw| {
+                    last_n(
+                        kmsgfile::records(&raw)
+                            .map(|record| kmsgfile::parse_record_with(record, &parse_options)),
+                        accepts,
+                        n,
+                        current_boot_only,
+                    )
+                }),
+                || {
+                    klogctl::ensure_filter_supported(&filter)?;
+                    last_n(
+                        klogctl::klog_with_options(clear, buffer_size, &parse_options)?
+                            .into_iter()
+                            .map(Ok::<_, error::RMesgError>),
+                        accepts,
+                        n,
+                        current_boot_only,
+                    )
+                },
+            ),
+            Backend::KLogCtl => {
+                klogctl::ensure_filter_supported(&filter)?;
+                last_n(
+                    klogctl::klog_with_options(clear, buffer_size, &parse_options)?
+                        .into_iter()
+                        .map(Ok::<_, error::RMesgError>),
+                    accepts,
+                    n,
+                    current_boot_only,
+                )
+            }
+            Backend::DevKMsg => {
+                let raw = kmsgfile::kmsg_raw(kmsg_file)?;
+                last_n(
+                    kmsgfile::records(&raw)
+                        .map(|record| kmsgfile::parse_record_with(record, &parse_options)),
+                    accepts,
+                    n,
+                    current_boot_only,
+                )
+            }
+            Backend::File(path) => last_n(
+                file::FileEntries::with_options(path, filter.clone())?
+                    .with_parse_options(parse_options),
+                accepts,
+                n,
+                false,
+            ),
+        }?;
+
+        if sorted {
+            sort_by_timestamp(&mut entries);
+        }
+        Ok(entries)
+    }
+
+    /// Counts the entries in the buffer at each level (see `rmesg::level_histogram`).
+    /// Only entries accepted by the filter are counted.
+    pub fn level_histogram(
+        self,
+    ) -> Result<BTreeMap<Option<entry::LogLevel>, usize>, error::RMesgError> {
+        Ok(count_levels(&self.entries()?))
+    }
+
+    /// Checks that the backend can be read, without reading (or clearing) anything, e.g. to
+    /// fail early before following. Returns `RMesgError::OperationNotPermitted` when the
+    /// process lacks the privileges to read it.
+    ///
+    /// For /dev/kmsg, the file is opened. For klogctl, the buffer size is asked for, which
+    /// takes the same privileges as reading. For a file, it is opened.
+    pub fn check_access(&self) -> Result<(), error::RMesgError> {
+        let kmsg_file = kmsg_file_override(&self.kmsg_path);
+        match &self.backend {
+            Backend::Default => fall_back_to_klogctl(
+                kmsgfile::kmsg_check_access(kmsg_file),
+                klogctl::klog_check_access,
+            ),
+            Backend::KLogCtl => klogctl::klog_check_access(),
+            Backend::DevKMsg => kmsgfile::kmsg_check_access(kmsg_file),
+            Backend::File(path) => {
+                fs::File::open(path)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads the buffer as one big string with line-breaks, as it came from the backend.
+    /// The filter doesn't apply.
+    pub fn logs_raw(self) -> Result<String, error::RMesgError> {
+        let kmsg_file = kmsg_file_override(&self.kmsg_path);
+        match self.backend {
+            Backend::Default => fall_back_to_klogctl(kmsgfile::kmsg_raw(kmsg_file), || {
+                klogctl::klog_raw(self.clear, self.buffer_size)
+            }),
+            Backend::KLogCtl => klogctl::klog_raw(self.clear, self.buffer_size),
+            Backend::DevKMsg => kmsgfile::kmsg_raw(kmsg_file),
+            Backend::File(path) => file::file_raw(&path),
+        }
+    }
+
+    /// Reads the buffer like `logs_raw`, split into its records: lines, along with the
+    /// continuation lines (starting with a space) of /dev/kmsg records. Records don't end
+    /// with a line break, and empty lines are skipped. The filter doesn't apply.
+    pub fn raw_records(self) -> Result<Vec<String>, error::RMesgError> {
+        let kmsg_file = kmsg_file_override(&self.kmsg_path);
+        let kmsg_records = |raw: String| split_records(&raw, true);
+        let klog_records = |raw: String| split_records(&raw, false);
+        match self.backend {
+            Backend::Default => {
+                fall_back_to_klogctl(kmsgfile::kmsg_raw(kmsg_file).map(kmsg_records), || {
+                    klogctl::klog_raw(self.clear, self.buffer_size).map(klog_records)
+                })
+            }
+            Backend::KLogCtl => klogctl::klog_raw(self.clear, self.buffer_size).map(klog_records),
+            Backend::DevKMsg => kmsgfile::kmsg_raw(kmsg_file).map(kmsg_records),
+            Backend::File(path) => {
+                let raw = file::file_raw(&path)?;
+                let format = raw
+                    .lines()
+                    .find(|line| !line.trim().is_empty())
+                    .map(file::FileFormat::detect);
+                Ok(split_records(&raw, format == Some(file::FileFormat::KMsg)))
+            }
+        }
+    }
+
+    /// Iterates over entries as they get logged.
+    #[cfg(feature = "sync")]
+    pub fn iter(self) -> Result<EntriesIterator, error::RMesgError> {
+        let Self {
+            backend,
+            clear,
+            buffer_size,
+            poll_interval,
+            max_poll_interval,
+            kmsg_path,
+            raw,
+            filter,
+            coalesce,
+            follow_new,
+            parse_options,
+            max_entries,
+            // Only apply to entries and last_n_entries
+            sorted: _,
+            current_boot_only: _,
+            // Only apply to streams
+            #[cfg(feature = "async")]
+                heartbeat: _,
+            #[cfg(feature = "async")]
+                reconnect: _,
+            #[cfg(feature = "async")]
+                channel_capacity: _,
+        } = self;
+        let kmsg_file = kmsg_file_override(&kmsg_path);
+        let poll = (poll_interval, max_poll_interval);
+
+        // Read what's in the buffer before following, so nothing logged in between is missed
+        let existing = match follow_new {
+            true => Self {
+                backend: backend.clone(),
+                buffer_size,
+                kmsg_path,
+                ..Self::default()
+            }
+            .entries()?,
+            false => vec![],
+        };
+
+        let entries = match backend {
+            Backend::Default => fall_back_to_klogctl(
+                kmsgfile::KMsgEntriesIter::with_options(kmsg_file, raw, filter.clone()).map(
+                    |entries| EntriesIterator::DevKMsg(entries.with_parse_options(parse_options)),
+                ),
+                || {
+                    Ok(EntriesIterator::KLogCtl(
+                        klog_entries_only_if_timestamp_enabled(clear, buffer_size, poll, filter)?
+                            .with_parse_options(parse_options),
+                    ))
+                },
+            ),
+            Backend::KLogCtl => Ok(EntriesIterator::KLogCtl(
+                klog_entries_only_if_timestamp_enabled(clear, buffer_size, poll, filter)?
+                    .with_parse_options(parse_options),
+            )),
+            Backend::DevKMsg => Ok(EntriesIterator::DevKMsg(
+                kmsgfile::KMsgEntriesIter::with_options(kmsg_file, raw, filter)?
+                    .with_parse_options(parse_options),
+            )),
+            Backend::File(path) => Ok(EntriesIterator::File(
+                file::FileEntries::with_options(path, filter)?.with_parse_options(parse_options),
+            )),
+        }?;
+
+        let entries = match follow_new {
+            true => EntriesIterator::New(Box::new(follow_new::FollowNew::new(entries, &existing))),
+            false => entries,
+        };
+
+        let entries = match coalesce {
+            true => EntriesIterator::Coalesced(Box::new(coalesce::Coalesce::new(entries))),
+            false => entries,
+        };
+
+        match max_entries {
+            Some(max_entries) => Ok(EntriesIterator::Limited(Box::new(
+                entries.take(max_entries),
+            ))),
+            None => Ok(entries),
+        }
+    }
+
+    /// Streams entries as they get logged.
+    #[cfg(feature = "async")]
+    pub async fn stream(self) -> Result<EntriesStream, error::RMesgError> {
+        let Self {
+            backend,
+            clear,
+            buffer_size,
+            poll_interval,
+            max_poll_interval,
+            kmsg_path,
+            raw,
+            filter,
+            coalesce,
+            follow_new,
+            parse_options,
+            max_entries,
+            // Only apply to entries and last_n_entries
+            sorted: _,
+            current_boot_only: _,
+            heartbeat: _,
+            reconnect,
+            channel_capacity,
+        } = self;
+        let kmsg_file = kmsg_file_override(&kmsg_path);
+        let poll = (poll_interval, max_poll_interval);
+
+        // Read what's in the buffer before following, so nothing logged in between is missed
+        let existing = match follow_new {
+            true => Self {
+                backend: backend.clone(),
+                buffer_size,
+                kmsg_path,
+                ..Self::default()
+            }
+            .entries()?,
+            false => vec![],
+        };
+
+        let entries = match backend {
+            Backend::Default => fall_back_to_klogctl(
+                kmsgfile::KMsgEntriesStream::with_options(kmsg_file, raw, filter.clone())
+                    .await
+                    .map(|entries| {
+                        EntriesStream::DevKMsg(
+                            with_channel_capacity(entries, channel_capacity)
+                                .with_reconnect(reconnect)
+                                .with_parse_options(parse_options),
+                        )
+                    }),
+                || {
+                    Ok(EntriesStream::KLogCtl(
+                        klog_entries_only_if_timestamp_enabled(clear, buffer_size, poll, filter)?
+                            .with_parse_options(parse_options),
+                    ))
+                },
+            ),
+            Backend::KLogCtl => Ok(EntriesStream::KLogCtl(
+                klog_entries_only_if_timestamp_enabled(clear, buffer_size, poll, filter)?
+                    .with_parse_options(parse_options),
+            )),
+            Backend::DevKMsg => Ok(EntriesStream::DevKMsg(
+                with_channel_capacity(
+                    kmsgfile::KMsgEntriesStream::with_options(kmsg_file, raw, filter).await?,
+                    channel_capacity,
+                )
+                .with_reconnect(reconnect)
+                .with_parse_options(parse_options),
+            )),
+            // Files are read whole, since they're finite
+            Backend::File(path) => Ok(EntriesStream::File(futures::stream::iter(
+                file::FileEntries::with_options(path, filter)?
+                    .with_parse_options(parse_options)
+                    .collect::<Vec<_>>(),
+            ))),
+        }?;
+
+        let entries = match follow_new {
+            true => EntriesStream::New(Box::new(follow_new::FollowNew::new(entries, &existing))),
+            false => entries,
+        };
+
+        let entries = match coalesce {
+            true => EntriesStream::Coalesced(Box::new(coalesce::Coalesce::new(entries))),
+            false => entries,
+        };
+
+        match max_entries {
+            Some(max_entries) => Ok(EntriesStream::Limited(Box::new(futures::StreamExt::take(
+                entries,
+                max_entries,
+            )))),
+            None => Ok(entries),
         }
     }
+
+    /// Streams entries as they get logged like `stream`, along with a
+    /// `heartbeat::StreamItem::Idle` each time none is logged for the `heartbeat` interval.
+    #[cfg(feature = "async")]
+    pub async fn stream_with_heartbeat(
+        self,
+    ) -> Result<heartbeat::Heartbeat<EntriesStream>, error::RMesgError> {
+        let interval = self.heartbeat;
+        Ok(heartbeat::Heartbeat::new(self.stream().await?, interval))
+    }
 }
 
+/// Reads all entries currently in the buffer. Same as `RmesgOptions::entries`.
 pub fn log_entries(b: Backend, clear: bool) -> Result<Vec<entry::Entry>, error::RMesgError> {
-    match b {
-        Backend::Default => match kmsgfile::kmsg(None) {
-            Ok(e) => Ok(e),
-            Err(error::RMesgError::DevKMsgFileOpenError(s)) => {
-                eprintln!(
-                    "Falling back from device file to klogctl syscall due to error: {}",
-                    s
-                );
-                klogctl::klog(clear)
+    RmesgOptions {
+        backend: b,
+        clear,
+        ..Default::default()
+    }
+    .entries()
+}
+
+/// Reads the last `n` entries. Same as `RmesgOptions::last_n_entries`.
+pub fn last_n_entries(
+    b: Backend,
+    clear: bool,
+    n: usize,
+) -> Result<Vec<entry::Entry>, error::RMesgError> {
+    RmesgOptions {
+        backend: b,
+        clear,
+        ..Default::default()
+    }
+    .last_n_entries(n)
+}
+
+/// Reads all entries currently in the buffer, like `log_entries` but without blocking the
+/// async runtime (the buffer is read on a blocking thread). For when the whole buffer is
+/// wanted at once, without streaming it.
+#[cfg(feature = "async")]
+pub async fn collect_entries(
+    b: Backend,
+    clear: bool,
+) -> Result<Vec<entry::Entry>, error::RMesgError> {
+    match tokio::task::spawn_blocking(move || log_entries(b, clear)).await {
+        Ok(entries) => entries,
+        Err(e) => Err(error::RMesgError::InternalError(format!(
+            "Reading entries failed: {}",
+            e
+        ))),
+    }
+}
+
+/// Stable-sorts entries by `timestamp_from_system_start`, for when sequence numbers and
+/// timestamps disagree (e.g. after clock adjustments). Entries without a timestamp go last,
+/// in the order they were in.
+pub fn sort_by_timestamp(entries: &mut [entry::Entry]) {
+    entries.sort_by_key(|e| {
+        (
+            e.timestamp_from_system_start.is_none(),
+            e.timestamp_from_system_start,
+        )
+    });
+}
+
+/// The earliest and latest `timestamp_from_system_start` among entries, e.g. to report the
+/// time span they cover. Entries without a timestamp are ignored, and when none has one,
+/// there's no span.
+pub fn time_span(entries: &[entry::Entry]) -> Option<(Duration, Duration)> {
+    let mut timestamps = entries.iter().filter_map(|e| e.timestamp_from_system_start);
+    let first = timestamps.next()?;
+    Some(
+        timestamps.fold((first, first), |(earliest, latest), timestamp| {
+            (earliest.min(timestamp), latest.max(timestamp))
+        }),
+    )
+}
+
+// Finds where boots start among entries in the order they were logged: at the banner the
+// kernel logs first, or where timestamps go back further than they do between CPUs
+#[derive(Default)]
+struct BootStarts {
+    previous_timestamp: Option<Duration>,
+}
+
+impl BootStarts {
+    fn starts_boot(&mut self, entry: &entry::Entry) -> bool {
+        let went_back = match (self.previous_timestamp, entry.timestamp_from_system_start) {
+            (Some(previous), Some(timestamp)) => timestamp + BOOT_RESET_MARGIN < previous,
+            _ => false,
+        };
+        if entry.timestamp_from_system_start.is_some() {
+            self.previous_timestamp = entry.timestamp_from_system_start;
+        }
+        went_back || entry.message.starts_with(LINUX_VERSION_BANNER)
+    }
+}
+
+// Keeps the last n accepted entries in a ring, so the entries kept stay bounded by n. With
+// `current_boot_only`, those logged before the last boot are dropped.
+fn last_n<E>(
+    entries: impl Iterator<Item = Result<entry::Entry, E>>,
+    accepts: impl Fn(&entry::Entry) -> bool,
+    n: usize,
+    current_boot_only: bool,
+) -> Result<Vec<entry::Entry>, error::RMesgError>
+where
+    error::RMesgError: From<E>,
+{
+    let mut boots = BootStarts::default();
+    let mut tail = VecDeque::new();
+    for entry in entries {
+        let entry = entry?;
+        if current_boot_only && boots.starts_boot(&entry) {
+            tail.clear();
+        }
+        if accepts(&entry) {
+            tail.push_back(entry);
+            if tail.len() > n {
+                tail.pop_front();
             }
-            Err(e) => Err(e),
-        },
-        Backend::KLogCtl => klogctl::klog(clear),
-        Backend::DevKMsg => kmsgfile::kmsg(None),
+        }
+    }
+
+    Ok(tail.into())
+}
+
+// The kmsgfile functions take the path to read /dev/kmsg from as a String
+fn kmsg_file_override(kmsg_path: &Option<PathBuf>) -> Option<String> {
+    kmsg_path
+        .as_ref()
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+// Drains /dev/kmsg on a background task when a channel capacity was set
+#[cfg(feature = "async")]
+fn with_channel_capacity(
+    entries: kmsgfile::KMsgEntriesStream,
+    channel_capacity: Option<usize>,
+) -> kmsgfile::KMsgEntriesStream {
+    match channel_capacity {
+        Some(capacity) => entries.with_channel_capacity(capacity),
+        None => entries,
+    }
+}
+
+// `entries` and `last_n_entries` fail on records they can't parse, and leave out raw lines
+fn whole_buffer_parse_options(parse_options: entry::ParseOptions) -> entry::ParseOptions {
+    entry::ParseOptions {
+        parse_mode: entry::ParseMode::Strict,
+        raw_lines: false,
+        ..parse_options
     }
 }
 
+/// Reads the buffer as one big string. Same as `RmesgOptions::logs_raw`.
 pub fn logs_raw(b: Backend, clear: bool) -> Result<String, error::RMesgError> {
-    match b {
-        Backend::Default => match kmsgfile::kmsg_raw(None) {
-            Ok(e) => Ok(e),
-            Err(error::RMesgError::DevKMsgFileOpenError(s)) => {
-                eprintln!(
-                    "Falling back from device file to klogctl syscall due to error: {}",
-                    s
-                );
-                klogctl::klog_raw(clear)
-            }
-            Err(e) => Err(e),
-        },
-        Backend::KLogCtl => klogctl::klog_raw(clear),
-        Backend::DevKMsg => kmsgfile::kmsg_raw(None),
+    RmesgOptions {
+        backend: b,
+        clear,
+        ..Default::default()
+    }
+    .logs_raw()
+}
+
+/// Reads the buffer split into its records. Same as `RmesgOptions::raw_records`.
+pub fn raw_records(b: Backend, clear: bool) -> Result<Vec<String>, error::RMesgError> {
+    RmesgOptions {
+        backend: b,
+        clear,
+        ..Default::default()
     }
+    .raw_records()
 }
 
+// Splits a raw buffer into records: /dev/kmsg records with their continuation lines when
+// `kmsg`, or else single lines
+fn split_records(raw: &str, kmsg: bool) -> Vec<String> {
+    let records: Box<dyn Iterator<Item = &str>> = match kmsg {
+        true => Box::new(kmsgfile::records(raw)),
+        false => Box::new(raw.split_inclusive('\n')),
+    };
+    records
+        .map(|record| record.trim_end_matches(['\r', '\n']))
+        .filter(|record| !record.trim().is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Iterates over entries as they get logged. Same as `RmesgOptions::iter`.
 #[cfg(feature = "sync")]
 pub fn logs_iter(b: Backend, clear: bool, raw: bool) -> Result<EntriesIterator, error::RMesgError> {
-    match b {
-        Backend::Default => match kmsgfile::KMsgEntriesIter::with_options(None, raw) {
-            Ok(e) => Ok(EntriesIterator::DevKMsg(e)),
-            Err(error::RMesgError::DevKMsgFileOpenError(s)) => {
-                eprintln!(
-                    "Falling back from device file to klogctl syscall due to error: {}",
-                    s
-                );
-                Ok(EntriesIterator::KLogCtl(
-                    klog_entries_only_if_timestamp_enabled(clear)?,
-                ))
-            }
-            Err(e) => Err(e),
-        },
-        Backend::KLogCtl => Ok(EntriesIterator::KLogCtl(
-            klog_entries_only_if_timestamp_enabled(clear)?,
-        )),
-        Backend::DevKMsg => Ok(EntriesIterator::DevKMsg(
-            kmsgfile::KMsgEntriesIter::with_options(None, raw)?,
-        )),
+    RmesgOptions {
+        backend: b,
+        clear,
+        raw,
+        ..Default::default()
     }
+    .iter()
 }
 
+/// Streams entries as they get logged. Same as `RmesgOptions::stream`.
+///
+/// For heartbeats while no entry is logged, see `RmesgOptions::stream_with_heartbeat`.
 #[cfg(feature = "async")]
 pub async fn logs_stream(
     b: Backend,
     clear: bool,
     raw: bool,
 ) -> Result<EntriesStream, error::RMesgError> {
-    match b {
-        Backend::Default => match kmsgfile::KMsgEntriesStream::with_options(None, raw).await {
-            Ok(e) => Ok(EntriesStream::DevKMsg(e)),
-            Err(error::RMesgError::DevKMsgFileOpenError(s)) => {
-                eprintln!(
-                    "Falling back from device file to klogctl syscall due to error: {}",
-                    s
-                );
-                Ok(EntriesStream::KLogCtl(
-                    klog_entries_only_if_timestamp_enabled(clear)?,
-                ))
-            }
-            Err(e) => Err(e),
-        },
-        Backend::KLogCtl => Ok(EntriesStream::KLogCtl(
-            klog_entries_only_if_timestamp_enabled(clear)?,
-        )),
-        Backend::DevKMsg => Ok(EntriesStream::DevKMsg(
-            kmsgfile::KMsgEntriesStream::with_options(None, raw).await?,
-        )),
+    RmesgOptions {
+        backend: b,
+        clear,
+        raw,
+        ..Default::default()
     }
+    .stream()
+    .await
 }
 
-fn klog_entries_only_if_timestamp_enabled(
+/// Reads entries from several backends into one time-ordered view, e.g. when subsystems log
+/// to different places.
+///
+/// Entries read by more than one backend are kept once: they are told apart by their
+/// sequence number, and entries without one (like entries read through klogctl) by their
+/// timestamp and message. Entries are sorted by timestamp (those without one first).
+/// Entries with the same timestamp keep the order they were read in, with those that have
+/// a sequence number first.
+///
+/// When a backend can't be read, the entries from the others are still returned, along
+/// with the error for each backend that failed (see `MergedEntries::failures`). An error is
+/// only returned when no backend could be read.
+pub fn merged_entries(
+    backends: &[Backend],
     clear: bool,
-) -> Result<klogctl::KLogEntries, error::RMesgError> {
-    let log_timestamps_enabled = klogctl::klog_timestamps_enabled()?;
+) -> Result<MergedEntries, error::RMesgError> {
+    let mut entry_sets = Vec::with_capacity(backends.len());
+    let mut failures = Vec::new();
 
-    // ensure timestamps in logs
-    if !log_timestamps_enabled {
-        eprintln!("WARNING: Timestamps are disabled but tailing/following logs (as you've requested) requires them.");
-        eprintln!("Aboring program.");
-        eprintln!("You can enable timestamps by running the following: ");
-        eprintln!("  echo Y > /sys/module/printk/parameters/time");
-        return Err(error::RMesgError::KLogTimestampsDisabled);
+    for b in backends {
+        match log_entries(b.clone(), clear) {
+            Ok(entries) => entry_sets.push(entries),
+            Err(e) => failures.push((b.clone(), e)),
+        }
+    }
+
+    if entry_sets.is_empty() && !failures.is_empty() {
+        return Err(failures.swap_remove(0).1);
     }
 
-    klogctl::KLogEntries::with_options(clear, klogctl::SUGGESTED_POLL_INTERVAL)
+    Ok(MergedEntries {
+        entries: merge(entry_sets),
+        failures,
+    })
 }
 
-/**********************************************************************************/
-// Tests! Tests! Tests!
+/// What `merged_entries` read
+#[derive(Debug)]
+pub struct MergedEntries {
+    /// The entries of every backend that could be read, merged
+    pub entries: Vec<entry::Entry>,
 
-#[cfg(all(test, target_os = "linux"))]
-mod test {
-    use super::*;
-    #[cfg(feature = "async")]
-    use tokio_stream::StreamExt;
+    /// Each backend that couldn't be read (and so has no entries in `entries`), with why
+    pub failures: Vec<(Backend, error::RMesgError)>,
+}
 
-    #[test]
-    fn test_log_entries() {
-        let entries = log_entries(Backend::Default, false);
-        assert!(entries.is_ok(), "Response from kmsg not Ok");
-        assert!(!entries.unwrap().is_empty(), "Should have non-empty logs");
-    }
+fn merge(entry_sets: Vec<Vec<entry::Entry>>) -> Vec<entry::Entry> {
+    // Entries with sequence numbers go first, so the messages of all of them are known
+    // by the time entries without one are checked against them
+    let (mut merged, without_sequence_nums): (Vec<entry::Entry>, Vec<entry::Entry>) = entry_sets
+        .into_iter()
+        .flatten()
+        .partition(|e| e.sequence_num.is_some());
 
-    #[cfg(feature = "sync")]
-    #[test]
-    fn test_iterator() {
-        // uncomment below if you want to be extra-sure
-        //let enable_timestamp_result = kernel_log_timestamps_enable(true);
-        //assert!(enable_timestamp_result.is_ok());
+    let mut seen_sequence_nums = HashSet::new();
+    merged.retain(|e| seen_sequence_nums.insert(e.sequence_num));
 
-        // Don't clear the buffer. Poll every second.
-        let iterator_result = logs_iter(Backend::Default, false, false);
-        assert!(iterator_result.is_ok());
+    // klogctl keeps the space after the timestamp in messages
+    let message_key =
+        |e: &entry::Entry| (e.timestamp_from_system_start, e.message.trim().to_owned());
+    let mut seen_messages: HashSet<_> = merged.iter().map(message_key).collect();
+    merged.extend(
+        without_sequence_nums
+            .into_iter()
+            .filter(|e| seen_messages.insert(message_key(e))),
+    );
 
-        let iterator = iterator_result.unwrap();
+    merged.sort_by_key(|e| e.timestamp_from_system_start);
+    merged
+}
 
-        // Read 10 lines and quit
-        for (count, entry) in iterator.enumerate() {
-            assert!(entry.is_ok());
-            if count > 10 {
-                break;
-            }
-        }
+/// Counts the entries in the buffer at each level (a histogram of severities), e.g. to get
+/// an overview before reading them. Entries without a level (such as continuation lines)
+/// are counted under `None`. Levels without any entries are left out.
+/// Same as `RmesgOptions::level_histogram`.
+pub fn level_histogram(
+    b: Backend,
+    clear: bool,
+) -> Result<BTreeMap<Option<entry::LogLevel>, usize>, error::RMesgError> {
+    RmesgOptions {
+        backend: b,
+        clear,
+        ..Default::default()
+    }
+    .level_histogram()
+}
+
+/// Checks that the backend can be read, without reading anything.
+/// Same as `RmesgOptions::check_access`.
+pub fn check_access(b: Backend) -> Result<(), error::RMesgError> {
+    RmesgOptions::new().backend(b).check_access()
+}
+
+/// Checks that the buffer can be cleared (see `klogctl::klog_check_clear_access`), without
+/// clearing anything, e.g. to fail before printing what clearing would have cleared.
+///
+/// Only the kernel log buffer can be cleared, so the file backend is an
+/// `RMesgError::UnsupportedOperation`.
+pub fn check_clear_access(b: Backend) -> Result<(), error::RMesgError> {
+    match b {
+        Backend::Default | Backend::KLogCtl | Backend::DevKMsg => {
+            klogctl::klog_check_clear_access()
+        }
+        Backend::File(path) => Err(error::RMesgError::UnsupportedOperation(format!(
+            "Only the kernel log buffer can be cleared, not the file {}",
+            path.display()
+        ))),
+    }
+}
+
+/// Counts the records in the buffer, without parsing them like `log_entries` does.
+/// See `klogctl::klog_record_count` and `kmsgfile::kmsg_record_count` for how precise the
+/// counts are. With the file backend, the non-empty lines of the file are counted.
+pub fn buffer_record_count(b: Backend) -> Result<usize, error::RMesgError> {
+    match b {
+        Backend::Default => fall_back_to_klogctl(
+            kmsgfile::kmsg_record_count(None),
+            klogctl::klog_record_count,
+        ),
+        Backend::KLogCtl => klogctl::klog_record_count(),
+        Backend::DevKMsg => kmsgfile::kmsg_record_count(None),
+        Backend::File(path) => Ok(file::file_raw(&path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .count()),
+    }
+}
+
+fn count_levels(entries: &[entry::Entry]) -> BTreeMap<Option<entry::LogLevel>, usize> {
+    let mut histogram = BTreeMap::new();
+    for entry in entries {
+        *histogram.entry(entry.level).or_insert(0) += 1;
+    }
+    histogram
+}
+
+/// Writes the entries to `w` as newline-delimited JSON (NDJSON): one entry per line, like
+/// `rmesg --json` prints them. Flushes `w` once all are written.
+#[cfg(feature = "extra-traits")]
+pub fn write_ndjson<I, W>(entries: I, w: &mut W) -> io::Result<()>
+where
+    I: IntoIterator,
+    I::Item: Borrow<entry::Entry>,
+    W: Write,
+{
+    write_ndjson_with_terminator(entries, w, b"\n")
+}
+
+/// Writes the entries to `w` as JSON like `write_ndjson`, but ending each with `terminator`
+/// rather than a newline, e.g. `b"\0"` or `b"\r\n"` for consumers that expect those.
+#[cfg(feature = "extra-traits")]
+pub fn write_ndjson_with_terminator<I, W>(
+    entries: I,
+    w: &mut W,
+    terminator: &[u8],
+) -> io::Result<()>
+where
+    I: IntoIterator,
+    I::Item: Borrow<entry::Entry>,
+    W: Write,
+{
+    for entry in entries {
+        serde_json::to_writer(&mut *w, entry.borrow())?;
+        w.write_all(terminator)?;
+    }
+    w.flush()
+}
+
+/// Writes the entries of a stream (such as `RmesgOptions::stream`) to `w` as
+/// newline-delimited JSON, like `write_ndjson`, until the stream ends or returns an error.
+///
+/// Since streams can follow the buffer indefinitely, `w` is flushed after every entry, so
+/// that each one gets through as soon as it is logged.
+#[cfg(all(feature = "async", feature = "extra-traits"))]
+pub async fn write_ndjson_stream<S, W>(entries: S, w: &mut W) -> Result<(), error::RMesgError>
+where
+    S: Stream<Item = Result<entry::Entry, error::RMesgError>> + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    write_ndjson_stream_with_terminator(entries, w, b"\n").await
+}
+
+/// Writes the entries of a stream like `write_ndjson_stream`, but ending each with
+/// `terminator` rather than a newline (see `write_ndjson_with_terminator`).
+#[cfg(all(feature = "async", feature = "extra-traits"))]
+pub async fn write_ndjson_stream_with_terminator<S, W>(
+    mut entries: S,
+    w: &mut W,
+    terminator: &[u8],
+) -> Result<(), error::RMesgError>
+where
+    S: Stream<Item = Result<entry::Entry, error::RMesgError>> + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    use futures::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    // One buffer for all the lines
+    let mut line = Vec::new();
+    while let Some(entry) = entries.next().await {
+        line.clear();
+        serde_json::to_writer(&mut line, &entry?).map_err(io::Error::other)?;
+        line.extend_from_slice(terminator);
+
+        w.write_all(&line).await?;
+        w.flush().await?;
+    }
+    Ok(())
+}
+
+/// Clears the kernel log buffer, e.g. before starting a fresh capture, and reports how many
+/// entries were cleared (see `klogctl::klog_clear` for how precise the count is, and the
+/// privilege clearing takes).
+///
+/// All backends read the same buffer, and /dev/kmsg has no way to clear it, so this is the
+/// klogctl syscall for every backend. Note that readers of /dev/kmsg (including the DevKMsg
+/// backend) still see cleared entries, since it is only cleared for readers of the klogctl
+/// syscall.
+#[cfg(feature = "async")]
+pub async fn clear_buffer(b: Backend) -> Result<klogctl::ClearReport, error::RMesgError> {
+    match b {
+        Backend::Default | Backend::KLogCtl | Backend::DevKMsg => klogctl::klog_clear(),
+        Backend::File(path) => Err(error::RMesgError::UnsupportedOperation(format!(
+            "Only the kernel log buffer can be cleared, not the file {}",
+            path.display()
+        ))),
+    }
+}
+
+/// Clears the kernel log buffer like `clear_buffer`, but returns the entries the filter
+/// accepts, so nothing important is lost to the clear (see
+/// `klogctl::klog_clear_and_return_filtered`). E.g. filter on a minimum level of warning to
+/// clear out debug noise while keeping the warnings and errors.
+pub fn clear_and_return_filtered(
+    b: Backend,
+    filter: filter::EntryFilter,
+) -> Result<Vec<entry::Entry>, error::RMesgError> {
+    match b {
+        Backend::Default | Backend::KLogCtl | Backend::DevKMsg => {
+            klogctl::klog_clear_and_return_filtered(&filter)
+        }
+        Backend::File(path) => Err(error::RMesgError::UnsupportedOperation(format!(
+            "Only the kernel log buffer can be cleared, not the file {}",
+            path.display()
+        ))),
+    }
+}
+
+/// Returns the console log level (see `klogctl::klog_console_level`)
+pub fn get_console_level() -> Result<entry::LogLevel, error::RMesgError> {
+    klogctl::klog_console_level()
+}
+
+/// Sets the console log level, so the kernel prints messages at this level and more severe
+/// to the console (see `klogctl::klog_console_level_set`, also for the privilege it takes).
+pub fn set_console_level(level: entry::LogLevel) -> Result<(), error::RMesgError> {
+    klogctl::klog_console_level_set(level)
+}
+
+/// Enables or disables printing messages to the console (see
+/// `klogctl::klog_console_output_set`).
+pub fn set_console_output(enabled: bool) -> Result<(), error::RMesgError> {
+    klogctl::klog_console_output_set(enabled)
+}
+
+/// Returns the backend that reading with this backend uses: `Backend::Default` resolves
+/// to `Backend::DevKMsg` when /dev/kmsg can be opened, and to `Backend::KLogCtl` otherwise.
+/// Other backends resolve to themselves.
+pub fn resolve_backend(b: Backend) -> Backend {
+    resolve_backend_with_kmsg_path(b, kmsgfile::DEV_KMSG_PATH)
+}
+
+fn resolve_backend_with_kmsg_path(b: Backend, kmsg_path: &str) -> Backend {
+    match b {
+        Backend::Default => match fs::File::open(kmsg_path) {
+            Ok(_) => Backend::DevKMsg,
+            Err(_) => Backend::KLogCtl,
+        },
+        b => b,
+    }
+}
+
+// Backend::Default reads /dev/kmsg first, and falls back to klogctl when the file can't be
+// opened. If klogctl fails too, the original error is the more useful one to return.
+fn fall_back_to_klogctl<T>(
+    devkmsg_result: Result<T, error::RMesgError>,
+    klogctl: impl FnOnce() -> Result<T, error::RMesgError>,
+) -> Result<T, error::RMesgError> {
+    match devkmsg_result {
+        Err(e @ error::RMesgError::DevKMsgFileOpenError(_))
+        | Err(e @ error::RMesgError::DeviceNotFound(_))
+        | Err(e @ error::RMesgError::OperationNotPermitted(_)) => {
+            eprintln!(
+                "Falling back from device file to klogctl syscall due to error: {}",
+                e
+            );
+            klogctl().map_err(|klogctl_error| {
+                eprintln!("Falling back to klogctl failed too: {}", klogctl_error);
+                e
+            })
+        }
+        result => result,
+    }
+}
+
+// `poll` is the poll interval (if not the suggested one) and the interval to back off up to
+fn klog_entries_only_if_timestamp_enabled(
+    clear: bool,
+    buffer_size: Option<usize>,
+    poll: (Option<Duration>, Option<Duration>),
+    filter: filter::EntryFilter,
+) -> Result<klogctl::KLogEntries, error::RMesgError> {
+    let log_timestamps_enabled = klogctl::klog_timestamps_enabled()?;
+
+    // ensure timestamps in logs
+    if !log_timestamps_enabled {
+        eprintln!("WARNING: Timestamps are disabled but tailing/following logs (as you've requested) requires them.");
+        eprintln!("Aboring program.");
+        eprintln!("You can enable timestamps by running the following: ");
+        eprintln!("  echo Y > /sys/module/printk/parameters/time");
+        return Err(error::RMesgError::KLogTimestampsDisabled);
+    }
+
+    let (poll_interval, max_poll_interval) = poll;
+    let entries = klogctl::KLogEntries::with_options(
+        clear,
+        buffer_size,
+        poll_interval.unwrap_or(klogctl::SUGGESTED_POLL_INTERVAL),
+        filter,
+    )?;
+    Ok(match max_poll_interval {
+        Some(max_poll_interval) => entries.with_max_poll_interval(max_poll_interval),
+        None => entries,
+    })
+}
+
+/// Computes the (wall-clock) time at which the system booted, from the given source: by
+/// default, by subtracting the uptime in /proc/uptime from the current time.
+///
+/// Combine with `Entry::wall_clock_time` to get absolute timestamps for entries.
+/// Note that changes to the system clock since boot (NTP adjustments, manual changes,
+/// time spent suspended) skew the result, the same way they do for `dmesg -T`. See
+/// `ClockSource` for which source is least skewed by time spent suspended.
+pub fn system_boot_time(source: ClockSource) -> Result<SystemTime, error::RMesgError> {
+    let now = SystemTime::now();
+    match source {
+        ClockSource::Uptime => boot_time_from_uptime(&fs::read_to_string(PROC_UPTIME_PATH)?, now),
+        ClockSource::ProcStatBtime => {
+            boot_time_from_proc_stat(&fs::read_to_string(PROC_STAT_PATH)?)
+        }
+        #[cfg(target_os = "linux")]
+        ClockSource::BootTime => boot_time_from_clock(libc::CLOCK_BOOTTIME, now),
+        #[cfg(not(target_os = "linux"))]
+        ClockSource::BootTime => Err(error::RMesgError::NotSupportedOnThisPlatform(
+            "CLOCK_BOOTTIME is only available on Linux".to_owned(),
+        )),
+        ClockSource::Monotonic => boot_time_from_clock(libc::CLOCK_MONOTONIC, now),
+    }
+}
+
+// /proc/uptime looks like this (uptime and idle time, in seconds):
+// 350735.47 234388.90
+fn parse_uptime(uptime: &str) -> Result<Duration, error::RMesgError> {
+    let uptime_secs = match uptime.split_whitespace().next() {
+        Some(secs) => common::parse_fragment::<f64>(secs, uptime)?,
+        None => {
+            return Err(error::RMesgError::InternalError(format!(
+                "Unable to find uptime in: {}",
+                uptime
+            )))
+        }
+    };
+
+    Duration::try_from_secs_f64(uptime_secs).map_err(|e| {
+        error::RMesgError::InternalError(format!("Invalid uptime {}: {}", uptime_secs, e))
+    })
+}
+
+fn boot_time_from_uptime(uptime: &str, now: SystemTime) -> Result<SystemTime, error::RMesgError> {
+    boot_time_before(now, parse_uptime(uptime)?)
+}
+
+// /proc/stat has the time of boot (in seconds since the epoch) on a line of its own:
+// btime 1699649264
+fn boot_time_from_proc_stat(stat: &str) -> Result<SystemTime, error::RMesgError> {
+    let btime = match stat.lines().find_map(|line| line.strip_prefix("btime ")) {
+        Some(btime) => common::parse_fragment::<u64>(btime, stat)?,
+        None => {
+            return Err(error::RMesgError::InternalError(
+                "Unable to find btime in /proc/stat".to_owned(),
+            ))
+        }
+    };
+
+    match SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(btime)) {
+        Some(boot_time) => Ok(boot_time),
+        None => Err(error::RMesgError::UnableToAddDurationToSystemTime),
+    }
+}
+
+fn boot_time_from_clock(
+    clock: libc::clockid_t,
+    now: SystemTime,
+) -> Result<SystemTime, error::RMesgError> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: clock_gettime only writes a timespec into the one passed in
+    if unsafe { libc::clock_gettime(clock, &mut time) } != 0 {
+        return Err(error::RMesgError::InternalError(format!(
+            "Unable to read clock {}: {}",
+            clock,
+            errno::errno()
+        )));
+    }
+
+    boot_time_before(now, Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+fn boot_time_before(
+    now: SystemTime,
+    since_boot: Duration,
+) -> Result<SystemTime, error::RMesgError> {
+    // Guard against a clock that can't represent a time this far back
+    match now.checked_sub(since_boot) {
+        Some(boot_time) => Ok(boot_time),
+        None => Err(error::RMesgError::UnableToAddDurationToSystemTime),
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "async")]
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn test_boot_time_from_uptime() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        // uptime is a float, so allow for rounding
+        let boot_time = boot_time_from_uptime("350735.47 234388.90\n", now).unwrap();
+        let expected = SystemTime::UNIX_EPOCH + Duration::from_millis(1_699_649_264_530);
+        let skew = match boot_time.duration_since(expected) {
+            Ok(d) => d,
+            Err(e) => e.duration(),
+        };
+        assert!(skew < Duration::from_millis(1));
+
+        assert!(boot_time_from_uptime("", now).is_err());
+        assert!(boot_time_from_uptime("notanumber 234388.90", now).is_err());
+    }
+
+    fn skew(a: SystemTime, b: SystemTime) -> Duration {
+        match a.duration_since(b) {
+            Ok(d) => d,
+            Err(e) => e.duration(),
+        }
+    }
+
+    #[test]
+    fn test_boot_time_from_proc_files() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let expected = SystemTime::UNIX_EPOCH + Duration::from_secs(1_699_649_264);
+
+        let uptime = fs::read_to_string(fixture("proc_uptime.txt")).unwrap();
+        let boot_time = boot_time_from_uptime(&uptime, now).unwrap();
+        assert!(skew(boot_time, expected) < Duration::from_secs(1));
+
+        let stat = fs::read_to_string(fixture("proc_stat.txt")).unwrap();
+        assert_eq!(boot_time_from_proc_stat(&stat).unwrap(), expected);
+
+        assert!(boot_time_from_proc_stat("cpu  224293 0 41812\n").is_err());
+        assert!(boot_time_from_proc_stat("btime soon\n").is_err());
+    }
+
+    #[test]
+    fn test_system_boot_time() {
+        let now = SystemTime::now();
+        let boot_times: Vec<SystemTime> = [
+            ClockSource::Uptime,
+            ClockSource::ProcStatBtime,
+            ClockSource::BootTime,
+            ClockSource::Monotonic,
+        ]
+        .iter()
+        .map(|source| system_boot_time(*source).unwrap())
+        .collect();
+        assert!(boot_times.iter().all(|boot_time| *boot_time < now));
+
+        // /proc/uptime, /proc/stat and CLOCK_BOOTTIME count the same time
+        assert!(skew(boot_times[0], boot_times[1]) < Duration::from_secs(2));
+        assert!(skew(boot_times[0], boot_times[2]) < Duration::from_secs(1));
+        // CLOCK_MONOTONIC doesn't count time suspended, so it can only boot later
+        assert!(boot_times[3] + Duration::from_secs(1) > boot_times[2]);
+    }
+
+    #[test]
+    fn test_log_entries() {
+        let entries = log_entries(Backend::Default, false);
+        assert!(entries.is_ok(), "Response from kmsg not Ok");
+        assert!(!entries.unwrap().is_empty(), "Should have non-empty logs");
+    }
+
+    #[test]
+    fn test_file_backend() {
+        let b = Backend::File(fixture("kern.log"));
+
+        let entries = log_entries(b.clone(), false);
+        assert_eq!(entries.unwrap().len(), 4);
+
+        let tail = last_n_entries(b.clone(), false, 1);
+        assert_eq!(
+            tail.unwrap()[0].message,
+            "Out of memory: Killed process 1234 (stress)"
+        );
+
+        assert!(logs_raw(b.clone(), false).unwrap().contains("kernel:"));
+        assert_eq!(resolve_backend(b.clone()), b);
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+            .iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_backend_from_str_and_display() {
+        for backend in [
+            Backend::Default,
+            Backend::KLogCtl,
+            Backend::DevKMsg,
+            Backend::File(PathBuf::from("/var/log/kern.log")),
+        ] {
+            assert_eq!(backend.to_string().parse::<Backend>().unwrap(), backend);
+        }
+
+        assert_eq!("klogctl".parse::<Backend>().unwrap(), Backend::KLogCtl);
+        assert_eq!(Backend::DevKMsg.to_string(), "devkmsg");
+
+        for unknown in ["", "kmsg", "KLOGCTL", "file:"] {
+            assert!(matches!(
+                unknown.parse::<Backend>(),
+                Err(error::RMesgError::UnknownBackend(s)) if s == unknown
+            ));
+        }
+    }
+
+    #[test]
+    fn test_options_builder() {
+        assert_eq!(RmesgOptions::new(), RmesgOptions::default());
+        assert_eq!(RmesgOptions::new().backend, Backend::Default);
+        assert_eq!(
+            RmesgOptions::new().entry_filter(),
+            &filter::EntryFilter::default()
+        );
+
+        let options = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .min_level(entry::LogLevel::Warning)
+            .facilities(vec![entry::LogFacility::Kern])
+            .since(Duration::from_secs(5))
+            .source(filter::MessageSource::Kernel)
+            .clear(false);
+        assert_eq!(options.backend, Backend::DevKMsg);
+        assert_eq!(
+            options.entry_filter(),
+            &filter::EntryFilter {
+                min_level: Some(entry::LogLevel::Warning),
+                facilities: Some(vec![entry::LogFacility::Kern].into_iter().collect()),
+                since: Some(Duration::from_secs(5)),
+                source: Some(filter::MessageSource::Kernel),
+                ..Default::default()
+            }
+        );
+
+        // Setting the whole filter replaces criteria set before
+        let options = options.filter(filter::EntryFilter::default());
+        assert_eq!(options.entry_filter(), &filter::EntryFilter::default());
+    }
+
+    #[test]
+    fn test_options_kmsg_path() {
+        let options = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("kmsg.txt"));
+        assert_eq!(options.clone().entries().unwrap().len(), 4);
+        assert!(options
+            .logs_raw()
+            .unwrap()
+            .starts_with("5,0,0,-;Linux version"));
+
+        let result = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("nonexistent"))
+            .entries();
+        assert!(matches!(
+            result,
+            Err(error::RMesgError::DeviceNotFound(path)) if path == fixture("nonexistent")
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_options_kmsg_path_stream() {
+        let entries: Vec<_> = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("kmsg.txt"))
+            .stream()
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(entries.len(), 4);
+    }
+
+    #[test]
+    fn test_options_escape_messages() {
+        let options = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("kmsg_escapes.txt"));
+
+        let messages = |options: RmesgOptions| -> Vec<String> {
+            options
+                .entries()
+                .unwrap()
+                .into_iter()
+                .map(|e| e.message)
+                .collect()
+        };
+        assert_eq!(
+            messages(options.clone()),
+            vec![
+                "usb 1-1: Product: \x1b[2J\x1b[31mEvil\x07",
+                "usb 1-1: Manufacturer: Plain"
+            ]
+        );
+        assert_eq!(
+            messages(options.clone().escape_messages(true)),
+            vec![
+                "usb 1-1: Product: \\x1b[2J\\x1b[31mEvil\\x07",
+                "usb 1-1: Manufacturer: Plain"
+            ]
+        );
+
+        // Truncated before being escaped, so escapes aren't cut short
+        assert_eq!(
+            messages(options.escape_messages(true).max_message_len(22)),
+            vec!["usb 1-1: Product: \\x1b[2J…", "usb 1-1: Manufacturer:…"]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_options_escape_messages_stream() {
+        let messages: Vec<String> = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("kmsg_escapes.txt"))
+            .escape_messages(true)
+            .stream()
+            .await
+            .unwrap()
+            .map(|e| e.unwrap().message)
+            .collect()
+            .await;
+        assert_eq!(messages[0], "usb 1-1: Product: \\x1b[2J\\x1b[31mEvil\\x07");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_options_heartbeat() {
+        let items: Vec<_> = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("kmsg.txt"))
+            .heartbeat(Duration::from_secs(60))
+            .stream_with_heartbeat()
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(items.len(), 4);
+        assert!(items
+            .iter()
+            .all(|item| matches!(item, Ok(heartbeat::StreamItem::Entry(_)))));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_options_channel_capacity() {
+        let entries: Vec<_> = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("kmsg.txt"))
+            .channel_capacity(16)
+            .stream()
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(entries.len(), 4);
+        assert!(entries.iter().all(|entry| entry.is_ok()));
+    }
+
+    #[test]
+    fn test_options_poll_interval() {
+        let options = RmesgOptions::new()
+            .backend(Backend::KLogCtl)
+            .poll_interval(Duration::from_secs(1))
+            .max_poll_interval(Duration::from_secs(30));
+        assert_eq!(options.poll_interval, Some(Duration::from_secs(1)));
+        assert_eq!(options.max_poll_interval, Some(Duration::from_secs(30)));
+
+        let options = RmesgOptions::new();
+        assert_eq!(options.poll_interval, None);
+        assert_eq!(options.max_poll_interval, None);
+    }
+
+    #[test]
+    fn test_check_access() {
+        assert!(check_access(Backend::Default).is_ok());
+        assert!(check_access(Backend::KLogCtl).is_ok());
+        assert!(check_access(Backend::File(fixture("klog.txt"))).is_ok());
+        assert!(matches!(
+            check_access(Backend::File(fixture("nonexistent.txt"))),
+            Err(error::RMesgError::IOError(_))
+        ));
+
+        let options = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("kmsg.txt"));
+        assert!(options.check_access().is_ok());
+        // Nothing was read, so everything is still there to be
+        assert_eq!(options.entries().unwrap().len(), 4);
+
+        assert!(matches!(
+            RmesgOptions::new()
+                .backend(Backend::DevKMsg)
+                .kmsg_path(fixture("nonexistent.txt"))
+                .check_access(),
+            Err(error::RMesgError::DeviceNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_buffer_record_count() {
+        assert_eq!(
+            buffer_record_count(Backend::File(fixture("klog.txt"))).unwrap(),
+            4
+        );
+
+        let count = buffer_record_count(Backend::Default).unwrap();
+        assert!(count > 0, "Should have non-empty logs");
+    }
+
+    #[test]
+    fn test_raw_records() {
+        let records = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("kmsg.txt"))
+            .raw_records()
+            .unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(
+            records[0],
+            "5,0,0,-;Linux version 6.1.0-21-amd64 (debian-kernel@lists.debian.org)"
+        );
+        // The continuation line stays with its record
+        assert_eq!(
+            records[2],
+            "6,2,1283919,-;usb 1-1: new high-speed USB device number 2 using xhci_hcd\n SUBSYSTEM=usb"
+        );
+        assert_eq!(
+            records[3],
+            "3,3,227000001,-;Out of memory: Killed process 1234 (stress)"
+        );
+
+        // Files in the /dev/kmsg format split the same way, others into lines
+        let records = raw_records(Backend::File(fixture("kmsg.txt")), false).unwrap();
+        assert_eq!(records.len(), 4);
+        let records = raw_records(Backend::File(fixture("klog.txt")), false).unwrap();
+        assert_eq!(records.len(), 4);
+
+        assert_eq!(
+            split_records("<6>[0.1] one\r\n\n<6>[0.2] two", false),
+            vec!["<6>[0.1] one", "<6>[0.2] two"]
+        );
+        assert_eq!(
+            split_records("6,1,0,-;one\n DEVICE=+usb:1-1\n6,2,0,-;two\n", true),
+            vec!["6,1,0,-;one\n DEVICE=+usb:1-1", "6,2,0,-;two"]
+        );
+    }
+
+    #[test]
+    fn test_boot_starts() {
+        let mut boots = BootStarts::default();
+        let mut entry = numbered_entries(1).next().unwrap().unwrap();
+        let mut starts_boot = |timestamp: Option<u64>, message: &str| {
+            entry.timestamp_from_system_start = timestamp.map(Duration::from_millis);
+            entry.message = message.to_owned();
+            boots.starts_boot(&entry)
+        };
+
+        assert!(!starts_boot(Some(50_000), "first"));
+        // Slightly out of order, as between CPUs
+        assert!(!starts_boot(Some(49_500), "from another CPU"));
+        assert!(!starts_boot(None, "without a timestamp"));
+        assert!(starts_boot(Some(10_000), "after a reset"));
+        assert!(starts_boot(Some(20_000), "Linux version 6.1.0"));
+        assert!(!starts_boot(Some(20_000), "Linux versions"));
+    }
+
+    #[test]
+    fn test_time_span() {
+        // Out of order, and with an entry without a timestamp
+        let entries = log_entries(Backend::File(fixture("dmesg_unsorted.txt")), false).unwrap();
+        assert_eq!(
+            time_span(&entries),
+            Some((Duration::from_secs(1), Duration::from_secs(5)))
+        );
+        assert_eq!(
+            time_span(&entries[1..2]),
+            Some((Duration::from_secs(1), Duration::from_secs(1)))
+        );
+
+        let untimestamped: Vec<entry::Entry> = numbered_entries(3).map(Result::unwrap).collect();
+        assert_eq!(time_span(&untimestamped), None);
+        assert_eq!(time_span(&[]), None);
+    }
+
+    #[test]
+    fn test_options_current_boot_only() {
+        let options = RmesgOptions::new()
+            .backend(Backend::DevKMsg)
+            .kmsg_path(fixture("kmsg_previous_boots.txt"));
+        let messages = |entries: Vec<entry::Entry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.message).collect()
+        };
+
+        assert_eq!(options.clone().entries().unwrap().len(), 7);
+        // The last boot has no banner, but its timestamps start over
+        assert_eq!(
+            messages(options.clone().current_boot_only(true).entries().unwrap()),
+            vec!["logged in this boot", "logged on another CPU in this boot"]
+        );
+        assert_eq!(
+            messages(
+                options
+                    .clone()
+                    .current_boot_only(true)
+                    .last_n_entries(5)
+                    .unwrap()
+            ),
+            vec!["logged in this boot", "logged on another CPU in this boot"]
+        );
+        assert_eq!(
+            messages(options.current_boot_only(true).last_n_entries(1).unwrap()),
+            vec!["logged on another CPU in this boot"]
+        );
+
+        // Files needn't be in the order they were logged, so they're read whole
+        let options =
+            RmesgOptions::new().backend(Backend::File(fixture("dmesg_previous_boot.txt")));
+        assert_eq!(options.current_boot_only(true).entries().unwrap().len(), 3);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_collect_entries() {
+        let collected = collect_entries(Backend::File(fixture("dmesg.txt")), false)
+            .await
+            .unwrap();
+        let expected = log_entries(Backend::File(fixture("dmesg.txt")), false).unwrap();
+        assert!(!collected.is_empty());
+        assert_eq!(collected, expected);
+
+        assert!(
+            collect_entries(Backend::File(fixture("doesnotexist.log")), false)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_options_sorted() {
+        let options = RmesgOptions::new().backend(Backend::File(fixture("dmesg_unsorted.txt")));
+        let messages = |entries: Vec<entry::Entry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.message).collect()
+        };
+
+        assert_eq!(
+            messages(options.clone().entries().unwrap()),
+            vec![
+                "third, logged after the clock was adjusted",
+                "first",
+                "not a dmesg line",
+                "second",
+                "first too",
+            ]
+        );
+
+        // Equal timestamps keep their order, and the entry without one goes last
+        assert_eq!(
+            messages(options.clone().sorted(true).entries().unwrap()),
+            vec![
+                "first",
+                "first too",
+                "second",
+                "third, logged after the clock was adjusted",
+                "not a dmesg line",
+            ]
+        );
+
+        assert_eq!(
+            messages(options.sorted(true).last_n_entries(3).unwrap()),
+            vec!["first too", "second", "not a dmesg line"]
+        );
+    }
+
+    #[cfg(feature = "extra-traits")]
+    fn kmsg_fixture_entries() -> Vec<entry::Entry> {
+        let contents = fs::read_to_string(fixture("kmsg.txt")).unwrap();
+        kmsgfile::records(&contents)
+            .map(|record| kmsgfile::parse_record(record).unwrap())
+            .collect()
+    }
+
+    #[cfg(feature = "extra-traits")]
+    #[test]
+    fn test_write_ndjson() {
+        let entries = kmsg_fixture_entries();
+        let mut written = Vec::new();
+        write_ndjson(&entries, &mut written).unwrap();
+
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.ends_with('\n'));
+        let parsed: Vec<entry::Entry> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(parsed, entries);
+
+        let mut written = Vec::new();
+        write_ndjson(Vec::<entry::Entry>::new(), &mut written).unwrap();
+        assert!(written.is_empty());
+    }
+
+    #[cfg(feature = "extra-traits")]
+    #[test]
+    fn test_write_ndjson_with_terminator() {
+        let entries = kmsg_fixture_entries();
+        let mut written = Vec::new();
+        write_ndjson_with_terminator(&entries[..2], &mut written, b"\0").unwrap();
+
+        let mut expected = serde_json::to_vec(&entries[0]).unwrap();
+        expected.push(0);
+        expected.extend(serde_json::to_vec(&entries[1]).unwrap());
+        expected.push(0);
+        assert_eq!(written, expected);
+        assert!(!written.contains(&b'\n'));
+    }
+
+    #[cfg(all(feature = "async", feature = "extra-traits"))]
+    #[tokio::test]
+    async fn test_write_ndjson_stream_with_terminator() {
+        let entries = kmsg_fixture_entries();
+        let mut written = Vec::new();
+        let stream = futures::stream::iter(entries.clone().into_iter().map(Ok));
+        write_ndjson_stream_with_terminator(stream, &mut written, b"\r\n")
+            .await
+            .unwrap();
+
+        let written = String::from_utf8(written).unwrap();
+        assert_eq!(written.matches("\r\n").count(), entries.len());
+        assert!(written.ends_with("}\r\n"));
+    }
+
+    #[cfg(all(feature = "async", feature = "extra-traits"))]
+    #[tokio::test]
+    async fn test_write_ndjson_stream() {
+        let entries = kmsg_fixture_entries();
+        let mut written = Vec::new();
+        let stream = futures::stream::iter(entries.clone().into_iter().map(Ok));
+        write_ndjson_stream(stream, &mut written).await.unwrap();
+
+        let parsed: Vec<entry::Entry> = String::from_utf8(written)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(parsed, entries);
+
+        // What was written before an error stays written
+        let mut written = Vec::new();
+        let stream = futures::stream::iter(vec![
+            Ok(entries[0].clone()),
+            Err(error::RMesgError::InternalError("Read failed".to_owned())),
+            Ok(entries[1].clone()),
+        ]);
+        let result = write_ndjson_stream(stream, &mut written).await;
+        assert!(matches!(result, Err(error::RMesgError::InternalError(_))));
+        assert_eq!(String::from_utf8(written).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_options_filter_behavior() {
+        let options = RmesgOptions::new().backend(Backend::File(fixture("klog.txt")));
+        assert_eq!(options.clone().entries().unwrap().len(), 4);
+
+        let errors = options
+            .clone()
+            .min_level(entry::LogLevel::Error)
+            .entries()
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].level, Some(entry::LogLevel::Error));
+
+        let usb = options
+            .clone()
+            .message_regex(regex::Regex::new("usb").unwrap())
+            .last_n_entries(5)
+            .unwrap();
+        assert!(!usb.is_empty());
+        assert!(usb.iter().all(|e| e.message.contains("usb")));
+
+        let not_usb = options
+            .message_regex(regex::Regex::new("usb").unwrap())
+            .invert_message_regex(true)
+            .entries()
+            .unwrap();
+        assert_eq!(usb.len() + not_usb.len(), 4);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_options_raw_lines() {
+        let contents = fs::read_to_string(fixture("klog.txt")).unwrap();
+        let options = RmesgOptions::new().backend(Backend::File(fixture("klog.txt")));
+
+        let entries: Vec<_> = options.clone().raw_lines(true).iter().unwrap().collect();
+        assert_eq!(entries.len(), 4);
+        for (entry, line) in entries.iter().zip(contents.lines()) {
+            assert_eq!(entry.as_ref().unwrap().raw_line.as_deref(), Some(line));
+        }
+
+        let entries = options.entries().unwrap();
+        assert!(entries.iter().all(|e| e.raw_line.is_none()));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_max_entries_iter() {
+        let options = RmesgOptions::new().backend(Backend::File(fixture("klog.txt")));
+        assert_eq!(options.clone().iter().unwrap().count(), 4);
+        assert_eq!(options.clone().max_entries(2).iter().unwrap().count(), 2);
+        assert_eq!(options.clone().max_entries(0).iter().unwrap().count(), 0);
+        assert_eq!(options.max_entries(10).iter().unwrap().count(), 4);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_max_entries_stream() {
+        let options = RmesgOptions::new().backend(Backend::File(fixture("klog.txt")));
+
+        let entries: Vec<_> = options.clone().stream().await.unwrap().collect().await;
+        assert_eq!(entries.len(), 4);
+
+        let mut stream = options.max_entries(2).stream().await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap().message,
+            " Linux version 6.1.0-21-amd64 (debian-kernel@lists.debian.org)"
+        );
+        assert!(stream.next().await.is_some());
+        assert!(stream.next().await.is_none());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_follow_new_stream() {
+        // Everything in a file was there before following started
+        let options = RmesgOptions::new()
+            .backend(Backend::File(fixture("klog.txt")))
+            .follow_new(true);
+        assert!(options.stream().await.unwrap().next().await.is_none());
+    }
+
+    fn entry_from(sequence_num: Option<usize>, timestamp_secs: u64, message: &str) -> entry::Entry {
+        entry::Entry {
+            sequence_num,
+            timestamp_from_system_start: Some(Duration::from_secs(timestamp_secs)),
+            message: message.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let devkmsg_entries = vec![
+            entry_from(Some(1), 1, "first"),
+            entry_from(Some(2), 2, "second"),
+            entry_from(Some(4), 4, "fourth"),
+        ];
+        let other_entries = vec![
+            entry_from(Some(2), 2, "second"),
+            entry_from(Some(3), 3, "third"),
+            entry_from(Some(4), 4, "fourth"),
+            entry_from(Some(5), 0, "fifth, but logged earliest"),
+        ];
+        let merged = merge(vec![devkmsg_entries.clone(), other_entries]);
+        assert_eq!(sequence_nums(merged), vec![5, 1, 2, 3, 4]);
+
+        // klogctl entries have no sequence numbers, and a leading space in messages
+        let klogctl_entries = vec![entry_from(None, 1, " first"), entry_from(None, 3, " third")];
+        for entry_sets in [
+            vec![devkmsg_entries.clone(), klogctl_entries.clone()],
+            vec![klogctl_entries, devkmsg_entries],
+        ] {
+            let merged = merge(entry_sets);
+            assert_eq!(
+                merged.iter().map(|e| e.message.trim()).collect::<Vec<_>>(),
+                vec!["first", "second", "third", "fourth"]
+            );
+            assert_eq!(merged[0].sequence_num, Some(1));
+        }
+
+        assert!(merge(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_merged_entries() {
+        let kern_log = Backend::File(fixture("kern.log"));
+        let missing = Backend::File(fixture("doesnotexist.log"));
+
+        // The same file twice is merged into one set of entries
+        let merged = merged_entries(&[kern_log.clone(), kern_log.clone()], false).unwrap();
+        assert_eq!(merged.entries.len(), 4);
+        assert!(merged.failures.is_empty());
+
+        // A backend failing leaves the others' entries, and is reported
+        let merged = merged_entries(&[missing.clone(), kern_log], false).unwrap();
+        assert_eq!(merged.entries.len(), 4);
+        assert_eq!(merged.failures.len(), 1);
+        assert_eq!(merged.failures[0].0, missing);
+        assert!(matches!(
+            merged.failures[0].1,
+            error::RMesgError::IOError(_)
+        ));
+
+        assert!(matches!(
+            merged_entries(&[missing], false),
+            Err(error::RMesgError::IOError(_))
+        ));
+        assert!(merged_entries(&[], false).unwrap().entries.is_empty());
+    }
+
+    #[test]
+    fn test_count_levels() {
+        let entry_at_level = |level| entry::Entry {
+            level,
+            message: "Test message".to_owned(),
+            ..Default::default()
+        };
+        let entries = vec![
+            entry_at_level(Some(entry::LogLevel::Info)),
+            entry_at_level(Some(entry::LogLevel::Error)),
+            entry_at_level(None),
+            entry_at_level(Some(entry::LogLevel::Info)),
+            entry_at_level(Some(entry::LogLevel::Emergency)),
+            entry_at_level(Some(entry::LogLevel::Info)),
+            entry_at_level(None),
+        ];
+
+        let histogram = count_levels(&entries);
+        assert_eq!(
+            histogram.into_iter().collect::<Vec<_>>(),
+            vec![
+                (None, 2),
+                (Some(entry::LogLevel::Emergency), 1),
+                (Some(entry::LogLevel::Error), 1),
+                (Some(entry::LogLevel::Info), 3),
+            ]
+        );
+
+        assert!(count_levels(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_level_histogram() {
+        let histogram = level_histogram(Backend::File(fixture("klog.txt")), false).unwrap();
+        assert_eq!(histogram.get(&Some(entry::LogLevel::Info)), Some(&2));
+        assert_eq!(histogram.get(&Some(entry::LogLevel::Warning)), None);
+        assert_eq!(histogram.values().sum::<usize>(), 4);
+    }
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_send() {
+        // Compiles only when these can be moved to other threads
+        assert_send::<klogctl::KLogEntries>();
+        assert_send::<file::FileEntries>();
+        assert_send::<coalesce::Coalesce<file::FileEntries>>();
+        assert_send::<dropped::DetectDroppedRecords<file::FileEntries>>();
+        assert_send::<multiline::GroupMultiline<file::FileEntries>>();
+        assert_send::<metrics::Metered<file::FileEntries>>();
+        assert_send::<RmesgOptions>();
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_send_iterators() {
+        assert_send::<kmsgfile::KMsgEntriesIter>();
+        assert_send::<EntriesIterator>();
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_send_streams() {
+        assert_send::<kmsgfile::KMsgEntriesStream>();
+        assert_send::<EntriesStream>();
+        assert_send::<heartbeat::Heartbeat<EntriesStream>>();
+        assert_send::<batch::Batched<EntriesStream>>();
+    }
+
+    #[test]
+    fn test_fall_back_to_klogctl() {
+        // /dev/kmsg works: klogctl isn't tried
+        let result = fall_back_to_klogctl(Ok("devkmsg"), || panic!("klogctl was tried"));
+        assert_eq!(result.unwrap(), "devkmsg");
+
+        // /dev/kmsg doesn't exist
+        let result = fall_back_to_klogctl(
+            Err(error::RMesgError::DeviceNotFound(PathBuf::from(
+                "/dev/kmsg",
+            ))),
+            || Ok("klogctl"),
+        );
+        assert_eq!(result.unwrap(), "klogctl");
+
+        // /dev/kmsg isn't readable
+        let result = fall_back_to_klogctl(
+            Err(error::RMesgError::OperationNotPermitted(
+                "Open File /dev/kmsg".to_owned(),
+            )),
+            || Ok("klogctl"),
+        );
+        assert_eq!(result.unwrap(), "klogctl");
+
+        // Both fail: the /dev/kmsg error is returned
+        let result: Result<&str, error::RMesgError> = fall_back_to_klogctl(
+            Err(error::RMesgError::OperationNotPermitted(
+                "Open File /dev/kmsg".to_owned(),
+            )),
+            || Err(error::RMesgError::KLogTimestampsDisabled),
+        );
+        assert!(matches!(
+            result,
+            Err(error::RMesgError::OperationNotPermitted(_))
+        ));
+
+        // Other errors don't fall back
+        let result = fall_back_to_klogctl(
+            Err(error::RMesgError::InternalError(
+                "Parsing failed".to_owned(),
+            )),
+            || Ok("klogctl"),
+        );
+        assert!(matches!(result, Err(error::RMesgError::InternalError(_))));
+    }
+
+    #[test]
+    fn test_resolve_backend() {
+        assert_eq!(resolve_backend(Backend::KLogCtl), Backend::KLogCtl);
+        assert_eq!(resolve_backend(Backend::DevKMsg), Backend::DevKMsg);
+        assert_ne!(resolve_backend(Backend::Default), Backend::Default);
+
+        assert_eq!(
+            resolve_backend_with_kmsg_path(Backend::Default, PROC_UPTIME_PATH),
+            Backend::DevKMsg
+        );
+        assert_eq!(
+            resolve_backend_with_kmsg_path(Backend::Default, "/dev/doesnotexist"),
+            Backend::KLogCtl
+        );
+        assert_eq!(
+            resolve_backend_with_kmsg_path(Backend::DevKMsg, "/dev/doesnotexist"),
+            Backend::DevKMsg
+        );
+    }
+
+    fn numbered_entries(
+        count: usize,
+    ) -> impl Iterator<Item = Result<entry::Entry, error::RMesgError>> {
+        (0..count).map(|i| {
+            Ok(entry::Entry {
+                sequence_num: Some(i),
+                message: format!("Message {}", i),
+                ..Default::default()
+            })
+        })
+    }
+
+    fn sequence_nums(entries: Vec<entry::Entry>) -> Vec<usize> {
+        entries.into_iter().filter_map(|e| e.sequence_num).collect()
+    }
+
+    #[test]
+    fn test_last_n() {
+        let filter = filter::EntryFilter::default();
+        let accepts = |e: &entry::Entry| filter.accepts(e);
+
+        let tail = last_n(numbered_entries(10), accepts, 3, false).unwrap();
+        assert_eq!(sequence_nums(tail), vec![7, 8, 9]);
+
+        let tail = last_n(numbered_entries(3), accepts, 10, false).unwrap();
+        assert_eq!(sequence_nums(tail), vec![0, 1, 2]);
+
+        let tail = last_n(numbered_entries(10), accepts, 0, false).unwrap();
+        assert!(tail.is_empty());
+
+        let tail = last_n(numbered_entries(0), accepts, 5, false).unwrap();
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn test_last_n_entries() {
+        let tail = last_n_entries(Backend::Default, false, 5);
+        assert!(tail.is_ok(), "Response from kmsg not Ok");
+
+        let tail = tail.unwrap();
+        assert!(!tail.is_empty(), "Should have non-empty logs");
+        assert!(tail.len() <= 5);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_iterator() {
+        // uncomment below if you want to be extra-sure
+        //let enable_timestamp_result = kernel_log_timestamps_enable(true);
+        //assert!(enable_timestamp_result.is_ok());
+
+        // Don't clear the buffer. Poll every second.
+        let iterator_result = logs_iter(Backend::Default, false, false);
+        assert!(iterator_result.is_ok());
+
+        let iterator = iterator_result.unwrap();
+
+        // Read 10 lines and quit
+        for (count, entry) in iterator.enumerate() {
+            assert!(entry.is_ok());
+            if count > 10 {
+                break;
+            }
+        }
     }
 
     #[cfg(feature = "async")]
@@ -219,8 +2462,8 @@ mod test {
 
         // Read 10 lines and quit
         let mut count: u32 = 0;
-        while let Some(entry) = stream.next().await {
-            assert!(entry.is_ok());
+        while let Some(item) = stream.next().await {
+            assert!(item.is_ok());
             count += 1;
             if count > 10 {
                 break;
@@ -228,3 +2471,44 @@ mod test {
         }
     }
 }
+
+#[cfg(all(test, not(target_os = "linux")))]
+mod test_unsupported_platform {
+    use super::*;
+
+    fn is_not_supported<T>(result: Result<T, error::RMesgError>) -> bool {
+        matches!(
+            result,
+            Err(error::RMesgError::NotSupportedOnThisPlatform(_))
+        )
+    }
+
+    #[test]
+    fn test_log_entries_not_supported() {
+        assert!(is_not_supported(log_entries(Backend::KLogCtl, false)));
+        assert!(is_not_supported(log_entries(Backend::DevKMsg, false)));
+        assert!(is_not_supported(log_entries(Backend::Default, false)));
+    }
+
+    #[test]
+    fn test_logs_raw_not_supported() {
+        assert!(is_not_supported(logs_raw(Backend::KLogCtl, false)));
+        assert!(is_not_supported(logs_raw(Backend::DevKMsg, false)));
+        assert!(is_not_supported(logs_raw(Backend::Default, false)));
+        assert!(is_not_supported(raw_records(Backend::DevKMsg, false)));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_logs_iter_not_supported() {
+        assert!(is_not_supported(logs_iter(Backend::DevKMsg, false, false)));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_logs_stream_not_supported() {
+        assert!(is_not_supported(
+            logs_stream(Backend::DevKMsg, false, false).await
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::entry::{Entry, LogLevel};
+use log::Level;
+
+/// The target of log records for entries without a facility
+pub const DEFAULT_TARGET: &str = "rmesg";
+
+/// The `log` level kernel log entries of this level are logged at.
+///
+/// `log` has fewer levels than the kernel, so emergencies, alerts and critical
+/// messages are logged as errors, and notices as info.
+pub fn log_level(level: LogLevel) -> Level {
+    match level {
+        LogLevel::Emergency | LogLevel::Alert | LogLevel::Critical | LogLevel::Error => {
+            Level::Error
+        }
+        LogLevel::Warning => Level::Warn,
+        LogLevel::Notice | LogLevel::Info => Level::Info,
+        LogLevel::Debug => Level::Debug,
+    }
+}
+
+/// Logs the entry's message through the `log` facade, with the target set to its
+/// facility name (e.g. "kern" or "daemon"), so loggers can filter on it.
+///
+/// Entries without a level are logged at `Level::Info`, and entries without a facility
+/// with the target `DEFAULT_TARGET`.
+pub fn log_entry(entry: &Entry) {
+    let level = entry.level.map(log_level).unwrap_or(Level::Info);
+
+    match entry.facility {
+        Some(facility) => log::log!(target: &facility.to_string(), level, "{}", entry.message),
+        None => log::log!(target: DEFAULT_TARGET, level, "{}", entry.message),
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::LogFacility;
+    use crate::test_util;
+    use log::{Log, Metadata, Record};
+
+    use std::sync::Mutex;
+
+    // Records (level, target, message) of everything logged
+    struct CapturingLogger {
+        records: Mutex<Vec<(Level, String, String)>>,
+    }
+
+    impl Log for CapturingLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.records.lock().unwrap().push((
+                record.level(),
+                record.target().to_owned(),
+                record.args().to_string(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger {
+        records: Mutex::new(Vec::new()),
+    };
+
+    fn entry(facility: Option<LogFacility>, level: Option<LogLevel>, message: &str) -> Entry {
+        Entry {
+            facility,
+            level,
+            ..test_util::entry(message)
+        }
+    }
+
+    #[test]
+    fn test_log_entry() {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        log_entry(&entry(
+            Some(LogFacility::Kern),
+            Some(LogLevel::Critical),
+            "Kernel panic imminent",
+        ));
+        log_entry(&entry(
+            Some(LogFacility::Daemon),
+            Some(LogLevel::Notice),
+            "Daemon started",
+        ));
+        log_entry(&entry(None, Some(LogLevel::Debug), "No facility"));
+        log_entry(&entry(Some(LogFacility::User), None, "No level"));
+
+        assert_eq!(
+            *LOGGER.records.lock().unwrap(),
+            vec![
+                (
+                    Level::Error,
+                    "kern".to_owned(),
+                    "Kernel panic imminent".to_owned()
+                ),
+                (
+                    Level::Info,
+                    "daemon".to_owned(),
+                    "Daemon started".to_owned()
+                ),
+                (Level::Debug, "rmesg".to_owned(), "No facility".to_owned()),
+                (Level::Info, "user".to_owned(), "No level".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(log_level(LogLevel::Emergency), Level::Error);
+        assert_eq!(log_level(LogLevel::Alert), Level::Error);
+        assert_eq!(log_level(LogLevel::Warning), Level::Warn);
+        assert_eq!(log_level(LogLevel::Notice), Level::Info);
+        assert_eq!(log_level(LogLevel::Debug), Level::Debug);
+    }
+}
@@ -1,91 +1,818 @@
 /// rmesg - a rust-based dmesg implementation.
 /// This CLI builds on top of the eponymous crate and provides a command-line utility.
 ///
-use clap::{Arg, Command};
-use futures_util::stream::StreamExt;
-use std::error::Error;
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use futures_util::stream::{Stream, StreamExt};
+use regex::Regex;
+use rmesg::entry::{Entry, LogFacility, LogLevel};
+use rmesg::error::RMesgError;
+use rmesg::filter::{EntryFilter, MessageSource};
+use rmesg::formatter::{EntryFormatter, TimeFormat};
+use std::collections::{BTreeMap, HashSet};
+use std::hash::Hash;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Debug)]
 struct Options {
+    subcommand: Option<Subcommand>,
+    console_level: Option<LogLevel>,
+    console_output: Option<bool>,
     follow: bool,
+    follow_new: bool,
     clear: bool,
     raw: bool,
+    json: bool,
+    logfmt: bool,
+    stats: bool,
+    table: bool,
+    time_format: TimeStyle,
+    color: ColorMode,
+    line_terminator: &'static str,
+    decode: bool,
+    raw_decode: bool,
+    escape: bool,
+    show_delta: bool,
+    show_seq: bool,
+    force_prefix: bool,
+    since: Option<TimeBound>,
+    until: Option<TimeBound>,
+    source: Option<MessageSource>,
+    levels: Option<HashSet<LogLevel>>,
+    facilities: Option<HashSet<LogFacility>>,
+    lines: Option<usize>,
+    grep: Option<Regex>,
+    grep_invert: bool,
+    buffer_size: Option<usize>,
+    max_rate: Option<u32>,
+    kmsg_path: Option<PathBuf>,
     backend: rmesg::Backend,
 }
 
+/// What to do instead of printing entries, as selected by a subcommand
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Subcommand {
+    /// Clear the buffer without printing it (like dmesg -C)
+    Clear,
+}
+
+/// One end of the --since/--until time window
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum TimeBound {
+    /// Time since system start, e.g. "120.5"
+    SinceBoot(Duration),
+    /// Time before now, e.g. "10 min ago"
+    Ago(Duration),
+}
+
+/// How timestamps are printed, as selected with -T or --time-format
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum TimeStyle {
+    /// Seconds since system start (the default)
+    SecondsSinceBoot,
+    Ctime,
+    Iso,
+    Reltime,
+    NoTime,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ColorMode {
+    /// Colorize only when stdout is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+/// Caps how many entries per second are printed while following, as selected with --max-rate
+struct RateLimiter {
+    max_per_second: u32,
+    window_start: Instant,
+    printed: u32,
+    suppressed: usize,
+}
+
+impl RateLimiter {
+    fn new(max_per_second: u32, now: Instant) -> Self {
+        Self {
+            max_per_second,
+            window_start: now,
+            printed: 0,
+            suppressed: 0,
+        }
+    }
+
+    /// Whether an entry arriving at `now` is printed. None when it is suppressed, otherwise
+    /// how many entries were suppressed since the last one printed, to note before printing it.
+    fn admit(&mut self, now: Instant) -> Option<usize> {
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.printed = 0;
+        }
+
+        if self.printed < self.max_per_second {
+            self.printed += 1;
+            Some(std::mem::take(&mut self.suppressed))
+        } else {
+            self.suppressed += 1;
+            None
+        }
+    }
+
+    /// How many entries were suppressed since the last one printed
+    fn suppressed(&self) -> usize {
+        self.suppressed
+    }
+
+    /// How many entries were suppressed since the last one printed or the last time this was
+    /// called, to note them without waiting for an entry to print
+    fn take_suppressed(&mut self) -> usize {
+        std::mem::take(&mut self.suppressed)
+    }
+}
+
+/// How often entries suppressed by --max-rate are noted while no entry gets printed
+const SUPPRESSED_NOTE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Notes suppressed entries on `notes` (stderr), so stdout stays parseable (e.g. with --json)
+fn note_suppressed<E: Write>(notes: &mut E, suppressed: usize) {
+    if suppressed > 0 {
+        // Like eprintln!, but without panicking when stderr is gone
+        let _ = writeln!(
+            notes,
+            "rmesg: suppressed {} messages over --max-rate",
+            suppressed
+        );
+    }
+}
+
+/// Prints entries in the format selected on the command line
+struct Printer {
+    json: bool,
+    logfmt: bool,
+    color: bool,
+    line_terminator: &'static str,
+    formatter: EntryFormatter,
+}
+
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let opts = parse_args();
+async fn main() -> ExitCode {
+    // Every code exit_code_for returns fits
+    ExitCode::from(run(parse_args()).await as u8)
+}
+
+/// Prints what the options select, and returns the exit code to exit with
+async fn run(opts: Options) -> i32 {
+    run_with(opts, rmesg::check_clear_access, &mut io::stdout()).await
+}
+
+/// Writes what the options select to `out`, checking -c can clear with `check_clear_access`
+async fn run_with<C, W>(opts: Options, check_clear_access: C, out: &mut W) -> i32
+where
+    C: FnOnce(rmesg::Backend) -> Result<(), RMesgError>,
+    W: Write,
+{
+    if opts.subcommand == Some(Subcommand::Clear) {
+        return match rmesg::clear_buffer(opts.backend.clone()).await {
+            Ok(_) => EXIT_SUCCESS,
+            Err(e) => {
+                eprintln!("Unable to clear the buffer: {}", e);
+
+                print_hint(&e);
+
+                exit_code_for(&e)
+            }
+        };
+    }
+
+    // Before anything is printed, so nothing suggests a clear that then fails
+    if opts.clear {
+        if let Err(e) = check_clear(&opts, check_clear_access) {
+            eprintln!("Unable to clear the buffer: {}", e);
+
+            print_hint(&e);
+
+            return exit_code_for(&e);
+        }
+    }
+
+    if let Some(level) = opts.console_level {
+        return match rmesg::set_console_level(level) {
+            Ok(()) => EXIT_SUCCESS,
+            Err(e) => {
+                eprintln!("Unable to set the console log level: {}", e);
+
+                print_hint(&e);
+
+                exit_code_for(&e)
+            }
+        };
+    }
+
+    if let Some(enabled) = opts.console_output {
+        return match rmesg::set_console_output(enabled) {
+            Ok(()) => EXIT_SUCCESS,
+            Err(e) => {
+                eprintln!("Unable to switch printing to the console: {}", e);
+
+                print_hint(&e);
+
+                exit_code_for(&e)
+            }
+        };
+    }
+
+    let mut printer = match Printer::new(&opts) {
+        Ok(printer) => printer,
+        Err(e) => {
+            eprintln!("Unable to determine system boot time: {}", e);
+            return exit_code_for(&e);
+        }
+    };
+
+    let filter = match entry_filter(&opts) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("Unable to determine time window: {}", e);
+            return exit_code_for(&e);
+        }
+    };
 
-    if !opts.follow {
-        nofollow(opts);
+    if opts.stats {
+        match rmesg_options(&opts, filter).level_histogram() {
+            Ok(histogram) => match print_stats(&histogram, out) {
+                Ok(()) => EXIT_SUCCESS,
+                Err(e) => report_print_error(e),
+            },
+            Err(e) => {
+                eprintln!("Unable to get log entries: {}", e);
+
+                print_hint(&e);
+
+                exit_code_for(&e)
+            }
+        }
+    } else if !opts.follow {
+        nofollow(opts, filter, &mut printer, out)
     } else {
-        let mut entries = match rmesg::logs_stream(opts.backend, opts.clear, opts.raw).await {
+        let options = rmesg_options(&opts, filter);
+
+        // Rather than find out once following starts
+        if let Err(e) = options.check_access() {
+            eprintln!("Unable to read logs: {}", e);
+
+            print_hint(&e);
+
+            return exit_code_for(&e);
+        }
+
+        let entries = match options.stream().await {
             Ok(entries) => entries,
             Err(e) => {
                 eprintln!("Unable to get logs stream: {}", e);
 
-                if let rmesg::error::RMesgError::OperationNotPermitted(_) = e {
-                    eprintln!("\nHint: Try using 'sudo' or run the program as root/superuser.");
-                }
+                print_hint(&e);
 
-                return Ok(());
+                return exit_code_for(&e);
             }
         };
 
-        while let Some(result) = entries.next().await {
-            match result {
-                Ok(entry) => println!("{}", entry),
-                Err(e) => {
-                    eprintln!("Unable to get logs stream: {}", e);
+        follow(entries, opts.max_rate, &mut printer, out, &mut io::stderr()).await
+    }
+}
+
+/// Prints entries as they get logged, at most `max_rate` a second (see --max-rate). Entries
+/// suppressed over the rate are noted on `notes` before the next entry printed, and every
+/// SUPPRESSED_NOTE_INTERVAL otherwise, so a burst followed by silence still gets noted.
+async fn follow<S, W, E>(
+    mut entries: S,
+    max_rate: Option<u32>,
+    printer: &mut Printer,
+    out: &mut W,
+    notes: &mut E,
+) -> i32
+where
+    S: Stream<Item = Result<Entry, RMesgError>> + Unpin,
+    W: Write,
+    E: Write,
+{
+    let mut limiter = max_rate.map(|max_rate| RateLimiter::new(max_rate, Instant::now()));
+    let mut note_ticks = tokio::time::interval_at(
+        tokio::time::Instant::now() + SUPPRESSED_NOTE_INTERVAL,
+        SUPPRESSED_NOTE_INTERVAL,
+    );
+
+    loop {
+        let result = tokio::select! {
+            result = entries.next() => match result {
+                Some(result) => result,
+                None => break,
+            },
+            _ = note_ticks.tick(), if limiter.is_some() => {
+                if let Some(limiter) = limiter.as_mut() {
+                    note_suppressed(notes, limiter.take_suppressed());
+                }
+                continue;
+            }
+        };
 
-                    if let rmesg::error::RMesgError::OperationNotPermitted(_) = e {
-                        eprintln!("\nHint: Try using 'sudo' or run the program as root/superuser.");
+        match result {
+            Ok(entry) => {
+                if let Some(limiter) = limiter.as_mut() {
+                    match limiter.admit(Instant::now()) {
+                        Some(suppressed) => note_suppressed(notes, suppressed),
+                        None => continue,
                     }
+                }
 
-                    return Ok(());
+                if let Err(e) = printer.write_entry(out, &entry) {
+                    return report_print_error(e);
                 }
             }
+            Err(e) => {
+                eprintln!("Unable to get logs stream: {}", e);
+
+                print_hint(&e);
+
+                return exit_code_for(&e);
+            }
         }
     }
 
-    Ok(())
+    if let Some(limiter) = limiter {
+        note_suppressed(notes, limiter.suppressed());
+    }
+
+    EXIT_SUCCESS
 }
 
-fn nofollow(opts: Options) {
-    if opts.raw {
-        match rmesg::logs_raw(opts.backend, opts.clear) {
-            Ok(raw) => {
-                print!("{}", raw)
+fn nofollow<W: Write>(
+    opts: Options,
+    filter: EntryFilter,
+    printer: &mut Printer,
+    out: &mut W,
+) -> i32 {
+    if opts.raw && opts.line_terminator != "\n" {
+        // Records rather than the raw buffer, to end each with the terminator
+        match rmesg_options(&opts, filter).raw_records() {
+            Ok(records) => {
+                for record in records {
+                    if let Err(e) = write!(out, "{}{}", record, opts.line_terminator) {
+                        return report_print_error(e);
+                    }
+                }
+                EXIT_SUCCESS
             }
             Err(e) => {
                 eprintln!("Unable to get raw logs: {}", e);
 
-                if let rmesg::error::RMesgError::OperationNotPermitted(_) = e {
-                    eprintln!("\nHint: Try using 'sudo' or run the program as root/superuser.");
-                }
+                print_hint(&e);
+
+                exit_code_for(&e)
+            }
+        }
+    } else if opts.raw {
+        match rmesg_options(&opts, filter).logs_raw() {
+            Ok(raw) => match out.write_all(raw.as_bytes()) {
+                Ok(()) => EXIT_SUCCESS,
+                Err(e) => report_print_error(e),
+            },
+            Err(e) => {
+                eprintln!("Unable to get raw logs: {}", e);
+
+                print_hint(&e);
+
+                exit_code_for(&e)
             }
         }
     } else {
-        match rmesg::log_entries(opts.backend, opts.clear) {
+        let options = rmesg_options(&opts, filter);
+        let entries = match opts.lines {
+            Some(n) => options.last_n_entries(n),
+            None => options.entries(),
+        };
+
+        match entries {
+            Ok(entries) if opts.table => match print_table(&entries, terminal_width(), out) {
+                Ok(()) => EXIT_SUCCESS,
+                Err(e) => report_print_error(e),
+            },
             Ok(entries) => {
                 for entry in entries {
-                    println!("{}", entry)
+                    if let Err(e) = printer.write_entry(out, &entry) {
+                        return report_print_error(e);
+                    }
                 }
+                EXIT_SUCCESS
             }
             Err(e) => {
                 eprintln!("Unable to get log entries: {}", e);
 
-                if let rmesg::error::RMesgError::OperationNotPermitted(_) = e {
-                    eprintln!("\nHint: Try using 'sudo' or run the program as root/superuser.");
-                }
+                print_hint(&e);
+
+                exit_code_for(&e)
             }
         }
     }
 }
 
-fn parse_args() -> Options {
-    let matches = Command::new("rmesg: A 'dmesg' port onto Rust")
+/// Checks that -c can clear the buffer. Only the klogctl backend clears, reading and clearing
+/// in one call, so with -c it is the default (see `options_from_matches`).
+fn check_clear<C>(opts: &Options, check_clear_access: C) -> Result<(), RMesgError>
+where
+    C: FnOnce(rmesg::Backend) -> Result<(), RMesgError>,
+{
+    match opts.backend {
+        rmesg::Backend::KLogCtl => check_clear_access(opts.backend.clone()),
+        _ => Err(RMesgError::UnsupportedOperation(format!(
+            "only the klogctl backend clears the buffer after printing it, not {}",
+            opts.backend
+        ))),
+    }
+}
+
+/// The options to read entries with, as selected on the command line
+fn rmesg_options(opts: &Options, filter: EntryFilter) -> rmesg::RmesgOptions {
+    let mut options = rmesg::RmesgOptions::new()
+        .backend(opts.backend.clone())
+        .clear(opts.clear)
+        .raw(opts.raw)
+        .escape_messages(opts.escape)
+        .follow_new(opts.follow_new)
+        .filter(filter);
+
+    if let Some(buffer_size) = opts.buffer_size {
+        options = options.buffer_size(buffer_size);
+    }
+    if let Some(kmsg_path) = &opts.kmsg_path {
+        options = options.kmsg_path(kmsg_path.clone());
+    }
+    options
+}
+
+/// Prints how many entries there are at each level, most severe first.
+/// Entries without a level are counted as unknown, printed only when there are some.
+fn print_stats<W: Write>(
+    histogram: &BTreeMap<Option<LogLevel>, usize>,
+    w: &mut W,
+) -> io::Result<()> {
+    for &level in LogLevel::all() {
+        writeln!(
+            w,
+            "{:<8}{}",
+            level,
+            histogram.get(&Some(level)).copied().unwrap_or(0)
+        )?;
+    }
+
+    if let Some(unknown) = histogram.get(&None) {
+        writeln!(w, "{:<8}{}", "unknown", unknown)?;
+    }
+
+    Ok(())
+}
+
+const TABLE_HEADERS: [&str; 4] = ["TIMESTAMP", "FACILITY", "LEVEL", "MESSAGE"];
+const TABLE_COLUMN_GAP: &str = "  ";
+
+// Messages are wrapped to the terminal width, unless that leaves them less room than this
+const MIN_TABLE_MESSAGE_WIDTH: usize = 20;
+
+/// The timestamp, facility and level columns of an entry in --table output.
+/// Missing values are left blank.
+fn table_cells(entry: &Entry) -> [String; 3] {
+    [
+        entry
+            .timestamp_from_system_start
+            .map(|ts| format!("{:.6}", ts.as_secs_f64()))
+            .unwrap_or_default(),
+        entry.facility.map(|f| f.to_string()).unwrap_or_default(),
+        entry.level.map(|l| l.to_string()).unwrap_or_default(),
+    ]
+}
+
+/// The widths of the columns before the message: wide enough for their header and every cell
+fn table_column_widths(rows: &[[String; 3]]) -> [usize; 3] {
+    let mut widths = [0; 3];
+    for (column, width) in widths.iter_mut().enumerate() {
+        *width = rows
+            .iter()
+            .map(|cells| cells[column].chars().count())
+            .chain(std::iter::once(TABLE_HEADERS[column].len()))
+            .max()
+            .unwrap_or(0);
+    }
+    widths
+}
+
+/// Splits a message into lines of at most `width` characters
+fn wrap_message(message: &str, width: usize) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut rest = message;
+    while let Some((end, _)) = rest.char_indices().nth(width) {
+        lines.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    lines.push(rest);
+    lines
+}
+
+/// Prints entries as a table with aligned columns. With a `terminal_width`, messages
+/// too long to fit are wrapped onto more lines in the message column.
+fn print_table<W: Write>(
+    entries: &[Entry],
+    terminal_width: Option<usize>,
+    w: &mut W,
+) -> io::Result<()> {
+    let rows: Vec<[String; 3]> = entries.iter().map(table_cells).collect();
+    let widths = table_column_widths(&rows);
+
+    let message_column = widths
+        .iter()
+        .map(|w| w + TABLE_COLUMN_GAP.len())
+        .sum::<usize>();
+    let message_width = terminal_width
+        .map(|terminal_width| terminal_width.saturating_sub(message_column))
+        .filter(|message_width| *message_width >= MIN_TABLE_MESSAGE_WIDTH);
+
+    let header = [
+        TABLE_HEADERS[0].to_owned(),
+        TABLE_HEADERS[1].to_owned(),
+        TABLE_HEADERS[2].to_owned(),
+    ];
+    for (cells, message) in std::iter::once((&header, TABLE_HEADERS[3]))
+        .chain(rows.iter().zip(entries.iter().map(|e| e.message.as_str())))
+    {
+        write!(
+            w,
+            "{:>w0$}{gap}{:<w1$}{gap}{:<w2$}{gap}",
+            cells[0],
+            cells[1],
+            cells[2],
+            gap = TABLE_COLUMN_GAP,
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+        )?;
+
+        let lines = match message_width {
+            Some(message_width) => wrap_message(message, message_width),
+            None => vec![message],
+        };
+        writeln!(w, "{}", lines[0])?;
+        for line in &lines[1..] {
+            writeln!(w, "{:message_column$}{}", "", line)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The width of the terminal stdout prints to, or None when it isn't printing to one
+fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+
+    // SAFETY: TIOCGWINSZ only writes a winsize into the one passed in
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 if size.ws_col > 0 => Some(size.ws_col as usize),
+        _ => std::env::var("COLUMNS").ok()?.parse().ok(),
+    }
+}
+
+/// Suggests what to do about errors users can get past
+fn print_hint(e: &RMesgError) {
+    match e {
+        RMesgError::OperationNotPermitted(_) => {
+            eprintln!("\nHint: Try using 'sudo' or run the program as root/superuser.")
+        }
+        RMesgError::DeviceNotFound(path) => eprintln!(
+            "\nHint: {} doesn't exist, as in containers it isn't exposed to. Try the klogctl backend (-b klogctl), or expose the device to the container.",
+            path.display()
+        ),
+        _ => {}
+    }
+}
+
+/// Reports an error printing to stdout, unless the reader went away (e.g. `rmesg -f | head`),
+/// which like standard Unix tools, is a reason to quietly stop printing (and succeed).
+/// Returns the exit code to exit with.
+fn report_print_error(e: io::Error) -> i32 {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        return EXIT_SUCCESS;
+    }
+
+    eprintln!("Unable to print log entry: {}", e);
+    EX_IOERR
+}
+
+// Exit codes, following sysexits.h
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_FAILURE: i32 = 1;
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_UNAVAILABLE: i32 = 69;
+const EX_IOERR: i32 = 74;
+const EX_NOPERM: i32 = 77;
+
+/// The exit code to exit with after this error, so scripts can tell what went wrong
+fn exit_code_for(e: &RMesgError) -> i32 {
+    match e {
+        RMesgError::OperationNotPermitted(_) => EX_NOPERM,
+        RMesgError::DeviceNotFound(_) | RMesgError::DevKMsgFileOpenError(_) => EX_UNAVAILABLE,
+        RMesgError::EntryParsingError(_)
+        | RMesgError::KMsgParseError { .. }
+        | RMesgError::Utf8StringConversionError(_)
+        | RMesgError::IntegerOutOfBound(_) => EX_DATAERR,
+        RMesgError::UnsupportedFilter(_)
+        | RMesgError::UnknownLogLevel(_)
+        | RMesgError::UnknownLogFacility(_)
+        | RMesgError::UnknownBackend(_) => EX_USAGE,
+        RMesgError::IOError(_) => EX_IOERR,
+        RMesgError::NotImplementedForThisPlatform
+        | RMesgError::NotSupportedOnThisPlatform(_)
+        | RMesgError::UnsupportedOperation(_)
+        | RMesgError::UnableToObtainSystemTime
+        | RMesgError::UnableToAddDurationToSystemTime
+        | RMesgError::UnableToObtainElapsedTime(_)
+        | RMesgError::KLogTimestampsDisabled
+        | RMesgError::InternalError(_) => EXIT_FAILURE,
+    }
+}
+
+/// Builds the filter selecting which entries to print
+fn entry_filter(opts: &Options) -> Result<EntryFilter, RMesgError> {
+    // Bounds relative to now need the current uptime to become relative to boot
+    let uptime = match (opts.since, opts.until) {
+        (Some(TimeBound::Ago(_)), _) | (_, Some(TimeBound::Ago(_))) => SystemTime::now()
+            .duration_since(rmesg::system_boot_time(rmesg::ClockSource::Uptime)?)
+            .unwrap_or_default(),
+        _ => Duration::ZERO,
+    };
+
+    Ok(EntryFilter {
+        since: opts.since.map(|bound| bound.since_boot(uptime)),
+        until: opts.until.map(|bound| bound.since_boot(uptime)),
+        source: opts.source,
+        levels: opts.levels.clone(),
+        facilities: opts.facilities.clone(),
+        message_regex: opts.grep.clone(),
+        invert_message_regex: opts.grep_invert,
+        ..Default::default()
+    })
+}
+
+impl TimeBound {
+    /// The time since system start this bound refers to, given the current uptime
+    fn since_boot(self, uptime: Duration) -> Duration {
+        match self {
+            Self::SinceBoot(since_boot) => since_boot,
+            Self::Ago(ago) => uptime.saturating_sub(ago),
+        }
+    }
+}
+
+/// Parses seconds since system start ("120.5"), or a time before now ("10 min ago")
+fn parse_time_bound(value: &str) -> Result<TimeBound, String> {
+    let to_duration = |secs: f64| {
+        Duration::try_from_secs_f64(secs).map_err(|e| format!("Invalid time '{}': {}", value, e))
+    };
+
+    match value.trim().strip_suffix("ago") {
+        None => match value.trim().parse::<f64>() {
+            Ok(secs) => Ok(TimeBound::SinceBoot(to_duration(secs)?)),
+            Err(_) => Err(format!(
+                "Expected seconds since boot or '<n> <unit> ago', found '{}'",
+                value
+            )),
+        },
+        Some(ago) => {
+            let ago = ago.trim();
+            let unit_start = ago
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(ago.len());
+            let (amount, unit) = ago.split_at(unit_start);
+
+            let amount = match amount.parse::<f64>() {
+                Ok(amount) => amount,
+                Err(_) => return Err(format!("Invalid amount of time in '{}'", value)),
+            };
+            let unit_secs = match unit.trim() {
+                "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+                "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+                "h" | "hour" | "hours" => 3600.0,
+                "d" | "day" | "days" => 86400.0,
+                u => return Err(format!("Unknown time unit '{}' in '{}'", u, value)),
+            };
+
+            Ok(TimeBound::Ago(to_duration(amount * unit_secs)?))
+        }
+    }
+}
+
+/// Parses a comma-separated list of levels or facilities (e.g. "warn,err"), naming the
+/// `kind` of item and the first bad one when there is one.
+fn parse_list<T: FromStr + Eq + Hash>(list: &str, kind: &str) -> Result<HashSet<T>, String> {
+    list.split(',')
+        .map(str::trim)
+        .map(|item| {
+            item.parse()
+                .map_err(|_| format!("Unknown {} '{}' in '{}'", kind, item, list))
+        })
+        .collect()
+}
+
+impl Printer {
+    fn new(opts: &Options) -> Result<Self, RMesgError> {
+        let color = match opts.color {
+            ColorMode::Auto => std::io::stdout().is_terminal(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        };
+
+        let time_format = match opts.time_format {
+            TimeStyle::SecondsSinceBoot => TimeFormat::SecondsSinceBoot,
+            TimeStyle::Ctime => {
+                TimeFormat::Ctime(rmesg::system_boot_time(rmesg::ClockSource::Uptime)?)
+            }
+            TimeStyle::Iso => {
+                TimeFormat::Iso8601(rmesg::system_boot_time(rmesg::ClockSource::Uptime)?)
+            }
+            TimeStyle::Reltime => {
+                TimeFormat::Reltime(rmesg::system_boot_time(rmesg::ClockSource::Uptime)?)
+            }
+            TimeStyle::NoTime => TimeFormat::None,
+        };
+
+        let formatter = EntryFormatter::new()
+            .with_timestamp(time_format)
+            .with_decode(opts.decode)
+            .with_priority(opts.raw_decode)
+            .with_delta(opts.show_delta)
+            .with_sequence(opts.show_seq)
+            .with_force_prefix(opts.force_prefix);
+
+        Ok(Self {
+            json: opts.json,
+            logfmt: opts.logfmt,
+            color,
+            line_terminator: opts.line_terminator,
+            formatter,
+        })
+    }
+
+    /// Writes an entry either through the formatter, or as a single line of JSON or logfmt,
+    /// ended with the line terminator
+    ///
+    /// Unlike println!, doesn't panic when writing fails, e.g. on a broken pipe.
+    fn write_entry<W: Write>(&mut self, w: &mut W, entry: &Entry) -> io::Result<()> {
+        let terminator = self.line_terminator;
+
+        if self.json {
+            return write!(
+                w,
+                "{}{}",
+                serde_json::to_string(entry).map_err(io::Error::other)?,
+                terminator
+            );
+        }
+
+        if self.logfmt {
+            return write!(w, "{}{}", entry.to_logfmt(), terminator);
+        }
+
+        let line = self.formatter.format(entry);
+
+        match entry.level.and_then(level_color) {
+            Some(color) if self.color => {
+                write!(w, "{}{}{}{}", color, line, ANSI_RESET, terminator)
+            }
+            _ => write!(w, "{}{}", line, terminator),
+        }
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// The ANSI escape sequence to color lines of this level with (like `dmesg -L`).
+/// Levels that print uncolored return None.
+fn level_color(level: LogLevel) -> Option<&'static str> {
+    match level {
+        LogLevel::Emergency | LogLevel::Alert | LogLevel::Critical => Some("\x1b[1;31m"),
+        LogLevel::Error => Some("\x1b[31m"),
+        LogLevel::Warning => Some("\x1b[33m"),
+        LogLevel::Debug => Some("\x1b[2m"),
+        LogLevel::Notice | LogLevel::Info => None,
+    }
+}
+
+fn command() -> Command {
+    Command::new("rmesg: A 'dmesg' port onto Rust")
         .version("0.2.0")
         .author("Archis Gore <me@archisgore.com>")
         .about(
@@ -98,12 +825,20 @@ fn parse_args() -> Options {
                 .action(clap::ArgAction::SetTrue)
                 .help("When specified, follows logs (like tail -f)"),
         )
+        .arg(
+            Arg::new("follow-new")
+                .short('W')
+                .long("follow-new")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Follow logs like -f, but only print messages logged from now on."),
+        )
         .arg(
             Arg::new("clear")
                 .short('c')
                 .num_args(0)
                 .action(clap::ArgAction::SetTrue)
-                .help("Clear ring buffer after printing"),
+                .help("Clear ring buffer after printing. Selects the klogctl backend unless -b is given."),
         )
         .arg(
             Arg::new("raw")
@@ -113,88 +848,408 @@ fn parse_args() -> Options {
                 .help("Print raw data as it came from the source backend."),
         )
         .arg(
-            Arg::new("backend")
-                .short('b')
+            Arg::new("json")
+                .short('J')
+                .long("json")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("raw")
+                .help("Print each entry as a single line of JSON (newline-delimited JSON)."),
+        )
+        .arg(
+            Arg::new("logfmt")
+                .long("logfmt")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["raw", "json"])
+                .help("Print each entry as a single line of logfmt (key=value pairs)."),
+        )
+        .arg(
+            Arg::new("stats")
+                .long("stats")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["follow", "follow-new", "raw", "json", "logfmt", "lines"])
+                .help("Print how many messages there are at each level, instead of the messages. Only messages selected by the other flags (e.g. -l, --since) are counted."),
+        )
+        .arg(
+            Arg::new("table")
+                .long("table")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "follow", "follow-new", "raw", "json", "logfmt", "stats", "ctime", "notime",
+                    "time-format", "decode", "raw-decode", "show-delta", "show-seq", "force-prefix",
+                ])
+                .help("Print messages as a table with aligned timestamp, facility, level and message columns. Long messages wrap to the terminal width."),
+        )
+        .arg(
+            Arg::new("ctime")
+                .short('T')
+                .long("ctime")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["raw", "json", "logfmt"])
+                .help("Print human-readable timestamps. May be inaccurate if the system was suspended or its clock changed since boot."),
+        )
+        .arg(
+            Arg::new("notime")
+                .short('t')
+                .long("notime")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["ctime", "raw", "json", "logfmt"])
+                .help("Don't print timestamps."),
+        )
+        .arg(
+            Arg::new("time-format")
+                .long("time-format")
                 .num_args(1)
-                .value_parser(["klogctl", "devkmsg"])
-                .help("Select backend from where to read the logs. klog is the syslog/klogctl system call through libc. kmsg is the /dev/kmsg file."),
+                .value_name("format")
+                .value_parser(["ctime", "iso", "reltime", "notime"])
+                .conflicts_with_all(["ctime", "notime", "raw", "json", "logfmt"])
+                .help("Print timestamps in this format. ctime is the same as -T, iso is ISO-8601 in UTC (e.g. 2024-06-05T14:32:01.123456Z), reltime prints dates only when the day changes (like dmesg -e), notime is the same as -t."),
         )
-        .get_matches();
-
-    let follow = matches.get_flag("follow");
-    let clear = matches.get_flag("clear");
-    let raw = matches.get_flag("raw");
-    let backend = match matches.get_one::<String>("backend").map(|s| s.as_str()) {
-        None => rmesg::Backend::Default,
-        Some("klogctl") => rmesg::Backend::KLogCtl,
-        Some("devkmsg") => rmesg::Backend::DevKMsg,
-        Some(v) => panic!("Something went wrong. Possible values for backend were not restricted by the CLI parser and this value slipped through somehow: {}", v),
-    };
-
-    Options {
-        follow,
-        clear,
-        raw,
-        backend,
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn parse_args_from(args: &[&str]) -> Options {
-        let cmd = Command::new("rmesg")
-            .version("0.2.0")
-            .author("Archis Gore <me@archisgore.com>")
-            .about("Reads (and prints) the kernel log buffer. Does not support all dmesg options (yet).")
-            .arg(
-                Arg::new("follow")
-                    .short('f')
-                    .num_args(0)
-                    .action(clap::ArgAction::SetTrue)
-                    .help("When specified, follows logs (like tail -f)"),
-            )
-            .arg(
-                Arg::new("clear")
-                    .short('c')
-                    .num_args(0)
-                    .action(clap::ArgAction::SetTrue)
-                    .help("Clear ring buffer after printing"),
-            )
-            .arg(
-                Arg::new("raw")
-                    .short('r')
-                    .num_args(0)
-                    .action(clap::ArgAction::SetTrue)
-                    .help("Print raw data as it came from the source backend."),
-            )
-            .arg(
-                Arg::new("backend")
-                    .short('b')
-                    .num_args(1)
-                    .value_parser(["klogctl", "devkmsg"])
-                    .help("Select backend from where to read the logs. klog is the syslog/klogctl system call through libc. kmsg is the /dev/kmsg file."),
-            );
+        .arg(
+            Arg::new("color")
+                .short('L')
+                .long("color")
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_name("when")
+                .value_parser(["auto", "always", "never"])
+                .default_missing_value("auto")
+                .help("Colorize messages by severity. Defaults to auto (only when printing to a terminal) if specified without a value."),
+        )
+        .arg(
+            Arg::new("show-delta")
+                .short('d')
+                .long("show-delta")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["raw", "json", "logfmt"])
+                .help("Show the time elapsed between printed messages (e.g. <+    0.000123>)."),
+        )
+        .arg(
+            Arg::new("show-seq")
+                .long("show-seq")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["raw", "json", "logfmt"])
+                .help("Print the sequence number of each message (e.g. [seq 12345]), when it has one. Messages read through klogctl don't."),
+        )
+        .arg(
+            Arg::new("force-prefix")
+                .short('p')
+                .long("force-prefix")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["raw", "json", "logfmt"])
+                .help("Repeat the timestamp and any other prefix on every line of a message spanning several lines."),
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .num_args(1)
+                .value_parser(parse_time_bound)
+                .help("Only print messages logged at or after this time. Either seconds since boot (e.g. 120.5) or a time before now (e.g. '10 min ago')."),
+        )
+        .arg(
+            Arg::new("until")
+                .long("until")
+                .num_args(1)
+                .value_parser(parse_time_bound)
+                .help("Only print messages logged at or before this time. Either seconds since boot (e.g. 120.5) or a time before now (e.g. '10 min ago')."),
+        )
+        .arg(
+            Arg::new("kernel")
+                .short('k')
+                .long("kernel")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Print kernel messages only."),
+        )
+        .arg(
+            Arg::new("userspace")
+                .short('u')
+                .long("userspace")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("kernel")
+                .help("Print userspace messages only. Not supported by the klogctl backend."),
+        )
+        .arg(
+            Arg::new("line-terminator")
+                .long("line-terminator")
+                .num_args(1)
+                .value_name("TERMINATOR")
+                .value_parser(["newline", "crlf", "nul"])
+                .conflicts_with_all(["table", "stats"])
+                .help("End each printed message with a newline (the default), a carriage return and newline (crlf), or a NUL byte (nul)."),
+        )
+        .arg(
+            Arg::new("decode")
+                .short('x')
+                .long("decode")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["raw", "json", "logfmt"])
+                .help("Decode facility and level (priority) numbers to human-readable prefixes."),
+        )
+        .arg(
+            Arg::new("raw-decode")
+                .long("raw-decode")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["raw", "json", "logfmt"])
+                .help("Print the priority number of each message, with the facility and level it decodes to (e.g. prio=6 (kern.info))."),
+        )
+        .arg(
+            Arg::new("noescape")
+                .long("noescape")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Print control characters in messages as they are, instead of escaping them as \\xNN. Printing them can mess up the terminal."),
+        )
+        .arg(
+            Arg::new("lines")
+                .short('n')
+                .long("lines")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with_all(["raw", "follow", "follow-new"])
+                .help("Print only the last N messages."),
+        )
+        .arg(
+            Arg::new("level")
+                .short('l')
+                .long("level")
+                .num_args(1)
+                .value_name("LIST")
+                .value_parser(|list: &str| parse_list::<LogLevel>(list, "level"))
+                .help("Print only messages at these comma-separated levels (e.g. warn,err). Names or numbers 0-7."),
+        )
+        .arg(
+            Arg::new("facility")
+                .long("facility")
+                .num_args(1)
+                .value_name("LIST")
+                .value_parser(|list: &str| parse_list::<LogFacility>(list, "facility"))
+                .help("Print only messages from these comma-separated facilities (e.g. kern,daemon). Names or numbers 0-23."),
+        )
+        .arg(
+            Arg::new("grep")
+                .short('g')
+                .long("grep")
+                .num_args(1)
+                .value_name("PATTERN")
+                .value_parser(|pattern: &str| Regex::new(pattern))
+                .help("Print only messages matching this regular expression. Prefix it with (?i) to ignore case."),
+        )
+        .arg(
+            Arg::new("grep-invert")
+                .long("grep-invert")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .requires("grep")
+                .help("Print only messages not matching the --grep pattern."),
+        )
+        .arg(
+            Arg::new("buffer-size")
+                .short('s')
+                .long("buffer-size")
+                .num_args(1)
+                .value_name("SIZE")
+                .value_parser(clap::value_parser!(usize))
+                .help("Size of the buffer to read the klogctl backend into, in bytes. Defaults to the size of the kernel log buffer."),
+        )
+        .arg(
+            Arg::new("max-rate")
+                .long("max-rate")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .requires("following")
+                .help("While following, print at most N messages per second, dropping the rest and noting on stderr how many were dropped."),
+        )
+        .arg(
+            Arg::new("console-level")
+                .long("console-level")
+                .num_args(1)
+                .value_name("LEVEL")
+                .value_parser(|level: &str| level.parse::<LogLevel>())
+                .exclusive(true)
+                .help("Set the level of messages printed to the console, instead of printing messages: this level and more severe are printed (like dmesg -n). A name or number 0-7. (-n is --lines.)"),
+        )
+        .arg(
+            Arg::new("console-off")
+                .short('D')
+                .long("console-off")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .exclusive(true)
+                .help("Disable printing messages to the console, instead of printing messages (like dmesg -D)."),
+        )
+        .arg(
+            Arg::new("console-on")
+                .short('E')
+                .long("console-on")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .exclusive(true)
+                .help("Enable printing messages to the console, instead of printing messages (like dmesg -E)."),
+        )
+        .arg(
+            Arg::new("kmsg-path")
+                .long("kmsg-path")
+                .num_args(1)
+                .value_name("PATH")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Read /dev/kmsg from this path instead, e.g. a namespaced or bind-mounted kmsg. Selects the devkmsg backend unless -b is given."),
+        )
+        .arg(
+            Arg::new("backend")
+                .short('b')
+                .num_args(1)
+                .value_parser(["klogctl", "devkmsg"])
+                .help("Select backend from where to read the logs. klog is the syslog/klogctl system call through libc. kmsg is the /dev/kmsg file."),
+        )
+        // Either way of following, for the flags that only apply while following
+        .group(
+            ArgGroup::new("following")
+                .args(["follow", "follow-new"])
+                .multiple(true),
+        )
+        // Subcommands do something other than print, so the flags for printing don't apply
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("clear")
+                .about("Clear the kernel log buffer without printing it (like dmesg -C)."),
+        )
+}
 
-        let matches = cmd.try_get_matches_from(args).unwrap();
+fn parse_args() -> Options {
+    options_from_matches(&command().get_matches())
+}
 
-        let follow = matches.get_flag("follow");
-        let clear = matches.get_flag("clear");
-        let raw = matches.get_flag("raw");
-        let backend = match matches.get_one::<String>("backend").map(|s| s.as_str()) {
-            None => rmesg::Backend::Default,
-            Some("klogctl") => rmesg::Backend::KLogCtl,
-            Some("devkmsg") => rmesg::Backend::DevKMsg,
-            Some(v) => panic!("Invalid backend value: {}", v),
-        };
+fn options_from_matches(matches: &ArgMatches) -> Options {
+    let subcommand = match matches.subcommand_name() {
+        None => None,
+        Some("clear") => Some(Subcommand::Clear),
+        Some(v) => panic!("Something went wrong. Possible subcommands were not restricted by the CLI parser and this one slipped through somehow: {}", v),
+    };
+    let console_level = matches.get_one::<LogLevel>("console-level").copied();
+    let console_output = match (
+        matches.get_flag("console-on"),
+        matches.get_flag("console-off"),
+    ) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    };
+    let follow_new = matches.get_flag("follow-new");
+    let follow = matches.get_flag("follow") || follow_new;
+    let clear = matches.get_flag("clear");
+    let raw = matches.get_flag("raw");
+    let json = matches.get_flag("json");
+    let logfmt = matches.get_flag("logfmt");
+    let stats = matches.get_flag("stats");
+    let table = matches.get_flag("table");
+    let decode = matches.get_flag("decode");
+    let raw_decode = matches.get_flag("raw-decode");
+    let escape = !matches.get_flag("noescape");
+    let show_delta = matches.get_flag("show-delta");
+    let show_seq = matches.get_flag("show-seq");
+    let force_prefix = matches.get_flag("force-prefix");
+    let since = matches.get_one::<TimeBound>("since").copied();
+    let until = matches.get_one::<TimeBound>("until").copied();
+    let source = match (matches.get_flag("kernel"), matches.get_flag("userspace")) {
+        (true, _) => Some(MessageSource::Kernel),
+        (_, true) => Some(MessageSource::Userspace),
+        _ => None,
+    };
+    let levels = matches.get_one::<HashSet<LogLevel>>("level").cloned();
+    let facilities = matches.get_one::<HashSet<LogFacility>>("facility").cloned();
+    let lines = matches.get_one::<usize>("lines").copied();
+    let grep = matches.get_one::<Regex>("grep").cloned();
+    let grep_invert = matches.get_flag("grep-invert");
+    let buffer_size = matches.get_one::<usize>("buffer-size").copied();
+    let max_rate = matches.get_one::<u32>("max-rate").copied();
+    let time_format = match matches.get_one::<String>("time-format").map(|s| s.as_str()) {
+        None if matches.get_flag("ctime") => TimeStyle::Ctime,
+        None if matches.get_flag("notime") => TimeStyle::NoTime,
+        None => TimeStyle::SecondsSinceBoot,
+        Some("ctime") => TimeStyle::Ctime,
+        Some("iso") => TimeStyle::Iso,
+        Some("reltime") => TimeStyle::Reltime,
+        Some("notime") => TimeStyle::NoTime,
+        Some(v) => panic!("Something went wrong. Possible values for time-format were not restricted by the CLI parser and this value slipped through somehow: {}", v),
+    };
+    let color = match matches.get_one::<String>("color").map(|s| s.as_str()) {
+        None | Some("never") => ColorMode::Never,
+        Some("auto") => ColorMode::Auto,
+        Some("always") => ColorMode::Always,
+        Some(v) => panic!("Something went wrong. Possible values for color were not restricted by the CLI parser and this value slipped through somehow: {}", v),
+    };
+    let line_terminator = match matches.get_one::<String>("line-terminator").map(|s| s.as_str()) {
+        None | Some("newline") => "\n",
+        Some("crlf") => "\r\n",
+        Some("nul") => "\0",
+        Some(v) => panic!("Something went wrong. Possible values for line-terminator were not restricted by the CLI parser and this value slipped through somehow: {}", v),
+    };
+    let kmsg_path = matches.get_one::<PathBuf>("kmsg-path").cloned();
+    let backend = match matches.get_one::<String>("backend") {
+        None if kmsg_path.is_some() => rmesg::Backend::DevKMsg,
+        // Only klogctl clears, and reads what it clears in the same call
+        None if clear => rmesg::Backend::KLogCtl,
+        None => rmesg::Backend::Default,
+        Some(v) => v.parse().unwrap_or_else(|_| panic!("Something went wrong. Possible values for backend were not restricted by the CLI parser and this value slipped through somehow: {}", v)),
+    };
 
-        Options {
-            follow,
-            clear,
-            raw,
-            backend,
-        }
+    Options {
+        subcommand,
+        console_level,
+        console_output,
+        follow,
+        follow_new,
+        clear,
+        raw,
+        json,
+        logfmt,
+        stats,
+        table,
+        time_format,
+        color,
+        line_terminator,
+        decode,
+        raw_decode,
+        escape,
+        show_delta,
+        show_seq,
+        force_prefix,
+        since,
+        until,
+        source,
+        levels,
+        facilities,
+        lines,
+        grep,
+        grep_invert,
+        buffer_size,
+        max_rate,
+        kmsg_path,
+        backend,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args_from(args: &[&str]) -> Options {
+        let matches = command().try_get_matches_from(args).unwrap();
+        options_from_matches(&matches)
     }
 
     #[test]
@@ -215,13 +1270,102 @@ mod tests {
         assert!(matches!(opts.backend, rmesg::Backend::Default));
     }
 
+    #[test]
+    fn test_follow_new_flag() {
+        let opts = parse_args_from(&["rmesg", "-W"]);
+        assert!(opts.follow);
+        assert!(opts.follow_new);
+
+        let opts = parse_args_from(&["rmesg", "-f"]);
+        assert!(!opts.follow_new);
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "--follow-new", "-n", "5"])
+            .is_err());
+    }
+
     #[test]
     fn test_clear_flag() {
         let opts = parse_args_from(&["rmesg", "-c"]);
         assert!(!opts.follow);
         assert!(opts.clear);
         assert!(!opts.raw);
+        // Only klogctl clears
+        assert!(matches!(opts.backend, rmesg::Backend::KLogCtl));
+
+        let opts = parse_args_from(&["rmesg", "-c", "-b", "devkmsg"]);
+        assert!(matches!(opts.backend, rmesg::Backend::DevKMsg));
+    }
+
+    #[tokio::test]
+    async fn test_clear_checked_before_printing() {
+        // A backend that can't clear fails before anything is read or printed
+        let opts = parse_args_from(&["rmesg", "-c", "-b", "devkmsg"]);
+        assert!(matches!(
+            check_clear(&opts, rmesg::check_clear_access),
+            Err(RMesgError::UnsupportedOperation(_))
+        ));
+        assert_eq!(run(opts).await, EXIT_FAILURE);
+
+        let opts = parse_args_from(&["rmesg", "-c", "--kmsg-path", "/nonexistent/kmsg"]);
+        assert!(matches!(
+            check_clear(&opts, rmesg::check_clear_access),
+            Err(RMesgError::UnsupportedOperation(_))
+        ));
+
+        // Without permission to clear, nothing is printed, for any output format
+        for args in [
+            &["rmesg", "-c"][..],
+            &["rmesg", "-c", "-J"][..],
+            &["rmesg", "-c", "-r"][..],
+            &["rmesg", "-c", "--stats"][..],
+        ]
+        .iter()
+        {
+            let mut checked = false;
+            let mut written = Vec::new();
+            let exit_code = run_with(
+                parse_args_from(args),
+                |b| {
+                    checked = true;
+                    assert_eq!(b, rmesg::Backend::KLogCtl);
+                    Err(RMesgError::OperationNotPermitted("EPERM".to_owned()))
+                },
+                &mut written,
+            )
+            .await;
+            assert!(checked);
+            assert_eq!(exit_code, EX_NOPERM);
+            assert!(written.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_clear_subcommand() {
+        let opts = parse_args_from(&["rmesg", "clear"]);
+        assert_eq!(opts.subcommand, Some(Subcommand::Clear));
+        assert!(!opts.clear);
         assert!(matches!(opts.backend, rmesg::Backend::Default));
+
+        // The legacy flag still clears after printing, without a subcommand
+        let opts = parse_args_from(&["rmesg", "-c"]);
+        assert_eq!(opts.subcommand, None);
+        assert!(opts.clear);
+
+        // Flags for printing don't go with it
+        for args in [
+            &["rmesg", "-c", "clear"][..],
+            &["rmesg", "-f", "clear"],
+            &["rmesg", "clear", "-f"],
+            &["rmesg", "clear", "now"],
+        ]
+        .iter()
+        {
+            assert!(command().try_get_matches_from(*args).is_err(), "{:?}", args);
+        }
+
+        let result = command().try_get_matches_from(["rmesg", "follow"]);
+        assert!(result.is_err());
     }
 
     #[test]
@@ -265,4 +1409,875 @@ mod tests {
     fn test_invalid_backend() {
         parse_args_from(&["rmesg", "-b", "invalid"]);
     }
+
+    #[test]
+    fn test_json_flag() {
+        let opts = parse_args_from(&["rmesg", "-J"]);
+        assert!(opts.json);
+        assert!(!opts.raw);
+
+        let opts = parse_args_from(&["rmesg", "--json", "-f"]);
+        assert!(opts.json);
+        assert!(opts.follow);
+    }
+
+    #[test]
+    fn test_ctime_flag() {
+        let opts = parse_args_from(&["rmesg", "-T"]);
+        assert_eq!(opts.time_format, TimeStyle::Ctime);
+
+        let opts = parse_args_from(&["rmesg", "--ctime", "-f"]);
+        assert_eq!(opts.time_format, TimeStyle::Ctime);
+        assert!(opts.follow);
+
+        let result = command().try_get_matches_from(["rmesg", "-T", "-J"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn test_ctime_line() {
+        use chrono::{DateTime, Local};
+
+        // Wed Jun  5 14:00:00 2024 UTC
+        let boot_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_596_000);
+        let mut printer = Printer::new(&parse_args_from(&["rmesg"])).unwrap();
+        printer.formatter = EntryFormatter::new().with_timestamp(TimeFormat::Ctime(boot_time));
+        let mut entry = Entry {
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Info),
+            sequence_num: Some(1),
+            timestamp_from_system_start: Some(Duration::from_secs_f64(1921.5)),
+            message: "Test message".to_owned(),
+            ..Default::default()
+        };
+
+        let expected_time =
+            DateTime::<Local>::from(SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_597_921))
+                .format("%a %b %e %H:%M:%S %Y");
+        let mut written = Vec::new();
+        printer.write_entry(&mut written, &entry).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            format!("[{}] Test message\n", expected_time)
+        );
+
+        entry.timestamp_from_system_start = None;
+        let mut written = Vec::new();
+        printer.write_entry(&mut written, &entry).unwrap();
+        assert_eq!(written, b"Test message\n");
+    }
+
+    #[test]
+    fn test_color_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert_eq!(opts.color, ColorMode::Never);
+
+        let opts = parse_args_from(&["rmesg", "-L"]);
+        assert_eq!(opts.color, ColorMode::Auto);
+
+        let opts = parse_args_from(&["rmesg", "--color=always"]);
+        assert_eq!(opts.color, ColorMode::Always);
+
+        let opts = parse_args_from(&["rmesg", "--color=never"]);
+        assert_eq!(opts.color, ColorMode::Never);
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "--color=sometimes"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_level_color() {
+        assert_eq!(level_color(LogLevel::Emergency), Some("\x1b[1;31m"));
+        assert_eq!(level_color(LogLevel::Critical), Some("\x1b[1;31m"));
+        assert_eq!(level_color(LogLevel::Warning), Some("\x1b[33m"));
+        assert_eq!(level_color(LogLevel::Debug), Some("\x1b[2m"));
+        assert_eq!(level_color(LogLevel::Info), None);
+    }
+
+    #[test]
+    fn test_decode_flag() {
+        let opts = parse_args_from(&["rmesg", "-x"]);
+        assert!(opts.decode);
+
+        let opts = parse_args_from(&["rmesg", "--decode", "-T"]);
+        assert!(opts.decode);
+        assert_eq!(opts.time_format, TimeStyle::Ctime);
+    }
+
+    #[test]
+    fn test_decode_prefix() {
+        let mut printer = Printer::new(&parse_args_from(&["rmesg", "-x", "-t"])).unwrap();
+        let mut entry = Entry {
+            facility: Some(LogFacility::Kern),
+            level: Some(LogLevel::Warning),
+            sequence_num: Some(1),
+            message: "Test message".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            printer.formatter.format(&entry),
+            "kern  :warn  : Test message"
+        );
+
+        entry.facility = Some(LogFacility::Daemon);
+        entry.level = Some(LogLevel::Notice);
+        assert_eq!(
+            printer.formatter.format(&entry),
+            "daemon:notice: Test message"
+        );
+
+        entry.facility = Some(LogFacility::AuthPriv);
+        entry.level = Some(LogLevel::Emergency);
+        assert_eq!(
+            printer.formatter.format(&entry),
+            "authpriv:emerg : Test message"
+        );
+
+        entry.facility = None;
+        entry.level = Some(LogLevel::Info);
+        assert_eq!(
+            printer.formatter.format(&entry),
+            "      :info  : Test message"
+        );
+
+        entry.level = None;
+        assert_eq!(
+            printer.formatter.format(&entry),
+            "      :      : Test message"
+        );
+    }
+
+    #[test]
+    fn test_raw_decode_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert!(!opts.raw_decode);
+
+        let opts = parse_args_from(&["rmesg", "--raw-decode", "-x"]);
+        assert!(opts.raw_decode);
+        assert!(opts.decode);
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "--raw-decode", "-J"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_line_terminator_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert_eq!(opts.line_terminator, "\n");
+        let opts = parse_args_from(&["rmesg", "--line-terminator", "crlf"]);
+        assert_eq!(opts.line_terminator, "\r\n");
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "--line-terminator", "tab"])
+            .is_err());
+        assert!(command()
+            .try_get_matches_from(["rmesg", "--line-terminator", "nul", "--table"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_nul_line_terminator() {
+        let opts = parse_args_from(&["rmesg", "--line-terminator", "nul", "-t"]);
+        let mut printer = Printer::new(&opts).unwrap();
+        let entries = rmesg::RmesgOptions::new()
+            .backend(rmesg::Backend::File(
+                [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "dmesg.txt"]
+                    .iter()
+                    .collect(),
+            ))
+            .entries()
+            .unwrap();
+
+        let mut written = Vec::new();
+        for entry in &entries[..2] {
+            printer.write_entry(&mut written, entry).unwrap();
+        }
+        let expected = format!("{}\0{}\0", entries[0].message, entries[1].message);
+        assert_eq!(written, expected.as_bytes());
+
+        let opts = parse_args_from(&["rmesg", "--line-terminator", "nul", "-J"]);
+        let mut printer = Printer::new(&opts).unwrap();
+        let mut written = Vec::new();
+        printer.write_entry(&mut written, &entries[0]).unwrap();
+        assert_eq!(written.last(), Some(&0));
+        assert!(!written.contains(&b'\n'));
+    }
+
+    #[test]
+    fn test_noescape_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert!(opts.escape);
+        assert_eq!(
+            rmesg_options(&opts, EntryFilter::default()),
+            rmesg::RmesgOptions::new().escape_messages(true)
+        );
+
+        let opts = parse_args_from(&["rmesg", "--noescape", "-J"]);
+        assert!(!opts.escape);
+        assert_eq!(
+            rmesg_options(&opts, EntryFilter::default()),
+            rmesg::RmesgOptions::new()
+        );
+    }
+
+    #[test]
+    fn test_notime_flag() {
+        let opts = parse_args_from(&["rmesg", "-t", "-x"]);
+        assert_eq!(opts.time_format, TimeStyle::NoTime);
+        assert!(opts.decode);
+
+        let opts = parse_args_from(&["rmesg", "--notime", "-f"]);
+        assert_eq!(opts.time_format, TimeStyle::NoTime);
+        assert!(opts.follow);
+
+        let opts = parse_args_from(&["rmesg", "--time-format", "notime"]);
+        assert_eq!(opts.time_format, TimeStyle::NoTime);
+
+        let result = command().try_get_matches_from(["rmesg", "-t", "-T"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn test_time_format_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert_eq!(opts.time_format, TimeStyle::SecondsSinceBoot);
+
+        let opts = parse_args_from(&["rmesg", "--time-format", "iso", "-f"]);
+        assert_eq!(opts.time_format, TimeStyle::Iso);
+
+        let opts = parse_args_from(&["rmesg", "--time-format", "reltime"]);
+        assert_eq!(opts.time_format, TimeStyle::Reltime);
+
+        let opts = parse_args_from(&["rmesg", "--time-format", "ctime"]);
+        assert_eq!(opts.time_format, TimeStyle::Ctime);
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "--time-format", "unix"])
+            .is_err());
+
+        let result = command().try_get_matches_from(["rmesg", "--time-format", "iso", "-T"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn test_show_delta_flag() {
+        let opts = parse_args_from(&["rmesg", "-d"]);
+        assert!(opts.show_delta);
+
+        let opts = parse_args_from(&["rmesg", "--show-delta", "-f"]);
+        assert!(opts.show_delta);
+        assert!(opts.follow);
+    }
+
+    #[test]
+    fn test_delta_tracker() {
+        let mut printer = Printer::new(&parse_args_from(&["rmesg", "-d"])).unwrap();
+
+        let timestamps = [
+            Some(Duration::from_micros(1_000_000)),
+            Some(Duration::from_micros(1_000_123)),
+            None,
+            Some(Duration::from_micros(1_500_123)),
+            // earlier than the previous one
+            Some(Duration::from_micros(1_400_000)),
+            Some(Duration::from_micros(1_400_001)),
+        ];
+
+        let mut written = Vec::new();
+        for timestamp in timestamps.iter() {
+            let entry = Entry {
+                timestamp_from_system_start: *timestamp,
+                message: "m".to_owned(),
+                ..Default::default()
+            };
+            printer.write_entry(&mut written, &entry).unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "<+    0.000000> [        1.000000] m
+<+    0.000123> [        1.000123] m
+m
+<+    0.500000> [        1.500123] m
+<+    0.000000> [        1.400000] m
+<+    0.000001> [        1.400001] m
+"
+        );
+    }
+
+    #[test]
+    fn test_show_seq_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert!(!opts.show_seq);
+
+        let opts = parse_args_from(&["rmesg", "--show-seq", "-t"]);
+        assert!(opts.show_seq);
+
+        let mut entry = Entry {
+            sequence_num: Some(12345),
+            timestamp_from_system_start: Some(Duration::from_secs(5)),
+            message: "usb 1-1: reset".to_owned(),
+            ..Default::default()
+        };
+        let mut printer = Printer::new(&opts).unwrap();
+        assert_eq!(
+            printer.formatter.format(&entry),
+            "[seq 12345] usb 1-1: reset"
+        );
+        entry.sequence_num = None;
+        assert_eq!(printer.formatter.format(&entry), "usb 1-1: reset");
+
+        for flag in ["-J", "--logfmt", "-r", "--table"].iter() {
+            assert!(command()
+                .try_get_matches_from(["rmesg", "--show-seq", flag])
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_force_prefix_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert!(!opts.force_prefix);
+
+        let opts = parse_args_from(&["rmesg", "-p", "--noescape"]);
+        assert!(opts.force_prefix);
+        let opts = parse_args_from(&["rmesg", "--force-prefix"]);
+        assert!(opts.force_prefix);
+
+        let entry = Entry {
+            timestamp_from_system_start: Some(Duration::from_secs(5)),
+            message: "Call Trace:\n dump_stack+0x5c/0x80".to_owned(),
+            ..Default::default()
+        };
+        let mut printer = Printer::new(&opts).unwrap();
+        assert_eq!(
+            printer.formatter.format(&entry),
+            "[        5.000000] Call Trace:\n[        5.000000]  dump_stack+0x5c/0x80"
+        );
+
+        for flag in ["-J", "--logfmt", "-r", "--table"].iter() {
+            assert!(command()
+                .try_get_matches_from(["rmesg", "-p", flag])
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_since_until_flags() {
+        let opts = parse_args_from(&["rmesg", "--since", "120.5", "--until", "10 min ago"]);
+        assert_eq!(
+            opts.since,
+            Some(TimeBound::SinceBoot(Duration::from_secs_f64(120.5)))
+        );
+        assert_eq!(opts.until, Some(TimeBound::Ago(Duration::from_secs(600))));
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "--since", "yesterday"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_time_bound() {
+        assert_eq!(
+            parse_time_bound("42"),
+            Ok(TimeBound::SinceBoot(Duration::from_secs(42)))
+        );
+        assert_eq!(
+            parse_time_bound("30s ago"),
+            Ok(TimeBound::Ago(Duration::from_secs(30)))
+        );
+        assert_eq!(
+            parse_time_bound("1.5 hours ago"),
+            Ok(TimeBound::Ago(Duration::from_secs(5400)))
+        );
+        assert_eq!(
+            parse_time_bound("2 days ago"),
+            Ok(TimeBound::Ago(Duration::from_secs(172800)))
+        );
+        assert!(parse_time_bound("-5").is_err());
+        assert!(parse_time_bound("10 fortnights ago").is_err());
+        assert!(parse_time_bound("ago").is_err());
+    }
+
+    #[test]
+    fn test_time_bound_since_boot() {
+        let uptime = Duration::from_secs(1000);
+        assert_eq!(
+            TimeBound::SinceBoot(Duration::from_secs(5)).since_boot(uptime),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            TimeBound::Ago(Duration::from_secs(600)).since_boot(uptime),
+            Duration::from_secs(400)
+        );
+        assert_eq!(
+            TimeBound::Ago(Duration::from_secs(6000)).since_boot(uptime),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_kernel_userspace_flags() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert_eq!(opts.source, None);
+
+        let opts = parse_args_from(&["rmesg", "-k"]);
+        assert_eq!(opts.source, Some(MessageSource::Kernel));
+
+        let opts = parse_args_from(&["rmesg", "--userspace"]);
+        assert_eq!(opts.source, Some(MessageSource::Userspace));
+
+        let result = command().try_get_matches_from(["rmesg", "-k", "-u"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn test_lines_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert_eq!(opts.lines, None);
+
+        let opts = parse_args_from(&["rmesg", "-n", "20"]);
+        assert_eq!(opts.lines, Some(20));
+
+        let opts = parse_args_from(&["rmesg", "--lines", "0", "-J"]);
+        assert_eq!(opts.lines, Some(0));
+        assert!(opts.json);
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "-n", "-3"])
+            .is_err());
+
+        let result = command().try_get_matches_from(["rmesg", "-n", "5", "-f"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn test_level_and_facility_flags() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert!(opts.levels.is_none());
+        assert!(opts.facilities.is_none());
+
+        let opts = parse_args_from(&["rmesg", "--level=warn,err", "--facility", "kern, DAEMON"]);
+        assert_eq!(
+            opts.levels,
+            Some([LogLevel::Warning, LogLevel::Error].into())
+        );
+        assert_eq!(
+            opts.facilities,
+            Some([LogFacility::Kern, LogFacility::Daemon].into())
+        );
+
+        let filter = entry_filter(&opts).unwrap();
+        assert_eq!(filter.levels, opts.levels);
+        assert_eq!(filter.facilities, opts.facilities);
+
+        let opts = parse_args_from(&["rmesg", "-l", "3"]);
+        assert_eq!(opts.levels, Some([LogLevel::Error].into()));
+    }
+
+    #[test]
+    fn test_console_level_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert_eq!(opts.console_level, None);
+
+        let opts = parse_args_from(&["rmesg", "--console-level", "warn"]);
+        assert_eq!(opts.console_level, Some(LogLevel::Warning));
+
+        let opts = parse_args_from(&["rmesg", "--console-level=1"]);
+        assert_eq!(opts.console_level, Some(LogLevel::Alert));
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "--console-level", "loud"])
+            .is_err());
+        // Sets the level instead of printing
+        let result = command().try_get_matches_from(["rmesg", "--console-level", "err", "-f"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn test_console_output_flags() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert_eq!(opts.console_output, None);
+
+        let opts = parse_args_from(&["rmesg", "-E"]);
+        assert_eq!(opts.console_output, Some(true));
+        let opts = parse_args_from(&["rmesg", "--console-off"]);
+        assert_eq!(opts.console_output, Some(false));
+
+        for conflicting in [
+            &["rmesg", "-D", "-E"][..],
+            &["rmesg", "-E", "-f"],
+            &["rmesg", "-D", "--console-level", "err"],
+        ]
+        .iter()
+        {
+            let result = command().try_get_matches_from(*conflicting);
+            assert_eq!(
+                result.unwrap_err().kind(),
+                clap::error::ErrorKind::ArgumentConflict
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(
+            parse_list::<LogLevel>("emerg,debug", "level"),
+            Ok([LogLevel::Emergency, LogLevel::Debug].into())
+        );
+        assert_eq!(
+            parse_list::<LogLevel>("warn,loud", "level"),
+            Err("Unknown level 'loud' in 'warn,loud'".to_owned())
+        );
+        assert_eq!(
+            parse_list::<LogFacility>("kern,", "facility"),
+            Err("Unknown facility '' in 'kern,'".to_owned())
+        );
+
+        let e = command()
+            .try_get_matches_from(["rmesg", "--facility", "kern,bogus"])
+            .unwrap_err();
+        assert!(e.to_string().contains("Unknown facility 'bogus'"), "{}", e);
+    }
+
+    #[test]
+    fn test_exit_code_for() {
+        let elapsed_error = SystemTime::UNIX_EPOCH
+            .duration_since(SystemTime::now())
+            .unwrap_err();
+        let s = || "test".to_owned();
+        let cases = vec![
+            (RMesgError::OperationNotPermitted(s()), EX_NOPERM),
+            (
+                RMesgError::DeviceNotFound("/dev/kmsg".into()),
+                EX_UNAVAILABLE,
+            ),
+            (RMesgError::DevKMsgFileOpenError(s()), EX_UNAVAILABLE),
+            (RMesgError::EntryParsingError(s()), EX_DATAERR),
+            (
+                RMesgError::KMsgParseError {
+                    line: s(),
+                    reason: s(),
+                },
+                EX_DATAERR,
+            ),
+            (RMesgError::Utf8StringConversionError(s()), EX_DATAERR),
+            (RMesgError::IntegerOutOfBound(s()), EX_DATAERR),
+            (RMesgError::UnsupportedFilter(s()), EX_USAGE),
+            (RMesgError::UnknownLogLevel(s()), EX_USAGE),
+            (RMesgError::UnknownLogFacility(s()), EX_USAGE),
+            (RMesgError::UnknownBackend(s()), EX_USAGE),
+            (RMesgError::IOError(s()), EX_IOERR),
+            (RMesgError::NotImplementedForThisPlatform, EXIT_FAILURE),
+            (RMesgError::NotSupportedOnThisPlatform(s()), EXIT_FAILURE),
+            (RMesgError::UnsupportedOperation(s()), EXIT_FAILURE),
+            (RMesgError::UnableToObtainSystemTime, EXIT_FAILURE),
+            (RMesgError::UnableToAddDurationToSystemTime, EXIT_FAILURE),
+            (
+                RMesgError::UnableToObtainElapsedTime(elapsed_error),
+                EXIT_FAILURE,
+            ),
+            (RMesgError::KLogTimestampsDisabled, EXIT_FAILURE),
+            (RMesgError::InternalError(s()), EXIT_FAILURE),
+        ];
+        for (e, code) in cases {
+            assert_eq!(exit_code_for(&e), code, "{}", e);
+            assert_ne!(code, EXIT_SUCCESS);
+        }
+    }
+
+    #[test]
+    fn test_report_print_error() {
+        let broken_pipe = io::Error::from(io::ErrorKind::BrokenPipe);
+        assert_eq!(report_print_error(broken_pipe), EXIT_SUCCESS);
+
+        let full = io::Error::from(io::ErrorKind::StorageFull);
+        assert_eq!(report_print_error(full), EX_IOERR);
+    }
+
+    #[test]
+    fn test_grep_flags() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert!(opts.grep.is_none());
+        assert!(!opts.grep_invert);
+
+        let opts = parse_args_from(&["rmesg", "-g", "(?i)oom"]);
+        assert_eq!(opts.grep.unwrap().as_str(), "(?i)oom");
+        assert!(!opts.grep_invert);
+
+        let opts = parse_args_from(&["rmesg", "--grep", "usb", "--grep-invert"]);
+        assert_eq!(opts.grep.unwrap().as_str(), "usb");
+        assert!(opts.grep_invert);
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "--grep", "(unclosed"])
+            .is_err());
+
+        let result = command().try_get_matches_from(["rmesg", "--grep-invert"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::MissingRequiredArgument
+        );
+    }
+
+    #[test]
+    fn test_buffer_size_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert_eq!(opts.buffer_size, None);
+
+        let opts = parse_args_from(&["rmesg", "-s", "1048576", "-b", "klogctl"]);
+        assert_eq!(opts.buffer_size, Some(1048576));
+
+        let opts = parse_args_from(&["rmesg", "--buffer-size", "4096"]);
+        assert_eq!(opts.buffer_size, Some(4096));
+
+        assert!(command()
+            .try_get_matches_from(["rmesg", "-s", "big"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_max_rate_flag() {
+        let opts = parse_args_from(&["rmesg", "-f"]);
+        assert_eq!(opts.max_rate, None);
+
+        let opts = parse_args_from(&["rmesg", "-f", "--max-rate", "100"]);
+        assert_eq!(opts.max_rate, Some(100));
+        let opts = parse_args_from(&["rmesg", "-W", "--max-rate", "100"]);
+        assert_eq!(opts.max_rate, Some(100));
+
+        // Only while following
+        let result = command().try_get_matches_from(["rmesg", "--max-rate", "100"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::MissingRequiredArgument
+        );
+
+        for invalid in ["0", "-1", "fast"].iter() {
+            assert!(command()
+                .try_get_matches_from(["rmesg", "-f", "--max-rate", invalid])
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(3, start);
+
+        // A burst of 10 within the first second prints 3
+        let admitted: Vec<_> = (0..10)
+            .map(|i| limiter.admit(start + Duration::from_millis(i * 10)))
+            .collect();
+        assert_eq!(
+            admitted,
+            vec![
+                Some(0),
+                Some(0),
+                Some(0),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+        assert_eq!(limiter.suppressed(), 7);
+
+        // The first entry of the next second notes what was dropped
+        let next_second = start + Duration::from_secs(1);
+        assert_eq!(limiter.admit(next_second), Some(7));
+        assert_eq!(limiter.suppressed(), 0);
+        assert_eq!(limiter.admit(next_second), Some(0));
+        assert_eq!(limiter.admit(next_second), Some(0));
+        assert_eq!(limiter.admit(next_second), None);
+        assert_eq!(limiter.suppressed(), 1);
+        assert_eq!(limiter.take_suppressed(), 1);
+        assert_eq!(limiter.suppressed(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_follow_notes_suppressed_without_new_entries() {
+        // A burst, then nothing more gets logged
+        let burst = (0..10).map(|i| {
+            Ok(Entry {
+                message: format!("entry {}", i),
+                ..Default::default()
+            })
+        });
+        let entries = futures_util::stream::iter(burst).chain(futures_util::stream::pending());
+
+        let mut printer = Printer::new(&parse_args_from(&["rmesg", "-f"])).unwrap();
+        let mut out = Vec::new();
+        let mut notes = Vec::new();
+        let following = follow(entries, Some(3), &mut printer, &mut out, &mut notes);
+        assert!(
+            tokio::time::timeout(SUPPRESSED_NOTE_INTERVAL * 2, following)
+                .await
+                .is_err(),
+            "Following shouldn't end while the stream doesn't"
+        );
+
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 3);
+        assert_eq!(
+            String::from_utf8(notes).unwrap(),
+            "rmesg: suppressed 7 messages over --max-rate\n"
+        );
+    }
+
+    #[test]
+    fn test_logfmt_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert!(!opts.logfmt);
+
+        let opts = parse_args_from(&["rmesg", "--logfmt", "-f"]);
+        assert!(opts.logfmt);
+        assert!(opts.follow);
+
+        for conflicting in ["-J", "-r", "-T"].iter() {
+            let result = command().try_get_matches_from(["rmesg", "--logfmt", conflicting]);
+            assert_eq!(
+                result.unwrap_err().kind(),
+                clap::error::ErrorKind::ArgumentConflict
+            );
+        }
+    }
+
+    #[test]
+    fn test_stats_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert!(!opts.stats);
+
+        let opts = parse_args_from(&["rmesg", "--stats", "-b", "klogctl"]);
+        assert!(opts.stats);
+
+        let result = command().try_get_matches_from(["rmesg", "--stats", "-f"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats_filtered() {
+        // Only the messages the other flags select are counted
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/kmsg.txt");
+        let opts = parse_args_from(&["rmesg", "--stats", "--kmsg-path", fixture, "-l", "info,err"]);
+        let mut written = Vec::new();
+        assert_eq!(
+            run_with(opts, rmesg::check_clear_access, &mut written).await,
+            EXIT_SUCCESS
+        );
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "emerg   0\nalert   0\ncrit    0\nerr     1\nwarn    0\nnotice  0\ninfo    2\ndebug   0\n"
+        );
+    }
+
+    #[test]
+    fn test_table_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert!(!opts.table);
+
+        let opts = parse_args_from(&["rmesg", "--table", "-n", "5"]);
+        assert!(opts.table);
+
+        let result = command().try_get_matches_from(["rmesg", "--table", "-f"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn test_table_column_widths() {
+        use rmesg::entry::LogFacility;
+
+        let entry = |secs: Option<f64>, facility, level| Entry {
+            facility,
+            level,
+            timestamp_from_system_start: secs.map(Duration::from_secs_f64),
+            message: "message".to_owned(),
+            ..Default::default()
+        };
+        let entries = [
+            entry(Some(0.5), Some(LogFacility::Kern), Some(LogLevel::Info)),
+            entry(
+                Some(24241.325252),
+                Some(LogFacility::Daemon),
+                Some(LogLevel::Emergency),
+            ),
+            entry(None, None, None),
+        ];
+
+        let rows: Vec<_> = entries.iter().map(table_cells).collect();
+        assert_eq!(rows[0], ["0.500000", "kern", "info"]);
+        assert_eq!(rows[2], ["", "", ""]);
+
+        // The widest of each column, or its header
+        assert_eq!(table_column_widths(&rows), [12, 8, 5]);
+        assert_eq!(table_column_widths(&rows[..1]), [9, 8, 5]);
+        assert_eq!(table_column_widths(&[]), [9, 8, 5]);
+    }
+
+    #[test]
+    fn test_wrap_message() {
+        assert_eq!(wrap_message("short", 10), vec!["short"]);
+        assert_eq!(wrap_message("", 10), vec![""]);
+        assert_eq!(
+            wrap_message("0123456789abcdefghijXY", 10),
+            vec!["0123456789", "abcdefghij", "XY"]
+        );
+        assert_eq!(wrap_message("ééé", 2), vec!["éé", "é"]);
+    }
+
+    #[test]
+    fn test_kmsg_path_flag() {
+        let opts = parse_args_from(&["rmesg"]);
+        assert_eq!(opts.kmsg_path, None);
+
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/kmsg.txt");
+        let opts = parse_args_from(&["rmesg", "--kmsg-path", fixture]);
+        assert_eq!(opts.kmsg_path, Some(PathBuf::from(fixture)));
+        assert_eq!(opts.backend, rmesg::Backend::DevKMsg);
+
+        let entries = rmesg_options(&opts, EntryFilter::default())
+            .entries()
+            .unwrap();
+        assert_eq!(entries.len(), 4);
+
+        let opts = parse_args_from(&["rmesg", "--kmsg-path", fixture, "-b", "klogctl"]);
+        assert_eq!(opts.backend, rmesg::Backend::KLogCtl);
+    }
+
+    #[test]
+    fn test_json_and_raw_conflict() {
+        let result = command().try_get_matches_from(["rmesg", "-J", "-r"]);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
 }
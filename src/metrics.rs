@@ -0,0 +1,286 @@
+use crate::entry::{Entry, LogLevel};
+use crate::error::RMesgError;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "async")]
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use futures::stream::Stream;
+#[cfg(feature = "async")]
+use futures::task::{Context, Poll};
+
+/// Counters of what flowed through a stream (or iterator) of entries: entries in total and
+/// by level, records that couldn't be parsed, and records lost before they were read.
+///
+/// The counters are atomic, so the metrics can be shared (in an `Arc`) between the task
+/// reading entries and the one serving them, e.g. with `render_prometheus`:
+///
+/// ```no_run
+/// use rmesg::metrics::Metrics;
+/// use rmesg::{Backend, RmesgOptions};
+/// use std::sync::Arc;
+///
+/// let metrics = Arc::new(Metrics::new());
+/// let entries = RmesgOptions::new().backend(Backend::File("kern.log".into())).entries()?;
+/// for entry in metrics.wrap_stream(entries.into_iter().map(Ok)) {
+///     // ...
+/// #   let _ = entry;
+/// }
+/// print!("{}", metrics.render_prometheus());
+/// # Ok::<(), rmesg::error::RMesgError>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct Metrics {
+    entries: AtomicU64,
+    // Indexed by severity, with entries without a level last
+    levels: [AtomicU64; 9],
+    parse_errors: AtomicU64,
+    dropped_records: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps a stream (or iterator) of entries, to count what goes through it in these metrics
+    pub fn wrap_stream<S>(self: &Arc<Self>, inner: S) -> Metered<S> {
+        Metered {
+            inner,
+            metrics: self.clone(),
+            last_sequence_num: None,
+        }
+    }
+
+    /// Entries read
+    pub fn entries(&self) -> u64 {
+        self.entries.load(Ordering::Relaxed)
+    }
+
+    /// Entries read at this level (or without a level, for None)
+    pub fn level_count(&self, level: Option<LogLevel>) -> u64 {
+        self.levels[level_index(level)].load(Ordering::Relaxed)
+    }
+
+    /// Records that couldn't be parsed into entries
+    pub fn parse_errors(&self) -> u64 {
+        self.parse_errors.load(Ordering::Relaxed)
+    }
+
+    /// Records lost before they were read, as told by gaps in sequence numbers (see
+    /// `dropped::DetectDroppedRecords`), or added with `add_dropped_records`
+    pub fn dropped_records(&self) -> u64 {
+        self.dropped_records.load(Ordering::Relaxed)
+    }
+
+    /// Counts records known to be lost some other way, e.g. those
+    /// `KMsgEntriesStream::dropped_records` counts
+    pub fn add_dropped_records(&self, dropped_records: u64) {
+        self.dropped_records
+            .fetch_add(dropped_records, Ordering::Relaxed);
+    }
+
+    /// Renders the counters in the Prometheus text exposition format
+    pub fn render_prometheus(&self) -> String {
+        let mut rendered = String::new();
+        // Writing into a String can't fail
+        let _ = self.write_prometheus(&mut rendered);
+        rendered
+    }
+
+    fn write_prometheus(&self, w: &mut String) -> std::fmt::Result {
+        write_counter(
+            w,
+            "rmesg_entries_total",
+            "Entries read from the kernel log.",
+        )?;
+        writeln!(w, "rmesg_entries_total {}", self.entries())?;
+
+        write_counter(
+            w,
+            "rmesg_entries_by_level_total",
+            "Entries read from the kernel log, by level.",
+        )?;
+        let levels = LogLevel::all()
+            .iter()
+            .copied()
+            .map(Some)
+            .chain(std::iter::once(None));
+        for level in levels {
+            writeln!(
+                w,
+                "rmesg_entries_by_level_total{{level=\"{}\"}} {}",
+                level.map_or_else(|| "unknown".to_owned(), |level| level.to_string()),
+                self.level_count(level)
+            )?;
+        }
+
+        write_counter(
+            w,
+            "rmesg_parse_errors_total",
+            "Records that couldn't be parsed into entries.",
+        )?;
+        writeln!(w, "rmesg_parse_errors_total {}", self.parse_errors())?;
+
+        write_counter(
+            w,
+            "rmesg_dropped_records_total",
+            "Records lost before they were read.",
+        )?;
+        writeln!(w, "rmesg_dropped_records_total {}", self.dropped_records())
+    }
+}
+
+fn level_index(level: Option<LogLevel>) -> usize {
+    match level {
+        Some(level) => level.as_severity().into(),
+        None => 8,
+    }
+}
+
+fn write_counter(w: &mut String, name: &str, help: &str) -> std::fmt::Result {
+    writeln!(w, "# HELP {} {}", name, help)?;
+    writeln!(w, "# TYPE {} counter", name)
+}
+
+/// Counts the entries (and errors) of the stream or iterator it wraps, as they pass through
+/// unchanged. Made with `Metrics::wrap_stream`.
+pub struct Metered<I> {
+    inner: I,
+    metrics: Arc<Metrics>,
+    last_sequence_num: Option<usize>,
+}
+
+impl<I> Metered<I> {
+    fn count(&mut self, next: &Option<Result<Entry, RMesgError>>) {
+        let metrics = &self.metrics;
+        match next {
+            Some(Ok(entry)) => {
+                metrics.entries.fetch_add(1, Ordering::Relaxed);
+                metrics.levels[level_index(entry.level)].fetch_add(1, Ordering::Relaxed);
+
+                // Like DetectDroppedRecords: going backwards isn't a gap
+                if let Some(sequence_num) = entry.sequence_num {
+                    if let Some(last) = self.last_sequence_num.replace(sequence_num) {
+                        if sequence_num > last + 1 {
+                            metrics.add_dropped_records((sequence_num - last - 1) as u64);
+                        }
+                    }
+                }
+            }
+            Some(Err(RMesgError::KMsgParseError { .. }))
+            | Some(Err(RMesgError::EntryParsingError(_))) => {
+                metrics.parse_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(Err(_)) | None => {}
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Entry, RMesgError>>> Iterator for Metered<I> {
+    type Item = Result<Entry, RMesgError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.inner.next();
+        self.count(&next);
+        next
+    }
+}
+
+#[cfg(feature = "async")]
+impl<S: Stream<Item = Result<Entry, RMesgError>> + Unpin> Stream for Metered<S> {
+    type Item = Result<Entry, RMesgError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(next) => {
+                this.count(&next);
+                Poll::Ready(next)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util;
+
+    fn entry(sequence_num: usize, level: Option<LogLevel>) -> Result<Entry, RMesgError> {
+        Ok(Entry {
+            level,
+            sequence_num: Some(sequence_num),
+            ..test_util::entry("Test message")
+        })
+    }
+
+    fn entries() -> Vec<Result<Entry, RMesgError>> {
+        vec![
+            entry(1, Some(LogLevel::Info)),
+            entry(2, Some(LogLevel::Warning)),
+            Err(RMesgError::KMsgParseError {
+                line: "bad".to_owned(),
+                reason: "Test".to_owned(),
+            }),
+            // Records 3 to 5 were lost
+            entry(6, Some(LogLevel::Info)),
+            entry(7, None),
+            Err(RMesgError::IOError("Not a parse error".to_owned())),
+        ]
+    }
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Arc::new(Metrics::new());
+        let passed: Vec<_> = metrics.wrap_stream(entries().into_iter()).collect();
+        assert_eq!(passed.len(), 6);
+
+        assert_eq!(metrics.entries(), 4);
+        assert_eq!(metrics.level_count(Some(LogLevel::Info)), 2);
+        assert_eq!(metrics.level_count(Some(LogLevel::Warning)), 1);
+        assert_eq!(metrics.level_count(Some(LogLevel::Error)), 0);
+        assert_eq!(metrics.level_count(None), 1);
+        assert_eq!(metrics.parse_errors(), 1);
+        assert_eq!(metrics.dropped_records(), 3);
+
+        metrics.add_dropped_records(2);
+        let rendered = metrics.render_prometheus();
+        for line in [
+            "# TYPE rmesg_entries_total counter",
+            "rmesg_entries_total 4",
+            "rmesg_entries_by_level_total{level=\"emerg\"} 0",
+            "rmesg_entries_by_level_total{level=\"warn\"} 1",
+            "rmesg_entries_by_level_total{level=\"info\"} 2",
+            "rmesg_entries_by_level_total{level=\"unknown\"} 1",
+            "rmesg_parse_errors_total 1",
+            "rmesg_dropped_records_total 5",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "{} not in:\n{}",
+                line,
+                rendered
+            );
+        }
+        assert_eq!(rendered.lines().count(), 20);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_metrics_stream() {
+        use futures::stream::{self, StreamExt};
+
+        let metrics = Arc::new(Metrics::new());
+        let passed: Vec<_> = metrics.wrap_stream(stream::iter(entries())).collect().await;
+        assert_eq!(passed.len(), 6);
+        assert_eq!(metrics.entries(), 4);
+        assert_eq!(metrics.dropped_records(), 3);
+    }
+}
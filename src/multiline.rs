@@ -0,0 +1,290 @@
+use crate::entry::Entry;
+use crate::error::RMesgError;
+use regex::Regex;
+
+#[cfg(feature = "async")]
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use futures::stream::Stream;
+#[cfg(feature = "async")]
+use futures::task::{Context, Poll};
+
+/// How `GroupMultiline` tells that an entry continues the one before it.
+/// An entry continues the one before when any of the enabled rules match it.
+#[derive(Clone, Debug)]
+pub struct MultilineHeuristic {
+    /// Entries without a priority (facility and level) continue the one before, like the
+    /// lines after the first of a message with line breaks, read through klogctl.
+    pub without_priority: bool,
+
+    /// Entries whose message starts with whitespace continue the one before, like the
+    /// frames of a call trace:
+    ///  dump_stack_lvl+0x48/0x70
+    pub indented: bool,
+
+    /// When set, entries whose message matches this continue the one before, e.g.
+    /// `^(RIP|RSP|RAX|Code):` for the registers dumped with an oops.
+    pub pattern: Option<Regex>,
+}
+
+impl Default for MultilineHeuristic {
+    fn default() -> Self {
+        Self {
+            without_priority: true,
+            indented: true,
+            pattern: None,
+        }
+    }
+}
+
+impl MultilineHeuristic {
+    /// Returns true if the entry looks like it continues the one before it
+    pub fn continues(&self, entry: &Entry) -> bool {
+        (self.without_priority && entry.facility.is_none() && entry.level.is_none())
+            || (self.indented && entry.message.starts_with(char::is_whitespace))
+            || self
+                .pattern
+                .as_ref()
+                .is_some_and(|pattern| pattern.is_match(&entry.message))
+    }
+}
+
+/// Wraps an iterator or stream of entries, and merges entries that continue the one before
+/// them (see `MultilineHeuristic`) into it, so that a dump spanning many entries (like a
+/// call trace or an oops) is yielded as one entry. The merged messages are joined with
+/// line breaks, and the entry keeps the facility, level, sequence number and timestamp of
+/// the first one.
+///
+/// An entry is only yielded once the next entry shows it isn't continued. Iterators wait
+/// for it, so when following, an entry comes when the next one gets logged. Streams don't
+/// wait for entries that aren't ready yet, and yield what they have grouped so far instead.
+pub struct GroupMultiline<I> {
+    inner: I,
+    heuristic: MultilineHeuristic,
+
+    // The entry being grouped, until an entry that doesn't continue it comes along
+    grouped: Option<Entry>,
+
+    // What the inner iterator/stream returned after a group, to be returned after it
+    queued: Option<Option<Result<Entry, RMesgError>>>,
+}
+
+/// Groups multiline dumps with the default `MultilineHeuristic`. Same as `GroupMultiline::new`.
+pub fn group_multiline<I>(inner: I) -> GroupMultiline<I> {
+    GroupMultiline::new(inner)
+}
+
+impl<I> GroupMultiline<I> {
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            heuristic: MultilineHeuristic::default(),
+            grouped: None,
+            queued: None,
+        }
+    }
+
+    /// Sets how entries continuing the one before are told apart
+    pub fn with_heuristic(mut self, heuristic: MultilineHeuristic) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    // Takes the next item from the inner iterator/stream, and returns what to return in
+    // its place, or None when it was grouped and the next item needs to be taken.
+    fn group(
+        &mut self,
+        next: Option<Result<Entry, RMesgError>>,
+    ) -> Option<Option<Result<Entry, RMesgError>>> {
+        match next {
+            Some(Ok(entry)) => match &mut self.grouped {
+                Some(grouped) if self.heuristic.continues(&entry) => {
+                    merge(grouped, entry);
+                    None
+                }
+                _ => self.grouped.replace(entry).map(|grouped| Some(Ok(grouped))),
+            },
+            next => match self.grouped.take() {
+                Some(grouped) => {
+                    self.queued = Some(next);
+                    Some(Some(Ok(grouped)))
+                }
+                None => Some(next),
+            },
+        }
+    }
+}
+
+fn merge(grouped: &mut Entry, entry: Entry) {
+    grouped.message.push('\n');
+    grouped.message.push_str(&entry.message);
+
+    if let (Some(grouped_line), Some(line)) = (&mut grouped.raw_line, entry.raw_line) {
+        grouped_line.push('\n');
+        grouped_line.push_str(&line);
+    }
+}
+
+impl<I: Iterator<Item = Result<Entry, RMesgError>>> Iterator for GroupMultiline<I> {
+    type Item = Result<Entry, RMesgError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(queued) = self.queued.take() {
+            return queued;
+        }
+
+        loop {
+            let next = self.inner.next();
+            if let Some(grouped) = self.group(next) {
+                return grouped;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<S: Stream<Item = Result<Entry, RMesgError>> + Unpin> Stream for GroupMultiline<S> {
+    type Item = Result<Entry, RMesgError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(queued) = this.queued.take() {
+            return Poll::Ready(queued);
+        }
+
+        loop {
+            let next = match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(next) => next,
+                // Not waiting for what isn't ready yet
+                Poll::Pending => {
+                    return match this.grouped.take() {
+                        Some(grouped) => Poll::Ready(Some(Ok(grouped))),
+                        None => Poll::Pending,
+                    }
+                }
+            };
+            if let Some(grouped) = this.group(next) {
+                return Poll::Ready(grouped);
+            }
+        }
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::{LogFacility, LogLevel};
+    use crate::kmsgfile;
+
+    // An oops as read from /dev/kmsg, between two unrelated entries
+    const OOPS: &str = "6,1000,5140900,-;usb 1-1: new high-speed USB device number 2
+1,1001,227000001,-;BUG: kernel NULL pointer dereference, address: 0000000000000008
+1,1002,227000002,-;#PF: supervisor read access in kernel mode
+4,1003,227000003,-;CPU: 2 PID: 1234 Comm: stress Not tainted 6.1.0-21-amd64
+4,1004,227000004,-;Call Trace:
+4,1005,227000005,-; <TASK>
+4,1006,227000006,-; dump_stack_lvl+0x48/0x70
+4,1007,227000007,-; ? __schedule+0x2f0/0xa60
+4,1008,227000008,-; </TASK>
+6,1009,227500000,-;EXT4-fs (vda): mounted filesystem
+";
+
+    fn oops_entries() -> Vec<Result<Entry, RMesgError>> {
+        kmsgfile::records(OOPS)
+            .map(kmsgfile::parse_record)
+            .collect()
+    }
+
+    #[test]
+    fn test_group_call_trace() {
+        let entries: Vec<Entry> = group_multiline(oops_entries().into_iter())
+            .map(Result::unwrap)
+            .collect();
+
+        let messages: Vec<&str> = entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "usb 1-1: new high-speed USB device number 2",
+                "BUG: kernel NULL pointer dereference, address: 0000000000000008",
+                "#PF: supervisor read access in kernel mode",
+                "CPU: 2 PID: 1234 Comm: stress Not tainted 6.1.0-21-amd64",
+                "Call Trace:\n <TASK>\n dump_stack_lvl+0x48/0x70\n ? __schedule+0x2f0/0xa60\n </TASK>",
+                "EXT4-fs (vda): mounted filesystem",
+            ]
+        );
+        assert_eq!(entries[4].sequence_num, Some(1004));
+        assert_eq!(entries[4].level, Some(LogLevel::Warning));
+    }
+
+    #[test]
+    fn test_group_whole_oops() {
+        // Everything from the BUG line on, up to the end of the trace
+        let heuristic = MultilineHeuristic {
+            pattern: Some(Regex::new(r"^(#PF|CPU|Call Trace):").unwrap()),
+            ..Default::default()
+        };
+        let entries: Vec<Entry> = GroupMultiline::new(oops_entries().into_iter())
+            .with_heuristic(heuristic)
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].sequence_num, Some(1001));
+        assert_eq!(entries[1].facility, Some(LogFacility::Kern));
+        assert!(entries[1].message.starts_with("BUG: kernel NULL pointer"));
+        assert!(entries[1].message.ends_with(" </TASK>"));
+        assert_eq!(entries[1].message.lines().count(), 8);
+    }
+
+    #[test]
+    fn test_without_priority_and_errors() {
+        let entry = |message: &str, level: Option<LogLevel>| Entry {
+            facility: level.map(|_| LogFacility::Kern),
+            level,
+            message: message.to_owned(),
+            metadata: std::collections::HashMap::new(),
+            ..Default::default()
+        };
+        let entries = vec![
+            Ok(entry("first", Some(LogLevel::Info))),
+            Ok(entry("continued", None)),
+            Err(RMesgError::InternalError("Parsing failed".to_owned())),
+            Ok(entry("after the error", None)),
+        ];
+
+        let grouped: Vec<_> = group_multiline(entries.into_iter()).collect();
+        assert_eq!(grouped.len(), 3);
+        assert_eq!(grouped[0].as_ref().unwrap().message, "first\ncontinued");
+        assert!(grouped[1].is_err());
+        assert_eq!(grouped[2].as_ref().unwrap().message, "after the error");
+
+        // With the rule off, nothing is grouped
+        let entries = vec![
+            Ok(entry("first", Some(LogLevel::Info))),
+            Ok(entry("continued", None)),
+        ];
+        let heuristic = MultilineHeuristic {
+            without_priority: false,
+            ..Default::default()
+        };
+        let grouped = GroupMultiline::new(entries.into_iter()).with_heuristic(heuristic);
+        assert_eq!(grouped.count(), 2);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_stream() {
+        use futures::stream::{self, StreamExt};
+
+        let messages: Vec<String> = group_multiline(stream::iter(oops_entries()))
+            .map(|e| e.unwrap().message)
+            .collect()
+            .await;
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[4].lines().count(), 5);
+    }
+}
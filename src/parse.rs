@@ -0,0 +1,179 @@
+// Only core and alloc are used here (no std, and no other crates), so that this module
+// also builds in no_std crates. tests/no_std.rs makes sure it keeps doing so.
+use alloc::format;
+use alloc::string::String;
+use core::any::type_name;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::str::FromStr;
+use core::time::Duration;
+
+const LEVEL_MASK: u32 = (1 << 3) - 1;
+
+/// Splits a syslog priority (`facility * 8 + level`) into its facility and level codes
+pub fn split_priority(priority: u32) -> (u32, u32) {
+    (priority >> 3, priority & LEVEL_MASK)
+}
+
+/// Combines facility and level codes into a syslog priority (`facility * 8 + level`)
+pub fn join_priority(facility: u8, level: u8) -> u8 {
+    (facility << 3) + level
+}
+
+/// The fields of a record from /dev/kmsg, borrowed from the line it was parsed from.
+///
+/// Displays like /dev/kmsg has it, i.e.:
+/// 6,779,91650777797,-;docker0: port 2(veth98d5024) entered disabled state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KMsgRecord<'a> {
+    /// The syslog priority (see `split_priority`)
+    pub priority: u32,
+    pub sequence_num: usize,
+    pub timestamp_from_system_start: Duration,
+    /// The flags field: `-` for none, `c` for a fragment of a line printed in pieces
+    pub flags: &'a str,
+    pub message: &'a str,
+}
+
+impl Display for KMsgRecord<'_> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "{},{},{},{};{}",
+            self.priority,
+            self.sequence_num,
+            self.timestamp_from_system_start.as_micros(),
+            self.flags,
+            self.message
+        )
+    }
+}
+
+/// Why a line that looked like a /dev/kmsg record couldn't be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError(pub String);
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parses a line read from /dev/kmsg (without its continuation lines), like:
+/// 6,3,0,-,more,deets;x86/fpu: Supporting XSAVE; feature 0x002: 'SSE registers'
+///
+/// Returns None for lines that don't start with the priority, sequence number and timestamp
+/// (like continuation lines), which are all message.
+pub fn parse_kmsg_line(line: &str) -> Result<Option<KMsgRecord<'_>>, ParseError> {
+    let (header, message) = match line.split_once(';') {
+        Some((header, message)) if !message.contains('\n') => (header, message),
+        _ => return Ok(None),
+    };
+
+    // Fields after the flags (added later on, like the caller) are ignored
+    let mut fields = header.split(',').map(|field| field.trim_matches(is_space));
+    let (priority, sequence_num, timestamp, flags) =
+        match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(priority), Some(sequence_num), Some(timestamp), Some(flags))
+                if [priority, sequence_num, timestamp]
+                    .iter()
+                    .all(|field| field.bytes().all(|b| b.is_ascii_digit())) =>
+            {
+                (priority, sequence_num, timestamp, flags)
+            }
+            _ => return Ok(None),
+        };
+
+    Ok(Some(KMsgRecord {
+        priority: parse_field(priority, line)?,
+        sequence_num: parse_field(sequence_num, line)?,
+        timestamp_from_system_start: Duration::from_micros(parse_field(timestamp, line)?),
+        flags,
+        message,
+    }))
+}
+
+/// Parses a fragment of a line into a number (or anything else parsed from a string)
+pub fn parse_field<N: FromStr>(field: &str, line: &str) -> Result<N, ParseError>
+where
+    N::Err: Display,
+{
+    field.trim().parse().map_err(|e| {
+        ParseError(format!(
+            "Unable to parse {} into a {} due to error: {}\nLine: {}",
+            field,
+            type_name::<N>(),
+            e,
+            line,
+        ))
+    })
+}
+
+// Whitespace like the POSIX [[:space:]] class (which, unlike char::is_ascii_whitespace,
+// includes the vertical tab)
+fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\x0B' | '\x0C' | '\r')
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_kmsg_line() {
+        let line = "6,3,91650777797,-,more,deets;x86/fpu: Supporting XSAVE; feature 0x002";
+        let record = parse_kmsg_line(line).unwrap().unwrap();
+        assert_eq!(
+            record,
+            KMsgRecord {
+                priority: 6,
+                sequence_num: 3,
+                timestamp_from_system_start: Duration::from_micros(91650777797),
+                flags: "-",
+                message: "x86/fpu: Supporting XSAVE; feature 0x002",
+            }
+        );
+        assert_eq!(
+            format!("{}", record),
+            "6,3,91650777797,-;x86/fpu: Supporting XSAVE; feature 0x002"
+        );
+
+        // Whitespace around the numbers is fine
+        let record = parse_kmsg_line(" 14 , 7 ,0,-;spaced").unwrap().unwrap();
+        assert_eq!((record.priority, record.sequence_num), (14, 7));
+
+        // The flags of a fragment, with the caller after them
+        let record = parse_kmsg_line("4,8,9,c,caller=T1;usb 1-1:")
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.flags, "c");
+        assert_eq!(format!("{}", record), "4,8,9,c;usb 1-1:");
+
+        for line in [
+            " LINE2=foobar",
+            "no header; at all",
+            "1,2;too few fields",
+            "",
+        ] {
+            assert_eq!(parse_kmsg_line(line), Ok(None), "{:?}", line);
+        }
+
+        for line in [
+            ",2,3,-;no priority",
+            "6,99999999999999999999999,0,-;overflow",
+        ] {
+            let e = parse_kmsg_line(line).unwrap_err();
+            assert!(e.0.starts_with("Unable to parse"), "{}", e);
+            assert!(e.0.ends_with(line), "{}", e);
+        }
+    }
+
+    #[test]
+    fn test_priority() {
+        assert_eq!(split_priority(6), (0, 6));
+        assert_eq!(split_priority(191), (23, 7));
+        assert_eq!(join_priority(23, 7), 191);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::entry::Entry;
+use crate::error::RMesgError;
+use core::pin::Pin;
+use futures::stream::{self, Stream, StreamExt};
+use futures::task::{Context, Poll};
+
+/// Wraps a stream of entries (e.g. a `kmsgfile::KMsgEntriesStream` or `klogctl::KLogEntries`), so the
+/// next entry can be looked at with `peek` without consuming it. Iterators can use
+/// `Iterator::peekable` instead.
+pub struct Peekable<S: Stream> {
+    inner: stream::Peekable<S>,
+}
+
+impl<S: Stream<Item = Result<Entry, RMesgError>> + Unpin> Peekable<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner: inner.peekable(),
+        }
+    }
+
+    /// Waits for the next item, and returns it without consuming it: the next poll of the
+    /// stream yields the same item. Returns None when the stream has ended.
+    ///
+    /// Dropping the returned future before it completes loses nothing.
+    pub async fn peek(&mut self) -> Option<&Result<Entry, RMesgError>> {
+        Pin::new(&mut self.inner).peek().await
+    }
+}
+
+impl<S: Stream<Item = Result<Entry, RMesgError>> + Unpin> Stream for Peekable<S> {
+    type Item = Result<Entry, RMesgError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.get_mut().inner).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::filter::EntryFilter;
+    use crate::kmsgfile::KMsgEntriesStream;
+    use crate::test_util::entry;
+    use futures::stream;
+
+    fn message(item: Option<&Result<Entry, RMesgError>>) -> Option<String> {
+        item.map(|item| item.as_ref().unwrap().message.clone())
+    }
+
+    #[tokio::test]
+    async fn test_peek() {
+        let mut entries =
+            Peekable::new(stream::iter(vec![Ok(entry("first")), Ok(entry("second"))]));
+
+        // Peeking again doesn't move past the entry
+        assert_eq!(message(entries.peek().await), Some("first".to_owned()));
+        assert_eq!(message(entries.peek().await), Some("first".to_owned()));
+        assert_eq!(
+            message(entries.next().await.as_ref()),
+            Some("first".to_owned())
+        );
+
+        assert_eq!(
+            message(entries.next().await.as_ref()),
+            Some("second".to_owned())
+        );
+        assert!(entries.peek().await.is_none());
+        assert!(entries.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_peek_kmsg_stream() {
+        let reader = std::io::Cursor::new(
+            "6,339,5140900,-;NET: Registered protocol family 10
+6,340,5690716,-;usb 1-1: new high-speed USB device number 2
+",
+        );
+        let mut entries = Peekable::new(KMsgEntriesStream::from_async_reader(
+            reader,
+            false,
+            EntryFilter::default(),
+        ));
+
+        for sequence_num in [339, 340].iter() {
+            let peeked = entries.peek().await.unwrap().as_ref().unwrap().clone();
+            assert_eq!(peeked.sequence_num, Some(*sequence_num));
+            assert_eq!(entries.next().await.unwrap().unwrap(), peeked);
+        }
+        assert!(entries.peek().await.is_none());
+    }
+}
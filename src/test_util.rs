@@ -0,0 +1,10 @@
+use crate::entry::Entry;
+
+/// An entry with just a message. Tests that need more fields set them with
+/// `Entry { .., ..entry(message) }`.
+pub(crate) fn entry(message: &str) -> Entry {
+    Entry {
+        message: message.to_owned(),
+        ..Default::default()
+    }
+}
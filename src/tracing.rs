@@ -0,0 +1,155 @@
+use crate::entry::{Entry, LogLevel};
+#[cfg(feature = "async")]
+use crate::error::RMesgError;
+#[cfg(feature = "async")]
+use futures::stream::{Stream, StreamExt};
+use tracing::Level;
+
+// Levels of tracing events have to be constants, so each level gets its own event! call
+macro_rules! emit_at_level {
+    ($level:expr, $entry:expr) => {
+        tracing::event!(
+            $level,
+            facility = $entry.facility.map(|f| f.to_string()).as_deref(),
+            sequence_num = $entry.sequence_num,
+            timestamp_from_system_start = $entry
+                .timestamp_from_system_start
+                .map(|ts| ts.as_secs_f64()),
+            "{}",
+            $entry.message
+        )
+    };
+}
+
+/// The tracing level kernel log entries of this level are emitted at.
+///
+/// tracing has fewer levels than the kernel, so emergencies, alerts and critical
+/// messages are emitted as errors, and notices as info.
+pub fn tracing_level(level: LogLevel) -> Level {
+    match level {
+        LogLevel::Emergency | LogLevel::Alert | LogLevel::Critical | LogLevel::Error => {
+            Level::ERROR
+        }
+        LogLevel::Warning => Level::WARN,
+        LogLevel::Notice | LogLevel::Info => Level::INFO,
+        LogLevel::Debug => Level::DEBUG,
+    }
+}
+
+/// Emits the entry as a tracing event, with its message as the event message and its
+/// `facility`, `sequence_num` and `timestamp_from_system_start` (in seconds) as fields.
+///
+/// Entries without a level are emitted at `Level::INFO`.
+pub fn emit(entry: &Entry) {
+    match entry.level.map(tracing_level).unwrap_or(Level::INFO) {
+        Level::ERROR => emit_at_level!(Level::ERROR, entry),
+        Level::WARN => emit_at_level!(Level::WARN, entry),
+        Level::INFO => emit_at_level!(Level::INFO, entry),
+        Level::DEBUG => emit_at_level!(Level::DEBUG, entry),
+        Level::TRACE => emit_at_level!(Level::TRACE, entry),
+    }
+}
+
+/// Drains a stream of entries (such as one from `rmesg::logs_stream`) into tracing events,
+/// until the stream ends or yields an error, which is returned.
+#[cfg(feature = "async")]
+pub async fn forward_stream<S>(stream: S) -> Result<(), RMesgError>
+where
+    S: Stream<Item = Result<Entry, RMesgError>>,
+{
+    futures::pin_mut!(stream);
+    while let Some(entry) = stream.next().await {
+        emit(&entry?);
+    }
+
+    Ok(())
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::LogFacility;
+    use crate::test_util;
+
+    use std::time::Duration;
+    use tracing_test::traced_test;
+
+    fn entry(level: Option<LogLevel>, message: &str) -> Entry {
+        Entry {
+            facility: Some(LogFacility::Kern),
+            level,
+            sequence_num: Some(42),
+            timestamp_from_system_start: Some(Duration::from_secs_f64(1921.5)),
+            ..test_util::entry(message)
+        }
+    }
+
+    #[test]
+    fn test_tracing_level() {
+        assert_eq!(tracing_level(LogLevel::Emergency), Level::ERROR);
+        assert_eq!(tracing_level(LogLevel::Critical), Level::ERROR);
+        assert_eq!(tracing_level(LogLevel::Error), Level::ERROR);
+        assert_eq!(tracing_level(LogLevel::Warning), Level::WARN);
+        assert_eq!(tracing_level(LogLevel::Notice), Level::INFO);
+        assert_eq!(tracing_level(LogLevel::Info), Level::INFO);
+        assert_eq!(tracing_level(LogLevel::Debug), Level::DEBUG);
+    }
+
+    #[test]
+    #[traced_test]
+    fn test_emit() {
+        emit(&entry(Some(LogLevel::Critical), "Kernel panic imminent"));
+        emit(&entry(
+            Some(LogLevel::Warning),
+            "CPU temperature above threshold",
+        ));
+        emit(&entry(None, "Message without level"));
+
+        logs_assert(|lines: &[&str]| {
+            let expected = [
+                ("ERROR", "Kernel panic imminent"),
+                ("WARN", "CPU temperature above threshold"),
+                ("INFO", "Message without level"),
+            ];
+            for (level, message) in expected.iter() {
+                match lines.iter().find(|line| line.contains(message)) {
+                    Some(line) if line.contains(level) => {}
+                    Some(line) => return Err(format!("Expected level {} in: {}", level, line)),
+                    None => return Err(format!("No event with message: {}", message)),
+                }
+            }
+            Ok(())
+        });
+
+        assert!(logs_contain("facility=\"kern\""));
+        assert!(logs_contain("sequence_num=42"));
+        assert!(logs_contain("timestamp_from_system_start=1921.5"));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    #[traced_test]
+    async fn test_forward_stream() {
+        let entries = vec![
+            Ok(entry(Some(LogLevel::Debug), "First message")),
+            Ok(entry(Some(LogLevel::Error), "Second message")),
+        ];
+        assert!(forward_stream(futures::stream::iter(entries)).await.is_ok());
+        assert!(logs_contain("First message"));
+        assert!(logs_contain("Second message"));
+
+        let entries = vec![
+            Ok(entry(Some(LogLevel::Info), "Before the error")),
+            Err(RMesgError::InternalError("Stream broke".to_owned())),
+            Ok(entry(Some(LogLevel::Info), "After the error")),
+        ];
+        assert!(forward_stream(futures::stream::iter(entries))
+            .await
+            .is_err());
+        assert!(logs_contain("Before the error"));
+        assert!(!logs_contain("After the error"));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::entry::Entry;
+use crate::error::RMesgError;
+use crate::ClockSource;
+use std::time::SystemTime;
+
+#[cfg(feature = "async")]
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use futures::stream::Stream;
+#[cfg(feature = "async")]
+use futures::task::{Context, Poll};
+
+/// Wraps an iterator or stream of entries, and yields each along with the wall-clock time
+/// it was logged at (see `Entry::wall_clock_time`), or None for entries without a timestamp.
+///
+/// The boot time is asked for once, on the first entry, and reused for the rest. When
+/// asking fails, the error is yielded in place of the entry, and it is asked for again on
+/// the next one.
+pub struct WithWallClock<I, F> {
+    inner: I,
+    boot_time_provider: F,
+    boot_time: Option<SystemTime>,
+}
+
+/// Wraps entries in `WithWallClock`, asking for the boot time through `rmesg::system_boot_time`
+pub fn with_wall_clock<I>(
+    inner: I,
+    source: ClockSource,
+) -> WithWallClock<I, impl FnMut() -> Result<SystemTime, RMesgError>> {
+    WithWallClock::new(inner, move || crate::system_boot_time(source))
+}
+
+impl<I, F: FnMut() -> Result<SystemTime, RMesgError>> WithWallClock<I, F> {
+    /// `boot_time_provider` is asked for the boot time, e.g. to use one from elsewhere
+    pub fn new(inner: I, boot_time_provider: F) -> Self {
+        Self {
+            inner,
+            boot_time_provider,
+            boot_time: None,
+        }
+    }
+
+    fn attach(
+        &mut self,
+        next: Option<Result<Entry, RMesgError>>,
+    ) -> Option<Result<(Entry, Option<SystemTime>), RMesgError>> {
+        let entry = match next? {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let boot_time = match self.boot_time {
+            Some(boot_time) => boot_time,
+            None => match (self.boot_time_provider)() {
+                Ok(boot_time) => *self.boot_time.insert(boot_time),
+                Err(e) => return Some(Err(e)),
+            },
+        };
+
+        let wall_clock_time = entry.wall_clock_time(boot_time);
+        Some(Ok((entry, wall_clock_time)))
+    }
+}
+
+impl<I, F> Iterator for WithWallClock<I, F>
+where
+    I: Iterator<Item = Result<Entry, RMesgError>>,
+    F: FnMut() -> Result<SystemTime, RMesgError>,
+{
+    type Item = Result<(Entry, Option<SystemTime>), RMesgError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.inner.next();
+        self.attach(next)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<S, F> Stream for WithWallClock<S, F>
+where
+    S: Stream<Item = Result<Entry, RMesgError>> + Unpin,
+    F: FnMut() -> Result<SystemTime, RMesgError> + Unpin,
+{
+    type Item = Result<(Entry, Option<SystemTime>), RMesgError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(next) => Poll::Ready(this.attach(next)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/**********************************************************************************/
+// Tests! Tests! Tests!
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util;
+
+    use std::time::Duration;
+
+    fn entry(secs: Option<u64>) -> Entry {
+        Entry {
+            timestamp_from_system_start: secs.map(Duration::from_secs),
+            ..test_util::entry("Test message")
+        }
+    }
+
+    #[test]
+    fn test_boot_time_asked_for_once() {
+        let boot_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut asked = 0;
+        let entries = (0..100).map(|secs| Ok(entry(Some(secs))));
+
+        let times: Vec<_> = WithWallClock::new(entries, || {
+            asked += 1;
+            Ok(boot_time)
+        })
+        .map(|e| e.unwrap().1)
+        .collect();
+
+        assert_eq!(asked, 1);
+        assert_eq!(times.len(), 100);
+        assert_eq!(times[0], Some(boot_time));
+        assert_eq!(times[99], Some(boot_time + Duration::from_secs(99)));
+    }
+
+    #[test]
+    fn test_without_timestamps_and_errors() {
+        let boot_time = SystemTime::UNIX_EPOCH;
+        let mut asked = 0;
+        let entries = vec![
+            Ok(entry(None)),
+            Err(RMesgError::InternalError("Parsing failed".to_owned())),
+            Ok(entry(Some(5))),
+        ];
+
+        let mut with_wall_clock = WithWallClock::new(entries.into_iter(), || {
+            asked += 1;
+            match asked {
+                1 => Err(RMesgError::UnableToObtainSystemTime),
+                _ => Ok(boot_time),
+            }
+        });
+
+        // Failing to ask is yielded in place of the entry, and asked again after
+        assert!(matches!(
+            with_wall_clock.next(),
+            Some(Err(RMesgError::UnableToObtainSystemTime))
+        ));
+        assert!(matches!(
+            with_wall_clock.next(),
+            Some(Err(RMesgError::InternalError(_)))
+        ));
+        assert_eq!(
+            with_wall_clock.next().unwrap().unwrap().1,
+            Some(boot_time + Duration::from_secs(5))
+        );
+        assert!(with_wall_clock.next().is_none());
+        drop(with_wall_clock);
+        assert_eq!(asked, 2);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_stream() {
+        use futures::stream::{self, StreamExt};
+
+        let boot_time = SystemTime::UNIX_EPOCH;
+        let mut asked = 0;
+        let entries = stream::iter(vec![Ok(entry(Some(1))), Ok(entry(None))]);
+
+        let times: Vec<_> = WithWallClock::new(entries, || {
+            asked += 1;
+            Ok(boot_time)
+        })
+        .map(|e| e.unwrap().1)
+        .collect()
+        .await;
+
+        assert_eq!(times, vec![Some(boot_time + Duration::from_secs(1)), None]);
+        assert_eq!(asked, 1);
+    }
+
+    // Reads /proc/uptime
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_with_wall_clock() {
+        let entries = vec![Ok(entry(Some(0)))];
+        let (_, wall_clock_time) = with_wall_clock(entries.into_iter(), ClockSource::Uptime)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!(wall_clock_time.unwrap() <= SystemTime::now());
+    }
+}
@@ -0,0 +1,125 @@
+use std::process::{Command, Stdio};
+
+// Like `rmesg | head -0`: the reader goes away before rmesg is done printing
+fn run_with_closed_stdout(args: &[&str]) -> std::process::Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rmesg"))
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Unable to run rmesg");
+
+    drop(child.stdout.take());
+    child.wait_with_output().expect("Unable to wait for rmesg")
+}
+
+#[test]
+fn test_broken_pipe_exits_cleanly() {
+    for args in [
+        vec![],
+        vec!["-J"],
+        vec!["--logfmt"],
+        vec!["-r"],
+        vec!["--stats"],
+    ]
+    .iter()
+    {
+        let output = run_with_closed_stdout(args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert!(
+            output.status.success(),
+            "rmesg {:?} failed: {}",
+            args,
+            stderr
+        );
+        assert!(
+            !stderr.contains("panicked"),
+            "rmesg {:?} panicked: {}",
+            args,
+            stderr
+        );
+        assert!(
+            !stderr.contains("Broken pipe"),
+            "rmesg {:?}: {}",
+            args,
+            stderr
+        );
+    }
+}
+
+// Following never reaches the end of a FIFO while it's held open for writing, so exiting
+// successfully means rmesg stopped on the broken pipe rather than running out of entries.
+#[cfg(target_os = "linux")]
+#[test]
+fn test_broken_pipe_exits_cleanly_when_following() {
+    use std::ffi::CString;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let fixture: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "kmsg.txt"]
+        .iter()
+        .collect();
+    let records = fs::read(fixture).unwrap();
+
+    let fifo = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join(format!("broken_pipe_kmsg.{}", std::process::id()));
+    let _ = fs::remove_file(&fifo);
+    let fifo_cstr = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo_cstr.as_ptr(), 0o600) }, 0);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rmesg"))
+        .args(["-f", "--kmsg-path"])
+        .arg(&fifo)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Unable to run rmesg");
+    drop(child.stdout.take());
+
+    // Keeps writing records until rmesg exits. rmesg opens the path once to check it can be
+    // read and again to follow it, so writes in between (with no reader) fail and are retried.
+    let done = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let done = done.clone();
+        let fifo = fifo.clone();
+        thread::spawn(move || {
+            let mut fifo = OpenOptions::new().write(true).open(fifo).unwrap();
+            while !done.load(Ordering::Relaxed) {
+                let _ = fifo.write_all(&records);
+                thread::sleep(Duration::from_millis(20));
+            }
+        })
+    };
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break Some(status);
+        }
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    done.store(true, Ordering::Relaxed);
+    writer.join().unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    fs::remove_file(&fifo).unwrap();
+
+    let status = status.expect("rmesg -f kept running after its stdout was closed");
+    assert!(status.success(), "rmesg -f failed: {}", stderr);
+    assert!(
+        !stderr.contains("panicked"),
+        "rmesg -f panicked: {}",
+        stderr
+    );
+}
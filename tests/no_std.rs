@@ -0,0 +1,42 @@
+use std::ffi::OsString;
+use std::fs;
+use std::process::Command;
+
+// Builds src/parse.rs as the only module of a no_std crate, so that anything std (or any
+// other crate) creeping into it fails the build
+#[test]
+fn test_parse_builds_without_std() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("no_std");
+    fs::create_dir_all(&dir).expect("Unable to create the build directory");
+
+    let root = dir.join("lib.rs");
+    fs::write(
+        &root,
+        format!(
+            "#![no_std]\nextern crate alloc;\n#[path = {:?}]\npub mod parse;\n",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/src/parse.rs")
+        ),
+    )
+    .expect("Unable to write the crate root");
+
+    let output = Command::new(std::env::var_os("RUSTC").unwrap_or_else(|| OsString::from("rustc")))
+        .args([
+            "--edition",
+            "2018",
+            "--crate-type",
+            "rlib",
+            "--crate-name",
+            "parse_no_std",
+        ])
+        .args(["--emit", "metadata", "-D", "warnings", "--out-dir"])
+        .arg(&dir)
+        .arg(&root)
+        .output()
+        .expect("Unable to run rustc");
+
+    assert!(
+        output.status.success(),
+        "src/parse.rs doesn't build without std:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}